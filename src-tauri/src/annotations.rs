@@ -0,0 +1,388 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+use crate::history_crypto;
+
+const THICKNESS_RANGE: (f64, f64) = (1.0, 8.0);
+const FONT_SIZE_RANGE: (f64, f64) = (14.0, 48.0);
+
+/// A point in pixels of the image's natural size
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Fields every annotation has, named as the frontend sends them
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AnnotationBase {
+    pub id: String,
+    /// Hex colour such as `#FF0000`
+    pub color: String,
+    /// Stroke width in pixels; redactions send 0
+    pub thickness: f64,
+    #[serde(rename = "createdAt")]
+    pub created_at: f64,
+}
+
+/// One annotation as drawn in the editor. Mirrors `Annotation` in the
+/// frontend's `types/index.ts`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Annotation {
+    Arrow {
+        #[serde(flatten)]
+        base: AnnotationBase,
+        start: Point,
+        end: Point,
+    },
+    Rectangle {
+        #[serde(flatten)]
+        base: AnnotationBase,
+        /// Top-left corner; rectangles drawn up or left have negative extents
+        origin: Point,
+        width: f64,
+        height: f64,
+    },
+    Text {
+        #[serde(flatten)]
+        base: AnnotationBase,
+        position: Point,
+        text: String,
+        #[serde(rename = "fontSize")]
+        font_size: f64,
+    },
+    Freehand {
+        #[serde(flatten)]
+        base: AnnotationBase,
+        points: Vec<Point>,
+    },
+    Redact {
+        #[serde(flatten)]
+        base: AnnotationBase,
+        origin: Point,
+        width: f64,
+        height: f64,
+        style: RedactionStyle,
+        reason: RedactionReason,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactionStyle {
+    Blur,
+    Pixelate,
+    Blackbox,
+}
+
+/// What a redaction hides: a detected kind of personal data, or `manual`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactionReason {
+    Email,
+    Phone,
+    Ip,
+    CreditCard,
+    Manual,
+}
+
+/// A problem with one field of one annotation
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AnnotationError {
+    /// Position in the annotations array; `None` when the payload as a
+    /// whole is malformed
+    pub index: Option<usize>,
+    /// Field name as sent by the frontend, e.g. `fontSize` or `points[3]`
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for AnnotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.index, self.field.is_empty()) {
+            (None, _) => write!(f, "annotations: {}", self.message),
+            (Some(index), true) => write!(f, "annotations[{}]: {}", index, self.message),
+            (Some(index), false) => {
+                write!(f, "annotations[{}].{}: {}", index, self.field, self.message)
+            }
+        }
+    }
+}
+
+impl Annotation {
+    pub fn is_redaction(&self) -> bool {
+        matches!(self, Annotation::Redact { .. })
+    }
+
+    fn base(&self) -> &AnnotationBase {
+        match self {
+            Annotation::Arrow { base, .. }
+            | Annotation::Rectangle { base, .. }
+            | Annotation::Text { base, .. }
+            | Annotation::Freehand { base, .. }
+            | Annotation::Redact { base, .. } => base,
+        }
+    }
+
+    /// Every problem with this annotation on an image of the given size
+    fn problems(&self, width: u32, height: u32) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        let bounds = (width as f64, height as f64);
+
+        let base = self.base();
+        if !is_hex_color(&base.color) {
+            problems.push((
+                "color".to_string(),
+                "must be a hex colour like #FF0000".to_string(),
+            ));
+        }
+        // Redactions are filled, not stroked
+        if !self.is_redaction() {
+            check_range(&mut problems, "thickness", base.thickness, THICKNESS_RANGE);
+        }
+
+        match self {
+            Annotation::Arrow { start, end, .. } => {
+                check_point(&mut problems, "start", *start, bounds);
+                check_point(&mut problems, "end", *end, bounds);
+            }
+            Annotation::Rectangle {
+                origin,
+                width,
+                height,
+                ..
+            }
+            | Annotation::Redact {
+                origin,
+                width,
+                height,
+                ..
+            } => {
+                check_point(&mut problems, "origin", *origin, bounds);
+                let corner = Point {
+                    x: origin.x + width,
+                    y: origin.y + height,
+                };
+                if *width == 0.0 || !width.is_finite() {
+                    problems.push(("width".to_string(), "must be a non-zero number".to_string()));
+                }
+                if *height == 0.0 || !height.is_finite() {
+                    problems.push((
+                        "height".to_string(),
+                        "must be a non-zero number".to_string(),
+                    ));
+                }
+                if !in_bounds(corner, bounds) {
+                    problems.push((
+                        "width".to_string(),
+                        format!("extends outside the {}x{} image", bounds.0, bounds.1),
+                    ));
+                }
+            }
+            Annotation::Text {
+                position,
+                text,
+                font_size,
+                ..
+            } => {
+                check_point(&mut problems, "position", *position, bounds);
+                if text.trim().is_empty() {
+                    problems.push(("text".to_string(), "must not be empty".to_string()));
+                }
+                check_range(&mut problems, "fontSize", *font_size, FONT_SIZE_RANGE);
+            }
+            Annotation::Freehand { points, .. } => {
+                if points.is_empty() {
+                    problems.push(("points".to_string(), "must not be empty".to_string()));
+                }
+                for (i, point) in points.iter().enumerate() {
+                    check_point(&mut problems, &format!("points[{}]", i), *point, bounds);
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+/// Parse and validate an annotations JSON array for an image of the given
+/// size, collecting every problem rather than stopping at the first
+pub(crate) fn parse_annotations(
+    annotations_json: &str,
+    width: u32,
+    height: u32,
+) -> Result<Vec<Annotation>, Vec<AnnotationError>> {
+    let values: Vec<serde_json::Value> = serde_json::from_str(annotations_json).map_err(|e| {
+        vec![AnnotationError {
+            index: None,
+            field: String::new(),
+            message: format!("must be a JSON array: {}", e),
+        }]
+    })?;
+
+    let mut annotations = Vec::with_capacity(values.len());
+    let mut errors = Vec::new();
+    for (index, value) in values.into_iter().enumerate() {
+        let annotation: Annotation = match serde_json::from_value(value) {
+            Ok(annotation) => annotation,
+            Err(e) => {
+                let message = e.to_string();
+                errors.push(AnnotationError {
+                    index: Some(index),
+                    field: error_field(&message),
+                    message,
+                });
+                continue;
+            }
+        };
+        for (field, message) in annotation.problems(width, height) {
+            errors.push(AnnotationError {
+                index: Some(index),
+                field,
+                message,
+            });
+        }
+        annotations.push(annotation);
+    }
+
+    if errors.is_empty() {
+        Ok(annotations)
+    } else {
+        Err(errors)
+    }
+}
+
+/// `parse_annotations` against the image at `image_path`, with the errors
+/// joined into one message for commands
+pub(crate) fn validate_for_image(
+    annotations_json: &str,
+    image_path: &Path,
+) -> Result<Vec<Annotation>, String> {
+    let (width, height) = history_crypto::image_dimensions(image_path)
+        .map_err(|e| format!("Failed to read image size: {}", e))?;
+
+    parse_annotations(annotations_json, width, height).map_err(|errors| {
+        let details: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        format!("Invalid annotations: {}", details.join("; "))
+    })
+}
+
+/// The field a deserialization error is about, where serde names one
+fn error_field(message: &str) -> String {
+    if let Some(rest) = message.strip_prefix("missing field `") {
+        return rest.split('`').next().unwrap_or_default().to_string();
+    }
+    if message.starts_with("unknown variant") || message.starts_with("missing field `type`") {
+        return "type".to_string();
+    }
+    String::new()
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
+fn in_bounds(point: Point, (width, height): (f64, f64)) -> bool {
+    point.x.is_finite()
+        && point.y.is_finite()
+        && (0.0..=width).contains(&point.x)
+        && (0.0..=height).contains(&point.y)
+}
+
+fn check_point(
+    problems: &mut Vec<(String, String)>,
+    field: &str,
+    point: Point,
+    bounds: (f64, f64),
+) {
+    if !in_bounds(point, bounds) {
+        problems.push((
+            field.to_string(),
+            format!(
+                "({}, {}) is outside the {}x{} image",
+                point.x, point.y, bounds.0, bounds.1
+            ),
+        ));
+    }
+}
+
+fn check_range(
+    problems: &mut Vec<(String, String)>,
+    field: &str,
+    value: f64,
+    (min, max): (f64, f64),
+) {
+    if !(min..=max).contains(&value) {
+        problems.push((
+            field.to_string(),
+            format!("must be between {} and {}, got {}", min, max, value),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_annotations, Annotation, RedactionReason, RedactionStyle};
+
+    #[test]
+    fn parses_every_annotation_kind_from_frontend_json() {
+        let json = r##"[
+            {"id": "a", "type": "arrow", "color": "#FF0000", "thickness": 3, "createdAt": 1,
+             "start": {"x": 10, "y": 10}, "end": {"x": 90, "y": 40}},
+            {"id": "b", "type": "rectangle", "color": "#00f", "thickness": 2, "createdAt": 2,
+             "origin": {"x": 50, "y": 50}, "width": -20, "height": 30},
+            {"id": "c", "type": "text", "color": "#000000", "thickness": 2, "createdAt": 3,
+             "position": {"x": 5, "y": 5}, "text": "Click here", "fontSize": 24},
+            {"id": "d", "type": "freehand", "color": "#00FF00", "thickness": 8, "createdAt": 4,
+             "points": [{"x": 0, "y": 0}, {"x": 100, "y": 80}]},
+            {"id": "e", "type": "redact", "color": "#FF0000", "thickness": 0, "createdAt": 5,
+             "origin": {"x": 0, "y": 60}, "width": 40, "height": 10,
+             "style": "pixelate", "reason": "credit_card"}
+        ]"##;
+
+        let annotations = parse_annotations(json, 100, 80).unwrap();
+        assert_eq!(annotations.len(), 5);
+        assert!(matches!(
+            &annotations[4],
+            Annotation::Redact {
+                style: RedactionStyle::Pixelate,
+                reason: RedactionReason::CreditCard,
+                ..
+            }
+        ));
+        assert_eq!(annotations.iter().filter(|a| a.is_redaction()).count(), 1);
+    }
+
+    #[test]
+    fn reports_each_invalid_field() {
+        let json = r##"[
+            {"id": "a", "type": "text", "color": "red", "thickness": 12, "createdAt": 1,
+             "position": {"x": 5, "y": 500}, "text": "hi", "fontSize": 60},
+            {"id": "b", "type": "arrow", "color": "#FF0000", "thickness": 3, "createdAt": 2},
+            {"id": "c", "type": "circle", "color": "#FF0000", "thickness": 3, "createdAt": 3}
+        ]"##;
+
+        let errors = parse_annotations(json, 100, 80).unwrap_err();
+        let fields: Vec<(Option<usize>, &str)> =
+            errors.iter().map(|e| (e.index, e.field.as_str())).collect();
+        assert_eq!(
+            fields,
+            vec![
+                (Some(0), "color"),
+                (Some(0), "thickness"),
+                (Some(0), "position"),
+                (Some(0), "fontSize"),
+                (Some(1), "start"),
+                (Some(2), "type"),
+            ]
+        );
+        assert!(errors[1]
+            .to_string()
+            .starts_with("annotations[0].thickness:"));
+        assert!(parse_annotations("{}", 100, 80).is_err());
+    }
+}
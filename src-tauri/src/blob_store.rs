@@ -0,0 +1,313 @@
+use rusqlite::{params, Connection};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::fs_util::sync_dir;
+use crate::history::ScreenshotMeta;
+use crate::history_crypto;
+
+/// Images stored once per distinct content, shared between entries
+pub(crate) const BLOBS_DIR_NAME: &str = ".blobs";
+
+/// SHA-256 of a file's decrypted contents, as lowercase hex, so the same
+/// image dedupes whether or not history is encrypted
+pub(crate) fn hash_file(path: &Path) -> Result<String, String> {
+    let contents = history_crypto::read(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    Ok(hash_bytes(&contents))
+}
+
+/// `hash_file` for contents already in memory
+pub(crate) fn hash_bytes(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+/// Blobs are fanned out by the first two hex digits to keep directories small
+pub(crate) fn blob_path(history_dir: &Path, hash: &str) -> PathBuf {
+    history_dir
+        .join(BLOBS_DIR_NAME)
+        .join(&hash[..2])
+        .join(format!("{}.png", hash))
+}
+
+/// The hash of a path inside the blob store, or `None` for any other path
+pub(crate) fn hash_of_path(path: &Path) -> Option<String> {
+    let in_store = path
+        .parent()
+        .and_then(Path::parent)
+        .and_then(Path::file_name)
+        .is_some_and(|name| name == BLOBS_DIR_NAME);
+    if !in_store {
+        return None;
+    }
+
+    let stem = path.file_stem()?.to_string_lossy();
+    (stem.len() == 64 && stem.chars().all(|c| c.is_ascii_hexdigit())).then(|| stem.to_string())
+}
+
+/// Copy `source` into the blob store unless identical content is already
+/// there, returning the stored path. Must be called while holding the
+/// history lock.
+pub(crate) fn store(history_dir: &Path, source: &Path) -> Result<PathBuf, String> {
+    let hash = hash_file(source)?;
+    let dest = blob_path(history_dir, &hash);
+    if dest.is_file() {
+        return Ok(dest);
+    }
+
+    let fan_dir = dest.parent().unwrap_or(history_dir);
+    fs::create_dir_all(fan_dir).map_err(|e| format!("Failed to create blob directory: {}", e))?;
+
+    let tmp = fan_dir.join(format!(".{}.tmp", hash));
+    history_crypto::copy_synced(history_dir, source, &tmp)
+        .map_err(|e| format!("Failed to store image: {}", e))?;
+    fs::rename(&tmp, &dest).map_err(|e| format!("Failed to store image: {}", e))?;
+    sync_dir(fan_dir).map_err(|e| format!("Failed to sync blob directory: {}", e))?;
+
+    Ok(dest)
+}
+
+/// `store` for contents already in memory, such as images read from a
+/// backup archive. Must be called while holding the history lock.
+pub(crate) fn store_bytes(history_dir: &Path, contents: &[u8]) -> Result<PathBuf, String> {
+    let hash = hash_bytes(contents);
+    let dest = blob_path(history_dir, &hash);
+    if dest.is_file() {
+        return Ok(dest);
+    }
+
+    let fan_dir = dest.parent().unwrap_or(history_dir);
+    fs::create_dir_all(fan_dir).map_err(|e| format!("Failed to create blob directory: {}", e))?;
+    history_crypto::write_atomic(history_dir, &dest, contents)
+        .map_err(|e| format!("Failed to store image: {}", e))?;
+
+    Ok(dest)
+}
+
+/// Blob paths referenced by an entry, keyed by role
+fn entry_blobs(meta: &ScreenshotMeta) -> Vec<(&'static str, String, PathBuf)> {
+    [
+        ("original", Some(&meta.original_path)),
+        ("annotated", meta.annotated_path.as_ref()),
+        ("thumbnail", Some(&meta.thumbnail_path)),
+    ]
+    .into_iter()
+    .filter_map(|(role, path)| {
+        let path = PathBuf::from(path?);
+        hash_of_path(&path).map(|hash| (role, hash, path))
+    })
+    .collect()
+}
+
+/// Make the entry's blob references match the paths in `meta`. References
+/// are derived from paths so that an index rebuilt from `meta.json` files
+/// gets them back.
+pub(crate) fn sync_refs(conn: &Connection, meta: &ScreenshotMeta) -> Result<(), String> {
+    write_refs(conn, "blob_refs", meta)
+}
+
+/// References held by a trashed entry, which keep its images alive until
+/// it is purged
+pub(crate) fn sync_trash_refs(conn: &Connection, meta: &ScreenshotMeta) -> Result<(), String> {
+    write_refs(conn, "trash_blob_refs", meta)
+}
+
+fn write_refs(conn: &Connection, table: &str, meta: &ScreenshotMeta) -> Result<(), String> {
+    let map_err = |e: rusqlite::Error| format!("Failed to write blob references: {}", e);
+
+    conn.execute(
+        &format!("DELETE FROM {} WHERE screenshot_id = ?1", table),
+        params![meta.id],
+    )
+    .map_err(map_err)?;
+
+    for (role, hash, path) in entry_blobs(meta) {
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        conn.execute(
+            "INSERT OR IGNORE INTO blobs (hash, size_bytes) VALUES (?1, ?2)",
+            params![hash, size as i64],
+        )
+        .map_err(map_err)?;
+        conn.execute(
+            &format!(
+                "INSERT INTO {} (screenshot_id, role, hash) VALUES (?1, ?2, ?3)",
+                table
+            ),
+            params![meta.id, role, hash],
+        )
+        .map_err(map_err)?;
+    }
+
+    Ok(())
+}
+
+/// Whether a live entry already has the image with this hash as its
+/// original. Trashed entries do not count, since purging the trash would
+/// lose the image.
+pub(crate) fn has_original(conn: &Connection, hash: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM blob_refs WHERE role = 'original' AND hash = ?1)",
+        params![hash],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to read blob references: {}", e))
+}
+
+/// Every (entry id, blob hash, blob size) reference, trashed entries
+/// included
+pub(crate) fn list_refs(conn: &Connection) -> Result<Vec<(String, String, u64)>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT r.screenshot_id, r.hash, b.size_bytes FROM (
+                 SELECT screenshot_id, hash FROM blob_refs
+                 UNION ALL SELECT screenshot_id, hash FROM trash_blob_refs
+             ) r
+             JOIN blobs b ON b.hash = r.hash",
+        )
+        .map_err(|e| format!("Failed to read blob references: {}", e))?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get::<_, i64>(2)? as u64))
+        })
+        .map_err(|e| format!("Failed to read blob references: {}", e))?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read blob references: {}", e))
+}
+
+/// Correct recorded blob sizes from the files in the store, such as after
+/// encryption rewrote them
+pub(crate) fn rescan_sizes(conn: &Connection, history_dir: &Path) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare("SELECT hash FROM blobs")
+        .map_err(|e| format!("Failed to read blobs: {}", e))?;
+    let hashes: Vec<String> = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(|e| format!("Failed to read blobs: {}", e))?
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to read blobs: {}", e))?;
+
+    for hash in hashes {
+        let size = fs::metadata(blob_path(history_dir, &hash))
+            .map(|m| m.len())
+            .unwrap_or(0);
+        conn.execute(
+            "UPDATE blobs SET size_bytes = ?1 WHERE hash = ?2 AND size_bytes != ?1",
+            params![size as i64, hash],
+        )
+        .map_err(|e| format!("Failed to update blob size: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Delete blobs no entry references any more, including files left in the
+/// store by saves that failed before reaching the index. Returns the bytes
+/// freed. Must be called while holding the history lock.
+pub(crate) fn collect_garbage(conn: &Connection, history_dir: &Path) -> Result<u64, String> {
+    conn.execute(
+        "DELETE FROM blobs WHERE hash NOT IN (SELECT hash FROM blob_refs)
+         AND hash NOT IN (SELECT hash FROM trash_blob_refs)",
+        [],
+    )
+    .map_err(|e| format!("Failed to clean up blobs: {}", e))?;
+
+    let mut stmt = conn
+        .prepare("SELECT hash FROM blobs")
+        .map_err(|e| format!("Failed to read blobs: {}", e))?;
+    let live: HashSet<String> = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(|e| format!("Failed to read blobs: {}", e))?
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to read blobs: {}", e))?;
+
+    let root = history_dir.join(BLOBS_DIR_NAME);
+    if !root.is_dir() {
+        return Ok(0);
+    }
+
+    let mut freed = 0u64;
+    for fan_dir in fs::read_dir(&root).map_err(|e| e.to_string())?.flatten() {
+        for file in fs::read_dir(fan_dir.path())
+            .map_err(|e| e.to_string())?
+            .flatten()
+        {
+            let path = file.path();
+            let keep = hash_of_path(&path).is_some_and(|hash| live.contains(&hash));
+            if !keep {
+                freed += file.metadata().map(|m| m.len()).unwrap_or(0);
+                fs::remove_file(&path)
+                    .map_err(|e| format!("Failed to delete blob {}: {}", path.display(), e))?;
+            }
+        }
+    }
+
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect_garbage, hash_of_path, list_refs, store, BLOBS_DIR_NAME};
+    use crate::history::ScreenshotMeta;
+    use crate::history_db;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn identical_images_share_a_blob_until_the_last_reference_goes() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let capture = dir.join("capture.png");
+        let thumb_a = dir.join("thumb_a.png");
+        let thumb_b = dir.join("thumb_b.png");
+        fs::write(&capture, b"same pixels").unwrap();
+        fs::write(&thumb_a, b"thumb a").unwrap();
+        fs::write(&thumb_b, b"thumb b").unwrap();
+
+        let original_a = store(&dir, &capture).unwrap();
+        let original_b = store(&dir, &capture).unwrap();
+        assert_eq!(original_a, original_b);
+        assert!(hash_of_path(&original_a).is_some());
+        assert!(hash_of_path(&capture).is_none());
+
+        let conn = history_db::open_in_memory().unwrap();
+        let a = ScreenshotMeta {
+            original_path: original_a.to_string_lossy().to_string(),
+            thumbnail_path: store(&dir, &thumb_a).unwrap().to_string_lossy().to_string(),
+            ..ScreenshotMeta::test_entry("a")
+        };
+        let b = ScreenshotMeta {
+            original_path: original_b.to_string_lossy().to_string(),
+            thumbnail_path: store(&dir, &thumb_b).unwrap().to_string_lossy().to_string(),
+            ..ScreenshotMeta::test_entry("b")
+        };
+        history_db::upsert(&conn, &a).unwrap();
+        history_db::upsert(&conn, &b).unwrap();
+        assert_eq!(list_refs(&conn).unwrap().len(), 4);
+
+        // A stray file from an interrupted save is swept as well
+        let stray = dir
+            .join(BLOBS_DIR_NAME)
+            .join("00")
+            .join(format!("{}.png", "0".repeat(64)));
+        fs::create_dir_all(stray.parent().unwrap()).unwrap();
+        fs::write(&stray, b"stray").unwrap();
+
+        history_db::delete(&conn, "a").unwrap();
+        collect_garbage(&conn, &dir).unwrap();
+        assert!(original_a.exists());
+        assert!(!Path::new(&a.thumbnail_path).exists());
+        assert!(!stray.exists());
+
+        history_db::delete(&conn, "b").unwrap();
+        collect_garbage(&conn, &dir).unwrap();
+        assert!(!original_a.exists());
+        assert!(list_refs(&conn).unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
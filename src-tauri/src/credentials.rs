@@ -1,24 +1,58 @@
 use keyring::Entry;
 
+use crate::history_crypto;
+
 const SERVICE_NAME: &str = "com.screenshot-annotate";
+/// Entries the app keeps for itself, which the commands below must not
+/// read, overwrite or delete
+const RESERVED_NAMES: &[&str] = &[history_crypto::KEY_CREDENTIAL];
 
 /// Store a credential in the macOS Keychain
 #[tauri::command]
 pub async fn store_credential(service: String, token: String) -> Result<(), String> {
-    let entry = Entry::new(SERVICE_NAME, &service)
+    check_name(&service)?;
+    write_credential(&service, &token)
+}
+
+/// Retrieve a credential from the macOS Keychain
+#[tauri::command]
+pub async fn get_credential(service: String) -> Result<Option<String>, String> {
+    check_name(&service)?;
+    read_credential(&service)
+}
+
+/// Delete a credential from the macOS Keychain
+#[tauri::command]
+pub async fn delete_credential(service: String) -> Result<(), String> {
+    check_name(&service)?;
+    remove_credential(&service)
+}
+
+fn check_name(service: &str) -> Result<(), String> {
+    let name = service.trim();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(name))
+    {
+        return Err(format!("Credential name is reserved: {}", name));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn write_credential(service: &str, token: &str) -> Result<(), String> {
+    let entry = Entry::new(SERVICE_NAME, service)
         .map_err(|e| format!("Failed to create keychain entry: {}", e))?;
 
     entry
-        .set_password(&token)
+        .set_password(token)
         .map_err(|e| format!("Failed to store credential: {}", e))?;
 
     Ok(())
 }
 
-/// Retrieve a credential from the macOS Keychain
-#[tauri::command]
-pub async fn get_credential(service: String) -> Result<Option<String>, String> {
-    let entry = Entry::new(SERVICE_NAME, &service)
+pub(crate) fn read_credential(service: &str) -> Result<Option<String>, String> {
+    let entry = Entry::new(SERVICE_NAME, service)
         .map_err(|e| format!("Failed to create keychain entry: {}", e))?;
 
     match entry.get_password() {
@@ -28,10 +62,8 @@ pub async fn get_credential(service: String) -> Result<Option<String>, String> {
     }
 }
 
-/// Delete a credential from the macOS Keychain
-#[tauri::command]
-pub async fn delete_credential(service: String) -> Result<(), String> {
-    let entry = Entry::new(SERVICE_NAME, &service)
+pub(crate) fn remove_credential(service: &str) -> Result<(), String> {
+    let entry = Entry::new(SERVICE_NAME, service)
         .map_err(|e| format!("Failed to create keychain entry: {}", e))?;
 
     match entry.delete_credential() {
@@ -40,3 +72,24 @@ pub async fn delete_credential(service: String) -> Result<(), String> {
         Err(e) => Err(format!("Failed to delete credential: {}", e)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{delete_credential, get_credential, store_credential};
+    use crate::history_crypto::KEY_CREDENTIAL;
+    use tauri::async_runtime::block_on;
+
+    #[test]
+    fn commands_refuse_the_history_key() {
+        for name in [KEY_CREDENTIAL.to_string(), KEY_CREDENTIAL.to_uppercase()] {
+            let error = block_on(get_credential(name.clone())).unwrap_err();
+            assert!(error.contains("reserved"));
+            assert!(block_on(store_credential(name.clone(), "x".to_string()))
+                .unwrap_err()
+                .contains("reserved"));
+            assert!(block_on(delete_credential(name))
+                .unwrap_err()
+                .contains("reserved"));
+        }
+    }
+}
@@ -1,5 +1,8 @@
-use image::ImageFormat;
+use image::{ImageFormat, RgbaImage};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::history_crypto;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExportResult {
@@ -13,8 +16,8 @@ pub async fn export_annotated(
     annotation_png_base64: String,
 ) -> Result<ExportResult, String> {
     // Load original screenshot
-    let original_img =
-        image::open(&original_path).map_err(|e| format!("Failed to load original image: {}", e))?;
+    let original_img = history_crypto::open_image(Path::new(&original_path))
+        .map_err(|e| format!("Failed to load original image: {}", e))?;
 
     // Decode annotation layer from base64
     let annotation_data = base64_decode(&annotation_png_base64)
@@ -42,32 +45,35 @@ pub async fn export_annotated(
         .save_with_format(&annotated_path, ImageFormat::Png)
         .map_err(|e| format!("Failed to save annotated image: {}", e))?;
 
-    // Generate thumbnail (200px width, maintaining aspect ratio)
-    let (width, height) = result_img.dimensions();
+    let thumbnail_filename = format!("thumbnail_{}.png", timestamp);
+    let thumbnail_path = temp_dir.join(&thumbnail_filename);
+    save_thumbnail(&result_img, &thumbnail_path)?;
+
+    Ok(ExportResult {
+        annotated_path: annotated_path.to_string_lossy().to_string(),
+        thumbnail_path: thumbnail_path.to_string_lossy().to_string(),
+    })
+}
+
+/// Save a 200px-wide thumbnail of `image`, keeping its aspect ratio
+pub(crate) fn save_thumbnail(image: &RgbaImage, path: &Path) -> Result<(), String> {
+    let (width, height) = image.dimensions();
     let thumbnail_width = 200u32;
     let thumbnail_height = (height as f32 * (thumbnail_width as f32 / width as f32)) as u32;
 
     let thumbnail_img = image::imageops::resize(
-        &result_img,
+        image,
         thumbnail_width,
-        thumbnail_height,
+        thumbnail_height.max(1),
         image::imageops::FilterType::Lanczos3,
     );
 
-    let thumbnail_filename = format!("thumbnail_{}.png", timestamp);
-    let thumbnail_path = temp_dir.join(&thumbnail_filename);
-
     thumbnail_img
-        .save_with_format(&thumbnail_path, ImageFormat::Png)
-        .map_err(|e| format!("Failed to save thumbnail: {}", e))?;
-
-    Ok(ExportResult {
-        annotated_path: annotated_path.to_string_lossy().to_string(),
-        thumbnail_path: thumbnail_path.to_string_lossy().to_string(),
-    })
+        .save_with_format(path, ImageFormat::Png)
+        .map_err(|e| format!("Failed to save thumbnail: {}", e))
 }
 
-fn base64_decode(input: &str) -> Result<Vec<u8>, String> {
+pub(crate) fn base64_decode(input: &str) -> Result<Vec<u8>, String> {
     // Remove data URL prefix if present (e.g., "data:image/png;base64,")
     let base64_data = if input.contains("base64,") {
         input.split("base64,").nth(1).unwrap_or(input)
@@ -0,0 +1,318 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::fs_util::write_atomic;
+use crate::history::{self, ScreenshotMeta};
+use crate::history_crypto;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GuideRequest {
+    pub title: String,
+    pub steps: Vec<GuideStep>,
+    pub output_dir: Option<String>,
+    pub inline_images: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GuideStep {
+    pub id: String,
+    pub caption: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GuideResult {
+    pub folder_path: String,
+    pub markdown_path: String,
+    pub html_path: String,
+}
+
+/// A step ready for rendering, with its image already copied into the guide folder
+struct RenderedStep {
+    caption: String,
+    image_file: String,
+    image_data_uri: Option<String>,
+}
+
+/// Export a sequence of history items as a numbered step-by-step guide.
+///
+/// Writes a folder with `guide.md`, `guide.html` and an `images/` directory.
+/// With `inline_images` the HTML embeds every image as a data URI so it can
+/// be pasted into a wiki on its own.
+#[tauri::command]
+pub async fn export_guide(request: GuideRequest) -> Result<GuideResult, String> {
+    if request.steps.is_empty() {
+        return Err("Guide must contain at least one step".to_string());
+    }
+
+    let entries = request
+        .steps
+        .iter()
+        .map(|step| history::find_history_entry(&step.id))
+        .collect::<Result<Vec<ScreenshotMeta>, String>>()?;
+
+    let parent = match &request.output_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::temp_dir(),
+    };
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let folder = create_guide_folder(&parent, timestamp)
+        .map_err(|e| format!("Failed to create guide directory: {}", e))?;
+
+    write_guide(&folder, &request, &entries)
+}
+
+/// Write the guide's images and pages into the freshly created `folder`,
+/// removing it again on failure since a guide missing some of its steps is
+/// worse than none
+fn write_guide(
+    folder: &Path,
+    request: &GuideRequest,
+    entries: &[ScreenshotMeta],
+) -> Result<GuideResult, String> {
+    let result = write_guide_files(folder, request, entries);
+    if result.is_err() {
+        let _ = fs::remove_dir_all(folder);
+    }
+    result
+}
+
+fn write_guide_files(
+    folder: &Path,
+    request: &GuideRequest,
+    entries: &[ScreenshotMeta],
+) -> Result<GuideResult, String> {
+    fs::create_dir(folder.join("images"))
+        .map_err(|e| format!("Failed to create guide directory: {}", e))?;
+
+    let mut rendered = Vec::with_capacity(entries.len());
+    for (i, (step, meta)) in request.steps.iter().zip(entries.iter()).enumerate() {
+        let source = meta.annotated_path.as_ref().unwrap_or(&meta.original_path);
+        let image_file = format!("images/step-{:02}.png", i + 1);
+
+        // Decrypted on the way out, so the guide is readable anywhere
+        let bytes = history_crypto::read(Path::new(source))
+            .map_err(|e| format!("Failed to read image for step {}: {}", i + 1, e))?;
+        write_atomic(&folder.join(&image_file), &bytes)
+            .map_err(|e| format!("Failed to copy image for step {}: {}", i + 1, e))?;
+
+        let image_data_uri = if request.inline_images {
+            Some(format!("data:image/png;base64,{}", BASE64.encode(bytes)))
+        } else {
+            None
+        };
+
+        rendered.push(RenderedStep {
+            caption: step.caption.trim().to_string(),
+            image_file,
+            image_data_uri,
+        });
+    }
+
+    let markdown_path = folder.join("guide.md");
+    write_atomic(
+        &markdown_path,
+        render_markdown(&request.title, &rendered).as_bytes(),
+    )
+    .map_err(|e| format!("Failed to write Markdown guide: {}", e))?;
+
+    let html_path = folder.join("guide.html");
+    write_atomic(
+        &html_path,
+        render_html(&request.title, &rendered).as_bytes(),
+    )
+    .map_err(|e| format!("Failed to write HTML guide: {}", e))?;
+
+    Ok(GuideResult {
+        folder_path: folder.to_string_lossy().to_string(),
+        markdown_path: markdown_path.to_string_lossy().to_string(),
+        html_path: html_path.to_string_lossy().to_string(),
+    })
+}
+
+/// A new `guide_{timestamp}` folder in `parent`. Exports made in the same
+/// second get `_2`, `_3` and so on rather than writing into each other.
+fn create_guide_folder(parent: &Path, timestamp: u64) -> io::Result<PathBuf> {
+    fs::create_dir_all(parent)?;
+
+    let mut attempt = 1;
+    loop {
+        let name = match attempt {
+            1 => format!("guide_{}", timestamp),
+            n => format!("guide_{}_{}", timestamp, n),
+        };
+        let folder = parent.join(name);
+        match fs::create_dir(&folder) {
+            Ok(()) => return Ok(folder),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn render_markdown(title: &str, steps: &[RenderedStep]) -> String {
+    let mut md = format!("# {}\n\n", escape_markdown(title.trim()));
+
+    for (i, step) in steps.iter().enumerate() {
+        md.push_str(&format!("## Step {}\n\n", i + 1));
+        if !step.caption.is_empty() {
+            md.push_str(&escape_markdown(&step.caption));
+            md.push_str("\n\n");
+        }
+        md.push_str(&format!("![Step {}]({})\n\n", i + 1, step.image_file));
+    }
+
+    md
+}
+
+fn render_html(title: &str, steps: &[RenderedStep]) -> String {
+    let title = escape_html(title.trim());
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\nbody {{ font-family: -apple-system, Helvetica, Arial, sans-serif; max-width: 960px; margin: 2em auto; padding: 0 1em; }}\n\
+         ol.steps img {{ max-width: 100%; border: 1px solid #ddd; margin: 0.5em 0 1.5em; }}\n</style>\n\
+         </head>\n<body>\n<h1>{}</h1>\n<ol class=\"steps\">\n",
+        title, title
+    );
+
+    for (i, step) in steps.iter().enumerate() {
+        let src = step
+            .image_data_uri
+            .as_deref()
+            .unwrap_or(step.image_file.as_str());
+
+        html.push_str("<li>\n");
+        if !step.caption.is_empty() {
+            html.push_str(&format!("<p>{}</p>\n", escape_html(&step.caption)));
+        }
+        html.push_str(&format!(
+            "<img src=\"{}\" alt=\"Step {}\">\n</li>\n",
+            escape_html(src),
+            i + 1
+        ));
+    }
+
+    html.push_str("</ol>\n</body>\n</html>\n");
+    html
+}
+
+/// Backslash-escape Markdown syntax so titles and captions read as typed,
+/// e.g. `<Settings>` or `*.log` are not taken as HTML or emphasis
+fn escape_markdown(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\`*_{}[]()<>#+-.!|~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        create_guide_folder, render_html, render_markdown, write_guide, GuideRequest, GuideStep,
+        RenderedStep,
+    };
+    use crate::history::ScreenshotMeta;
+    use std::fs;
+
+    fn steps(inline: bool) -> Vec<RenderedStep> {
+        vec![
+            RenderedStep {
+                caption: "Open <Settings>".to_string(),
+                image_file: "images/step-01.png".to_string(),
+                image_data_uri: inline.then(|| "data:image/png;base64,AAAA".to_string()),
+            },
+            RenderedStep {
+                caption: String::new(),
+                image_file: "images/step-02.png".to_string(),
+                image_data_uri: inline.then(|| "data:image/png;base64,BBBB".to_string()),
+            },
+        ]
+    }
+
+    #[test]
+    fn markdown_numbers_steps_and_links_images() {
+        let md = render_markdown("Reset MFA #2", &steps(false));
+        assert!(md.starts_with("# Reset MFA \\#2\n"));
+        assert!(md.contains("## Step 1\n\nOpen \\<Settings\\>\n\n![Step 1](images/step-01.png)"));
+        assert!(md.contains("## Step 2\n\n![Step 2](images/step-02.png)"));
+    }
+
+    #[test]
+    fn html_escapes_captions_and_uses_relative_paths() {
+        let html = render_html("Reset & verify", &steps(false));
+        assert!(html.contains("<h1>Reset &amp; verify</h1>"));
+        assert!(html.contains("<p>Open &lt;Settings&gt;</p>"));
+        assert!(html.contains("src=\"images/step-02.png\""));
+    }
+
+    #[test]
+    fn html_inlines_data_uris_when_requested() {
+        let html = render_html("Guide", &steps(true));
+        assert!(html.contains("src=\"data:image/png;base64,AAAA\""));
+        assert!(!html.contains("images/step-01.png"));
+    }
+
+    #[test]
+    fn exports_in_the_same_second_get_their_own_folders() {
+        let parent = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+
+        let first = create_guide_folder(&parent, 1_700_000_000).unwrap();
+        let second = create_guide_folder(&parent, 1_700_000_000).unwrap();
+        assert_eq!(first.file_name().unwrap(), "guide_1700000000");
+        assert_eq!(second.file_name().unwrap(), "guide_1700000000_2");
+
+        fs::remove_dir_all(&parent).unwrap();
+    }
+
+    #[test]
+    fn failed_export_leaves_no_folder_behind() {
+        let parent = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let folder = create_guide_folder(&parent, 1_700_000_000).unwrap();
+        fs::write(parent.join("step.png"), b"pixels").unwrap();
+
+        let request = GuideRequest {
+            title: "Reset MFA".to_string(),
+            steps: ["first", "second"]
+                .iter()
+                .map(|caption| GuideStep {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    caption: caption.to_string(),
+                })
+                .collect(),
+            output_dir: None,
+            inline_images: false,
+        };
+        let entries: Vec<ScreenshotMeta> = [parent.join("step.png"), parent.join("missing.png")]
+            .iter()
+            .zip(&request.steps)
+            .map(|(path, step)| ScreenshotMeta {
+                original_path: path.to_string_lossy().to_string(),
+                ..ScreenshotMeta::test_entry(&step.id)
+            })
+            .collect();
+
+        let error = write_guide(&folder, &request, &entries).unwrap_err();
+        assert!(error.contains("step 2"));
+        assert!(!folder.exists());
+
+        fs::remove_dir_all(&parent).unwrap();
+    }
+}
@@ -1,10 +1,28 @@
+use fs4::fs_std::FileExt;
+use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
-const STORAGE_BUDGET_MB: u64 = 500;
-const STORAGE_BUDGET_BYTES: u64 = STORAGE_BUDGET_MB * 1024 * 1024;
+use crate::annotations;
+use crate::blob_store::{self, BLOBS_DIR_NAME};
+use crate::fs_util::sync_dir;
+use crate::history_crypto::{self, write_atomic};
+use crate::history_db;
+use crate::history_fields;
+use crate::history_location;
+use crate::history_schema;
+use crate::history_search::{self, HasFilter, SortKey, OCR_TEXT_FILE_NAME};
+use crate::history_similar;
+use crate::history_trash;
+use crate::retention;
+
+/// Entries are assembled here and renamed into place once complete
+pub(crate) const STAGING_DIR_NAME: &str = ".staging";
+const LOCK_FILE_NAME: &str = ".history.lock";
+pub(crate) const META_FILE_NAME: &str = "meta.json";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ScreenshotMeta {
@@ -17,6 +35,102 @@ pub struct ScreenshotMeta {
     pub uploaded_url: Option<String>,
     pub size_bytes: u64,
     pub annotation_count: usize,
+    #[serde(default)]
+    pub redaction_count: usize,
+    /// Pinned entries are never evicted
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub collections: Vec<String>,
+    /// Every upload attempt, oldest first
+    #[serde(default)]
+    pub uploads: Vec<UploadRecord>,
+    /// Free-form notes, searchable
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Values of the configured custom fields, by field key
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+}
+
+#[cfg(test)]
+impl ScreenshotMeta {
+    /// An entry with placeholder paths under `/history/{id}` and no labels,
+    /// for tests to adjust with struct update syntax
+    pub(crate) fn test_entry(id: &str) -> Self {
+        ScreenshotMeta {
+            id: id.to_string(),
+            original_path: format!("/history/{}/original.png", id),
+            annotated_path: None,
+            thumbnail_path: format!("/history/{}/thumbnail.png", id),
+            created_at: "2026-09-01T10:00:00+00:00".to_string(),
+            ticket_id: None,
+            uploaded_url: None,
+            size_bytes: 10,
+            annotation_count: 0,
+            redaction_count: 0,
+            pinned: false,
+            tags: Vec::new(),
+            collections: Vec::new(),
+            uploads: Vec::new(),
+            notes: None,
+            fields: BTreeMap::new(),
+        }
+    }
+}
+
+/// One attempt to attach an entry to a ticket
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UploadRecord {
+    pub service: String,
+    pub ticket_id: String,
+    pub ticket_url: Option<String>,
+    pub attachment_url: Option<String>,
+    pub comment: Option<String>,
+    pub uploaded_at: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Fields of a saved entry that can be changed. Absent fields are left as
+/// they are; an empty `ticket_id` or `notes` clears it.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct HistoryEntryUpdate {
+    pub ticket_id: Option<String>,
+    pub pinned: Option<bool>,
+    pub notes: Option<String>,
+    /// Custom field values merged into the entry's; an empty value removes
+    /// the field
+    pub fields: Option<BTreeMap<String, String>>,
+}
+
+/// Options for one page of the history gallery. Structured filters are
+/// combined with any filters written into `search`.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct HistoryQuery {
+    pub search: Option<String>,
+    /// `relevance`, `date`, `size`, `ticket` or `annotations`
+    pub sort: Option<String>,
+    pub descending: Option<bool>,
+    /// `next_cursor` from the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+    /// Inclusive start date, `YYYY-MM-DD`
+    pub after: Option<String>,
+    /// Exclusive end date, `YYYY-MM-DD`
+    pub before: Option<String>,
+    /// `true` for uploaded entries only, `false` for never-uploaded ones
+    pub has_upload: Option<bool>,
+    pub has_ticket: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryPage {
+    pub items: Vec<ScreenshotMeta>,
+    pub total_count: usize,
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -33,79 +147,129 @@ pub async fn save_to_history(
     thumbnail_path: String,
     annotations_json: String,
     ticket_id: Option<String>,
+    ocr_text: Option<String>,
 ) -> Result<String, String> {
-    let history_dir = get_history_dir()?;
+    save_entry(
+        &get_history_dir()?,
+        Path::new(&original_path),
+        annotated_path.as_deref().map(Path::new),
+        Path::new(&thumbnail_path),
+        &annotations_json,
+        ticket_id,
+        ocr_text.as_deref(),
+    )
+}
+
+/// Save a new entry from image files on disk and return its id. Takes the
+/// history lock itself.
+pub(crate) fn save_entry(
+    history_dir: &Path,
+    original_path: &Path,
+    annotated_path: Option<&Path>,
+    thumbnail_path: &Path,
+    annotations_json: &str,
+    ticket_id: Option<String>,
+    ocr_text: Option<&str>,
+) -> Result<String, String> {
+    // Reject malformed annotations before anything is written
+    let annotations = annotations::validate_for_image(annotations_json, original_path)?;
+
     let id = Uuid::new_v4().to_string();
+    let staging_dir = history_dir.join(STAGING_DIR_NAME).join(&id);
     let screenshot_dir = history_dir.join(&id);
 
+    // Serialize writers across app instances for the whole save
+    let _lock = lock_history(history_dir)?;
+    clear_staging(history_dir)?;
+
     let persist_result = (|| -> Result<(), String> {
-        // Create screenshot directory
-        fs::create_dir_all(&screenshot_dir)
+        // Build the entry in a staging directory so a crash never leaves a
+        // half-written entry in the history directory itself
+        fs::create_dir_all(&staging_dir)
             .map_err(|e| format!("Failed to create screenshot directory: {}", e))?;
 
-        // Copy original file
-        let original_dest = screenshot_dir.join("original.png");
-        fs::copy(&original_path, &original_dest)
-            .map_err(|e| format!("Failed to copy original: {}", e))?;
-
-        // Copy annotated file if present
-        let annotated_dest = if let Some(ref annotated) = annotated_path {
-            let dest = screenshot_dir.join("annotated.png");
-            fs::copy(annotated, &dest).map_err(|e| format!("Failed to copy annotated: {}", e))?;
-            Some(dest.to_string_lossy().to_string())
-        } else {
-            None
-        };
-
-        // Copy thumbnail
-        let thumbnail_dest = screenshot_dir.join("thumbnail.png");
-        fs::copy(&thumbnail_path, &thumbnail_dest)
-            .map_err(|e| format!("Failed to copy thumbnail: {}", e))?;
+        // Images go to the shared blob store, so saving the same capture
+        // again does not store it twice
+        let original_dest = blob_store::store(history_dir, original_path)?;
+        let annotated_dest = annotated_path
+            .map(|annotated| blob_store::store(history_dir, annotated))
+            .transpose()?;
+        let thumbnail_dest = blob_store::store(history_dir, thumbnail_path)?;
 
         // Save annotations JSON
-        let annotations_dest = screenshot_dir.join("annotations.json");
-        fs::write(&annotations_dest, &annotations_json)
-            .map_err(|e| format!("Failed to write annotations: {}", e))?;
-
-        // Calculate size
-        let size_bytes = calculate_dir_size(&screenshot_dir)?;
+        write_atomic(
+            history_dir,
+            &staging_dir.join("annotations.json"),
+            annotations_json.as_bytes(),
+        )
+        .map_err(|e| format!("Failed to write annotations: {}", e))?;
+
+        // Save recognized screen text for full-text search
+        if let Some(text) = ocr_text {
+            write_atomic(
+                history_dir,
+                &staging_dir.join(OCR_TEXT_FILE_NAME),
+                text.as_bytes(),
+            )
+            .map_err(|e| format!("Failed to write OCR text: {}", e))?;
+        }
 
-        // Parse annotation count
-        let annotations: serde_json::Value = serde_json::from_str(&annotations_json)
-            .map_err(|e| format!("Failed to parse annotations JSON: {}", e))?;
-        let annotation_count = annotations.as_array().map(|a| a.len()).unwrap_or(0);
+        let annotation_count = annotations.len();
+        let redaction_count = annotations.iter().filter(|a| a.is_redaction()).count();
 
         // Create metadata
-        let meta = ScreenshotMeta {
+        let mut meta = ScreenshotMeta {
             id: id.clone(),
             original_path: original_dest.to_string_lossy().to_string(),
-            annotated_path: annotated_dest,
+            annotated_path: annotated_dest.map(|path| path.to_string_lossy().to_string()),
             thumbnail_path: thumbnail_dest.to_string_lossy().to_string(),
             created_at: chrono::Utc::now().to_rfc3339(),
             ticket_id,
             uploaded_url: None,
-            size_bytes,
+            size_bytes: 0,
             annotation_count,
+            redaction_count,
+            pinned: false,
+            tags: Vec::new(),
+            collections: Vec::new(),
+            uploads: Vec::new(),
+            notes: None,
+            fields: BTreeMap::new(),
         };
 
-        // Save metadata
-        let meta_path = screenshot_dir.join("meta.json");
-        let meta_json = serde_json::to_string_pretty(&meta)
-            .map_err(|e| format!("Failed to serialize metadata: {}", e))?;
-        fs::write(&meta_path, meta_json).map_err(|e| format!("Failed to write metadata: {}", e))?;
-
-        // Update index
-        update_index(&meta)?;
+        meta.size_bytes = entry_size(&staging_dir, &meta)?;
 
-        // Enforce storage budget
-        enforce_storage_budget()?;
+        // Save metadata
+        write_meta(history_dir, &staging_dir, &meta)?;
+
+        // Publish the finished entry with a single atomic rename
+        fs::rename(&staging_dir, &screenshot_dir)
+            .map_err(|e| format!("Failed to move screenshot into history: {}", e))?;
+        sync_dir(history_dir).map_err(|e| format!("Failed to sync history directory: {}", e))?;
+
+        // Record in the history database, together with the search and
+        // image indexes so they never disagree with the entry row
+        let conn = history_db::open(history_dir)?;
+        history_db::atomically(&conn, || {
+            history_db::upsert(&conn, &meta)?;
+            history_search::index_entry(&conn, &screenshot_dir, &meta)?;
+            history_similar::index_entry(&conn, &meta)?;
+            Ok(())
+        })?;
+
+        // Apply the retention policy and storage budget
+        retention::enforce(history_dir, &conn)?;
 
         Ok(())
     })();
 
     if let Err(err) = persist_result {
+        let _ = fs::remove_dir_all(&staging_dir);
         let _ = fs::remove_dir_all(&screenshot_dir);
-        let _ = remove_from_index(&id);
+        if let Ok(conn) = history_db::open(history_dir) {
+            let _ = history_db::delete(&conn, &id);
+            let _ = blob_store::collect_garbage(&conn, history_dir);
+        }
         return Err(err);
     }
 
@@ -117,85 +281,134 @@ pub async fn get_history(
     search: Option<String>,
     limit: Option<u32>,
 ) -> Result<Vec<ScreenshotMeta>, String> {
-    let index = load_index()?;
+    let conn = history_db::open(&get_history_dir()?)?;
     let limit = limit.unwrap_or(20) as usize;
+    let query = history_search::parse_query(search.as_deref().unwrap_or_default())?;
 
-    let mut results: Vec<ScreenshotMeta> = if let Some(search_term) = search {
-        let search_lower = search_term.to_lowercase();
-        index
-            .into_iter()
-            .filter(|meta| {
-                meta.ticket_id
-                    .as_ref()
-                    .map(|t| t.to_lowercase().contains(&search_lower))
-                    .unwrap_or(false)
-                    || meta.created_at.to_lowercase().contains(&search_lower)
-            })
-            .collect()
-    } else {
-        index
-    };
+    history_search::search(&conn, &query, limit)
+}
+
+/// Fetch one page of history. Pass the returned `next_cursor` back to get the
+/// following page; it is `None` on the last page.
+#[tauri::command]
+pub async fn query_history(request: HistoryQuery) -> Result<HistoryPage, String> {
+    let conn = history_db::open(&get_history_dir()?)?;
+    let limit = request.limit.unwrap_or(20).clamp(1, 500) as usize;
+
+    let mut query = history_search::parse_query(request.search.as_deref().unwrap_or_default())?;
+    if let Some(ref after) = request.after {
+        query.after = Some(history_search::parse_date(after)?);
+    }
+    if let Some(ref before) = request.before {
+        query.before = Some(history_search::parse_date(before)?);
+    }
+    for (wanted, filter) in [
+        (request.has_upload, HasFilter::Upload),
+        (request.has_ticket, HasFilter::Ticket),
+    ] {
+        match wanted {
+            Some(true) => query.has.push(filter),
+            Some(false) => query.lacks.push(filter),
+            None => {}
+        }
+    }
+
+    let sort = request.sort.as_deref().map(SortKey::parse).transpose()?;
+    let page = history_search::search_page(
+        &conn,
+        &query,
+        sort,
+        request.descending,
+        request.cursor.as_deref(),
+        limit,
+    )?;
+
+    Ok(HistoryPage {
+        items: page.items,
+        total_count: page.total_count,
+        next_cursor: page.next_cursor,
+    })
+}
+
+/// Change the editable fields of a saved entry and return the updated entry
+#[tauri::command]
+pub async fn update_history_entry(
+    id: String,
+    update: HistoryEntryUpdate,
+) -> Result<ScreenshotMeta, String> {
+    let normalized_id = parse_history_id(&id)?.to_string();
+    let history_dir = get_history_dir()?;
+    let _lock = lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
 
-    // Sort by created_at descending (most recent first)
-    results.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    let mut meta = history_db::find(&conn, &normalized_id)?
+        .ok_or_else(|| format!("History item not found: {}", normalized_id))?;
 
-    // Limit results
-    results.truncate(limit);
+    if let Some(ticket_id) = update.ticket_id {
+        let ticket_id = ticket_id.trim();
+        meta.ticket_id = (!ticket_id.is_empty()).then(|| ticket_id.to_string());
+    }
+    if let Some(pinned) = update.pinned {
+        meta.pinned = pinned;
+    }
+    if let Some(notes) = update.notes {
+        meta.notes = history_fields::normalize_notes(&notes);
+    }
+    if let Some(fields) = update.fields {
+        let definitions = history_fields::load_fields(&conn)?;
+        history_fields::apply_values(&definitions, &mut meta.fields, &fields)?;
+    }
 
-    Ok(results)
+    history_db::atomically(&conn, || {
+        history_db::upsert(&conn, &meta)?;
+        refresh_entry(&conn, &history_dir, &normalized_id)
+    })
 }
 
+/// Move an entry to the trash, from where it can be restored until the
+/// retention policy's `trash_days` have passed
 #[tauri::command]
 pub async fn delete_from_history(id: String) -> Result<(), String> {
     let normalized_id = parse_history_id(&id)?.to_string();
     let history_dir = get_history_dir()?;
     let screenshot_dir = history_dir.join(&normalized_id);
 
-    if screenshot_dir.exists() {
-        if !screenshot_dir.is_dir() {
-            return Err("History entry is not a directory".to_string());
-        }
-
-        fs::remove_dir_all(&screenshot_dir)
-            .map_err(|e| format!("Failed to delete screenshot directory: {}", e))?;
+    if screenshot_dir.exists() && !screenshot_dir.is_dir() {
+        return Err("History entry is not a directory".to_string());
     }
 
-    // Remove from index
-    remove_from_index(&normalized_id)?;
+    let _lock = lock_history(&history_dir)?;
 
-    Ok(())
+    let conn = history_db::open(&history_dir)?;
+    history_trash::move_to_trash(&conn, &history_dir, &normalized_id)
 }
 
 #[tauri::command]
 pub async fn get_storage_usage() -> Result<StorageUsage, String> {
-    let history_dir = get_history_dir()?;
-    let mut used_bytes = 0u64;
-    let mut item_count = 0usize;
-
-    if history_dir.exists() {
-        for entry in fs::read_dir(&history_dir).map_err(|e| e.to_string())? {
-            let entry = entry.map_err(|e| e.to_string())?;
-            if entry.path().is_dir() {
-                used_bytes += calculate_dir_size(&entry.path())?;
-                item_count += 1;
-            }
-        }
-    }
+    let conn = history_db::open(&get_history_dir()?)?;
+    let (used_bytes, item_count) = history_db::storage_usage(&conn)?;
 
     Ok(StorageUsage {
         used_bytes,
-        budget_bytes: STORAGE_BUDGET_BYTES,
+        budget_bytes: retention::load_policy(&conn)?.budget_bytes(),
         item_count,
     })
 }
 
 // Helper functions
 
-fn get_history_dir() -> Result<PathBuf, String> {
-    let app_support =
-        dirs::data_local_dir().ok_or_else(|| "Failed to get local data directory".to_string())?;
-
-    let history_dir = app_support.join("com.screenshot-annotate").join("history");
+pub(crate) fn get_history_dir() -> Result<PathBuf, String> {
+    let history_dir = match history_location::configured_dir()? {
+        // Never recreate a chosen location, which may be an unmounted volume
+        Some(dir) if !dir.is_dir() => {
+            return Err(format!(
+                "History location is not available: {}",
+                dir.display()
+            ))
+        }
+        Some(dir) => dir,
+        None => history_location::default_dir()?,
+    };
 
     if !history_dir.exists() {
         fs::create_dir_all(&history_dir)
@@ -205,107 +418,236 @@ fn get_history_dir() -> Result<PathBuf, String> {
     Ok(history_dir)
 }
 
-fn get_index_path() -> Result<PathBuf, String> {
-    let history_dir = get_history_dir()?;
-    Ok(history_dir.join("index.json"))
+/// Exclusive lock on the history directory, released when dropped
+pub(crate) struct HistoryLock {
+    _file: File,
 }
 
-fn parse_history_id(id: &str) -> Result<Uuid, String> {
-    Uuid::parse_str(id).map_err(|_| "Invalid history item id".to_string())
-}
+pub(crate) fn lock_history(history_dir: &Path) -> Result<HistoryLock, String> {
+    let file = File::create(history_dir.join(LOCK_FILE_NAME))
+        .map_err(|e| format!("Failed to open history lock: {}", e))?;
+    file.lock_exclusive()
+        .map_err(|e| format!("Failed to lock history: {}", e))?;
 
-fn load_index() -> Result<Vec<ScreenshotMeta>, String> {
-    let index_path = get_index_path()?;
+    Ok(HistoryLock { _file: file })
+}
 
-    if !index_path.exists() {
-        return Ok(Vec::new());
+/// Remove leftovers from saves that were interrupted mid-write. Must only be
+/// called while holding the history lock.
+pub(crate) fn clear_staging(history_dir: &Path) -> Result<(), String> {
+    let staging_root = history_dir.join(STAGING_DIR_NAME);
+    if staging_root.exists() {
+        fs::remove_dir_all(&staging_root)
+            .map_err(|e| format!("Failed to clear staging directory: {}", e))?;
     }
 
-    let content = fs::read_to_string(&index_path).map_err(|e| e.to_string())?;
-    serde_json::from_str(&content).map_err(|e| e.to_string())
+    Ok(())
 }
 
-fn update_index(meta: &ScreenshotMeta) -> Result<(), String> {
-    let index_path = get_index_path()?;
-    let mut index = load_index()?;
+/// Names inside the history directory that are not screenshot entries
+pub(crate) fn is_internal_entry(name: &str) -> bool {
+    name.starts_with('.')
+}
 
-    // Add or update entry
-    if let Some(existing) = index.iter_mut().find(|m| m.id == meta.id) {
-        *existing = meta.clone();
-    } else {
-        index.push(meta.clone());
-    }
+pub(crate) fn parse_history_id(id: &str) -> Result<Uuid, String> {
+    Uuid::parse_str(id).map_err(|_| "Invalid history item id".to_string())
+}
 
-    let json = serde_json::to_string_pretty(&index).map_err(|e| e.to_string())?;
-    fs::write(&index_path, json).map_err(|e| e.to_string())?;
+/// Look up a single history entry by id
+pub(crate) fn find_history_entry(id: &str) -> Result<ScreenshotMeta, String> {
+    let normalized_id = parse_history_id(id)?.to_string();
+    let conn = history_db::open(&get_history_dir()?)?;
 
-    Ok(())
+    history_db::find(&conn, &normalized_id)?
+        .ok_or_else(|| format!("History item not found: {}", normalized_id))
 }
 
-fn remove_from_index(id: &str) -> Result<(), String> {
-    let index_path = get_index_path()?;
-    let mut index = load_index()?;
+/// Append an upload attempt to an entry. A successful upload also becomes
+/// the entry's `uploaded_url`.
+pub(crate) fn record_upload(id: &str, record: UploadRecord) -> Result<ScreenshotMeta, String> {
+    let normalized_id = parse_history_id(id)?.to_string();
+    let history_dir = get_history_dir()?;
+    let _lock = lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
 
-    index.retain(|m| m.id != id);
+    let mut meta = history_db::find(&conn, &normalized_id)?
+        .ok_or_else(|| format!("History item not found: {}", normalized_id))?;
 
-    let json = serde_json::to_string_pretty(&index).map_err(|e| e.to_string())?;
-    fs::write(&index_path, json).map_err(|e| e.to_string())?;
+    if record.success {
+        meta.uploaded_url = record
+            .attachment_url
+            .clone()
+            .or_else(|| record.ticket_url.clone());
+    }
+    meta.uploads.push(record);
 
-    Ok(())
+    history_db::atomically(&conn, || {
+        history_db::upsert(&conn, &meta)?;
+        refresh_entry(&conn, &history_dir, &normalized_id)
+    })
 }
 
-fn calculate_dir_size(path: &PathBuf) -> Result<u64, String> {
-    let mut size = 0u64;
+/// Rewrite an entry's `meta.json` and search document from the database
+/// after its labels or other metadata changed. Must be called while holding
+/// the history lock.
+pub(crate) fn refresh_entry(
+    conn: &Connection,
+    history_dir: &Path,
+    id: &str,
+) -> Result<ScreenshotMeta, String> {
+    let meta =
+        history_db::find(conn, id)?.ok_or_else(|| format!("History item not found: {}", id))?;
+    let entry_dir = history_dir.join(id);
+
+    write_meta(history_dir, &entry_dir, &meta)?;
+    history_search::index_entry(conn, &entry_dir, &meta)?;
+
+    Ok(meta)
+}
 
-    for entry in fs::read_dir(path).map_err(|e| e.to_string())? {
-        let entry = entry.map_err(|e| e.to_string())?;
-        let metadata = entry.metadata().map_err(|e| e.to_string())?;
+/// Write an entry's `meta.json` with portable paths
+pub(crate) fn write_meta(
+    history_dir: &Path,
+    entry_dir: &Path,
+    meta: &ScreenshotMeta,
+) -> Result<(), String> {
+    let stored = portable_paths(meta.clone());
+    let meta_json = serde_json::to_string_pretty(&history_schema::versioned(&stored))
+        .map_err(|e| format!("Failed to serialize metadata: {}", e))?;
+    write_atomic(
+        history_dir,
+        &entry_dir.join(META_FILE_NAME),
+        meta_json.as_bytes(),
+    )
+    .map_err(|e| format!("Failed to write metadata: {}", e))
+}
 
-        if metadata.is_file() {
-            size += metadata.len();
-        } else if metadata.is_dir() {
-            size += calculate_dir_size(&entry.path())?;
+/// Read an entry's `meta.json`, upgrading metadata written by older
+/// versions. The error says why the file cannot be used.
+pub(crate) fn read_meta(history_dir: &Path, entry_dir: &Path) -> Result<ScreenshotMeta, String> {
+    let content = history_crypto::read_to_string(&entry_dir.join(META_FILE_NAME))
+        .map_err(|e| format!("Failed to read meta.json: {}", e))?;
+    history_schema::parse_meta(&content)
+        .map(|meta| resolved_paths(history_dir, meta))
+        .map_err(|e| format!("Unreadable meta.json: {}", e))
+}
+
+/// `meta` with its image paths relative to the history directory, as
+/// stored in `meta.json` and the index so that history can be moved
+pub(crate) fn portable_paths(meta: ScreenshotMeta) -> ScreenshotMeta {
+    map_paths(meta, relative_path)
+}
+
+/// `meta` with portable paths resolved against `history_dir`. Absolute
+/// paths written before history could move are rebased the same way.
+pub(crate) fn resolved_paths(history_dir: &Path, meta: ScreenshotMeta) -> ScreenshotMeta {
+    map_paths(meta, |id, path| {
+        relative_path(id, path)
+            .map(|relative| history_dir.join(relative).to_string_lossy().to_string())
+    })
+}
+
+fn map_paths(
+    mut meta: ScreenshotMeta,
+    map: impl Fn(&str, &str) -> Option<String>,
+) -> ScreenshotMeta {
+    let id = meta.id.clone();
+    for path in [
+        Some(&mut meta.original_path),
+        meta.annotated_path.as_mut(),
+        Some(&mut meta.thumbnail_path),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if let Some(mapped) = map(&id, path) {
+            *path = mapped;
         }
     }
+    meta
+}
 
-    Ok(size)
+/// The part of `path` from the entry directory or the blob store on, with
+/// `/` separators, or `None` for a path outside history. Both `/` and `\`
+/// separate parts, so paths written on Windows are understood everywhere.
+pub(crate) fn relative_path(id: &str, path: &str) -> Option<String> {
+    let parts: Vec<&str> = path
+        .split(['/', '\\'])
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect();
+    let start = parts
+        .iter()
+        .rposition(|part| *part == id || *part == BLOBS_DIR_NAME)?;
+
+    Some(parts[start..].join("/"))
 }
 
-fn enforce_storage_budget() -> Result<(), String> {
-    let usage = futures::executor::block_on(get_storage_usage())?;
+/// Recompute entry, trash and blob sizes from disk and reset the running
+/// storage total. Saves and deletes keep the total current, so only repair
+/// and changes that rewrite every file need this. Must be called while
+/// holding the history lock.
+pub(crate) fn rescan_usage(conn: &Connection, history_dir: &Path) -> Result<(), String> {
+    blob_store::rescan_sizes(conn, history_dir)?;
+
+    for mut meta in history_db::list_oldest_first(conn)? {
+        let entry_dir = history_dir.join(&meta.id);
+        if !entry_dir.is_dir() {
+            continue;
+        }
+        let size = entry_size(&entry_dir, &meta)?;
+        if size != meta.size_bytes {
+            meta.size_bytes = size;
+            history_db::upsert(conn, &meta)?;
+            write_meta(history_dir, &entry_dir, &meta)?;
+        }
+    }
+
+    history_trash::rescan_sizes(conn, history_dir)?;
+    history_db::recount_storage_usage(conn)
+}
 
-    if usage.used_bytes <= STORAGE_BUDGET_BYTES {
-        return Ok(());
+/// Size of an entry directory plus the blobs it references, shared or not
+pub(crate) fn entry_size(entry_dir: &Path, meta: &ScreenshotMeta) -> Result<u64, String> {
+    let mut size = calculate_dir_size(entry_dir)?;
+
+    for path in [
+        Some(&meta.original_path),
+        meta.annotated_path.as_ref(),
+        Some(&meta.thumbnail_path),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        let path = Path::new(path);
+        if blob_store::hash_of_path(path).is_some() {
+            size += fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        }
     }
 
-    // Load index and sort by created_at ascending (oldest first)
-    let mut index = load_index()?;
-    index.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    Ok(size)
+}
 
-    let history_dir = get_history_dir()?;
-    let mut current_size = usage.used_bytes;
+pub(crate) fn calculate_dir_size(path: &Path) -> Result<u64, String> {
+    let mut size = 0u64;
 
-    // Delete oldest entries until under budget
-    for meta in index.iter() {
-        if current_size <= STORAGE_BUDGET_BYTES {
-            break;
-        }
+    for entry in fs::read_dir(path).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let metadata = entry.metadata().map_err(|e| e.to_string())?;
 
-        let screenshot_dir = history_dir.join(&meta.id);
-        if screenshot_dir.exists() {
-            let dir_size = calculate_dir_size(&screenshot_dir)?;
-            fs::remove_dir_all(&screenshot_dir).map_err(|e| e.to_string())?;
-            remove_from_index(&meta.id)?;
-            current_size = current_size.saturating_sub(dir_size);
+        if metadata.is_file() {
+            size += metadata.len();
+        } else if metadata.is_dir() {
+            size += calculate_dir_size(&entry.path())?;
         }
     }
 
-    Ok(())
+    Ok(size)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parse_history_id;
+    use super::{parse_history_id, portable_paths, resolved_paths, ScreenshotMeta};
+    use std::path::Path;
 
     #[test]
     fn parse_history_id_accepts_uuid() {
@@ -324,4 +666,35 @@ mod tests {
         assert!(parse_history_id("").is_err());
         assert!(parse_history_id("not-a-uuid").is_err());
     }
+
+    #[test]
+    fn paths_are_stored_relative_and_rebased_on_load() {
+        let id = "550e8400-e29b-41d4-a716-446655440000";
+        let blob = format!(".blobs/ab/{}.png", "ab".repeat(32));
+        let meta = ScreenshotMeta {
+            original_path: format!("/old/history/{}", blob),
+            annotated_path: Some(format!("/old/history/{}/annotated.png", id)),
+            thumbnail_path: "/elsewhere/thumbnail.png".to_string(),
+            ..ScreenshotMeta::test_entry(id)
+        };
+
+        let portable = portable_paths(meta);
+        assert_eq!(portable.original_path, blob);
+        assert_eq!(
+            portable.annotated_path.as_deref(),
+            Some(format!("{}/annotated.png", id).as_str())
+        );
+        // Paths outside history are left alone
+        assert_eq!(portable.thumbnail_path, "/elsewhere/thumbnail.png");
+
+        let resolved = resolved_paths(Path::new("/new/history"), portable);
+        assert_eq!(
+            Path::new(&resolved.original_path),
+            Path::new("/new/history").join(&blob)
+        );
+        assert_eq!(
+            Path::new(resolved.annotated_path.as_deref().unwrap()),
+            Path::new("/new/history").join(id).join("annotated.png")
+        );
+    }
 }
@@ -0,0 +1,623 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::blob_store;
+use crate::fs_util::sync_dir;
+use crate::history::{self, ScreenshotMeta, STAGING_DIR_NAME};
+use crate::history_crypto;
+use crate::history_db;
+use crate::history_schema;
+use crate::history_search;
+use crate::history_trash::TRASH_DIR_NAME;
+use crate::retention::{self, EvictionPlan};
+
+const ARCHIVE_FORMAT: &str = "screenshot-annotate-history";
+/// Bump when the archive layout changes; imports refuse newer versions
+const ARCHIVE_VERSION: u32 = 1;
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+const ENTRIES_PREFIX: &str = "entries/";
+const BLOBS_PREFIX: &str = "blobs/";
+
+/// Archive layout: `manifest.json`, one `entries/<id>/` folder per entry
+/// holding its files, and shared images once each under `blobs/`. Paths in
+/// the archived `meta.json` files are relative to the archive root.
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveManifest {
+    format: String,
+    version: u32,
+    exported_at: String,
+    entry_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryExportRequest {
+    /// Specific entries to export; takes precedence over `search`
+    pub ids: Option<Vec<String>>,
+    /// Gallery search syntax; everything when both this and `ids` are empty
+    pub search: Option<String>,
+    pub output_path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryExportResult {
+    pub archive_path: String,
+    pub entry_count: usize,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RenamedEntry {
+    pub archive_id: String,
+    pub new_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct FailedImport {
+    pub archive_id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct HistoryImportResult {
+    /// Ids of the imported entries in this history
+    pub imported: Vec<String>,
+    /// Entries this history already had
+    pub skipped: Vec<String>,
+    /// Entries whose id was taken by a different entry here
+    pub renamed: Vec<RenamedEntry>,
+    pub failed: Vec<FailedImport>,
+    /// What the storage budget evicted once the import finished
+    pub eviction: Option<EvictionPlan>,
+}
+
+/// Export history to a single archive for moving to another machine. The
+/// archive is never encrypted, even when history is.
+#[tauri::command]
+pub async fn export_history_archive(
+    request: HistoryExportRequest,
+) -> Result<HistoryExportResult, String> {
+    let history_dir = history::get_history_dir()?;
+    let conn = history_db::open(&history_dir)?;
+
+    let entries = match request.ids {
+        Some(ids) => ids
+            .iter()
+            .map(|id| {
+                let id = history::parse_history_id(id)?.to_string();
+                history_db::find(&conn, &id)?
+                    .ok_or_else(|| format!("History item not found: {}", id))
+            })
+            .collect::<Result<Vec<_>, String>>()?,
+        None => history_search::search_all(&conn, request.search.as_deref().unwrap_or_default())?,
+    };
+    if entries.is_empty() {
+        return Err("No history entries to export".to_string());
+    }
+
+    let archive_path = match request.output_path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            std::env::temp_dir().join(format!("history_backup_{}.zip", timestamp))
+        }
+    };
+
+    write_archive(&history_dir, &entries, &archive_path)?;
+    let size_bytes = fs::metadata(&archive_path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+
+    Ok(HistoryExportResult {
+        archive_path: archive_path.to_string_lossy().to_string(),
+        entry_count: entries.len(),
+        size_bytes,
+    })
+}
+
+/// Merge an archive into this history, then apply the retention policy
+#[tauri::command]
+pub async fn import_history_archive(archive_path: String) -> Result<HistoryImportResult, String> {
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    history::clear_staging(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    let mut result = import_archive(&conn, &history_dir, Path::new(&archive_path))?;
+    result.eviction = Some(retention::enforce(&history_dir, &conn)?);
+
+    Ok(result)
+}
+
+/// Archive name for a file inside an entry directory
+fn entry_file_name(id: &str, relative: &Path) -> String {
+    let parts: Vec<String> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().to_string())
+        .collect();
+    format!("{}{}/{}", ENTRIES_PREFIX, id, parts.join("/"))
+}
+
+/// Where an image path from `meta` lives in the archive, if it is stored in
+/// history at all
+fn archived_path(history_dir: &Path, id: &str, path: &str) -> Option<String> {
+    let path = Path::new(path);
+    if let Some(hash) = blob_store::hash_of_path(path) {
+        return Some(format!("{}{}.png", BLOBS_PREFIX, hash));
+    }
+    path.strip_prefix(history_dir.join(id))
+        .ok()
+        .map(|relative| entry_file_name(id, relative))
+}
+
+fn write_archive(
+    history_dir: &Path,
+    entries: &[ScreenshotMeta],
+    archive_path: &Path,
+) -> Result<(), String> {
+    let file_name = archive_path
+        .file_name()
+        .ok_or_else(|| "Archive path has no file name".to_string())?;
+    let tmp_path = archive_path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+
+    let result = (|| -> Result<(), String> {
+        let file = File::create(&tmp_path)
+            .map_err(|e| format!("Failed to create history archive: {}", e))?;
+        let mut zip = ZipWriter::new(file);
+        let map_err = |e: zip::result::ZipError| format!("Failed to write history archive: {}", e);
+
+        let manifest = ArchiveManifest {
+            format: ARCHIVE_FORMAT.to_string(),
+            version: ARCHIVE_VERSION,
+            exported_at: chrono::Utc::now().to_rfc3339(),
+            entry_ids: entries.iter().map(|meta| meta.id.clone()).collect(),
+        };
+        let manifest_json = serde_json::to_string_pretty(&manifest)
+            .map_err(|e| format!("Failed to serialize archive manifest: {}", e))?;
+        add_file(&mut zip, MANIFEST_FILE_NAME, manifest_json.as_bytes())?;
+
+        let mut written_blobs = HashSet::new();
+        for meta in entries {
+            let entry_dir = history_dir.join(&meta.id);
+            let mut archived = meta.clone();
+
+            for path in [
+                Some(&mut archived.original_path),
+                archived.annotated_path.as_mut(),
+                Some(&mut archived.thumbnail_path),
+            ]
+            .into_iter()
+            .flatten()
+            {
+                let Some(name) = archived_path(history_dir, &meta.id, path) else {
+                    continue;
+                };
+                if name.starts_with(BLOBS_PREFIX) && written_blobs.insert(name.clone()) {
+                    let contents = history_crypto::read(Path::new(path.as_str()))
+                        .map_err(|e| format!("Failed to read image for {}: {}", meta.id, e))?;
+                    add_file(&mut zip, &name, &contents)?;
+                }
+                *path = name;
+            }
+
+            for file in entry_files(&entry_dir)? {
+                let relative = file.strip_prefix(&entry_dir).unwrap_or(&file);
+                if relative == Path::new("meta.json") {
+                    continue;
+                }
+                let contents = history_crypto::read(&file)
+                    .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+                add_file(&mut zip, &entry_file_name(&meta.id, relative), &contents)?;
+            }
+
+            let meta_json = serde_json::to_string_pretty(&history_schema::versioned(&archived))
+                .map_err(|e| format!("Failed to serialize metadata: {}", e))?;
+            add_file(
+                &mut zip,
+                &entry_file_name(&meta.id, Path::new("meta.json")),
+                meta_json.as_bytes(),
+            )?;
+        }
+
+        zip.finish()
+            .map_err(map_err)?
+            .sync_all()
+            .map_err(|e| format!("Failed to write history archive: {}", e))?;
+        fs::rename(&tmp_path, archive_path)
+            .map_err(|e| format!("Failed to write history archive: {}", e))
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+fn add_file(zip: &mut ZipWriter<File>, name: &str, contents: &[u8]) -> Result<(), String> {
+    // PNGs are already compressed
+    let method = if name.ends_with(".png") {
+        CompressionMethod::Stored
+    } else {
+        CompressionMethod::Deflated
+    };
+    zip.start_file(
+        name,
+        SimpleFileOptions::default().compression_method(method),
+    )
+    .map_err(|e| format!("Failed to write {} to archive: {}", name, e))?;
+    zip.write_all(contents)
+        .map_err(|e| format!("Failed to write {} to archive: {}", name, e))
+}
+
+/// Files under an entry directory, skipping temp files
+fn entry_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())?.flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            files.extend(entry_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+fn read_archive_file(archive: &mut ZipArchive<File>, name: &str) -> Result<Vec<u8>, String> {
+    let mut file = archive
+        .by_name(name)
+        .map_err(|e| format!("Archive is missing {}: {}", name, e))?;
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)
+        .map_err(|e| format!("Failed to read {} from archive: {}", name, e))?;
+    Ok(contents)
+}
+
+fn read_archive_json<T: serde::de::DeserializeOwned>(
+    archive: &mut ZipArchive<File>,
+    name: &str,
+) -> Result<T, String> {
+    serde_json::from_slice(&read_archive_file(archive, name)?)
+        .map_err(|e| format!("Failed to parse {} from archive: {}", name, e))
+}
+
+/// A relative path from the archive, refusing anything that could escape
+/// the directory it is extracted into
+fn safe_relative_path(name: &str) -> Result<PathBuf, String> {
+    let path = Path::new(name);
+    if name.is_empty()
+        || !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(format!("Unsafe path in archive: {}", name));
+    }
+    Ok(path.to_path_buf())
+}
+
+/// Merge every entry in the archive into `history_dir`. Must be called
+/// while holding the history lock.
+fn import_archive(
+    conn: &Connection,
+    history_dir: &Path,
+    archive_path: &Path,
+) -> Result<HistoryImportResult, String> {
+    let file =
+        File::open(archive_path).map_err(|e| format!("Failed to open history archive: {}", e))?;
+    let mut archive =
+        ZipArchive::new(file).map_err(|e| format!("Failed to read history archive: {}", e))?;
+
+    let manifest: ArchiveManifest = read_archive_json(&mut archive, MANIFEST_FILE_NAME)?;
+    if manifest.format != ARCHIVE_FORMAT {
+        return Err("Not a history archive".to_string());
+    }
+    if manifest.version > ARCHIVE_VERSION {
+        return Err(format!(
+            "History archive version {} is newer than this app supports",
+            manifest.version
+        ));
+    }
+
+    let mut result = HistoryImportResult::default();
+    for archive_id in &manifest.entry_ids {
+        let outcome = (|| -> Result<Option<String>, String> {
+            let archive_id = history::parse_history_id(archive_id)?.to_string();
+            let meta_name = entry_file_name(&archive_id, Path::new("meta.json"));
+            let meta_json = String::from_utf8(read_archive_file(&mut archive, &meta_name)?)
+                .map_err(|e| format!("Failed to parse {} from archive: {}", meta_name, e))?;
+            let meta = history_schema::parse_meta(&meta_json)
+                .map_err(|e| format!("Failed to parse {} from archive: {}", meta_name, e))?;
+
+            // Same capture, possibly imported before under a new id
+            let original_hash = if meta.original_path.starts_with(BLOBS_PREFIX)
+                || meta.original_path.starts_with(ENTRIES_PREFIX)
+            {
+                Some(blob_store::hash_bytes(&read_archive_file(
+                    &mut archive,
+                    &meta.original_path,
+                )?))
+            } else {
+                None
+            };
+            if has_capture(conn, &archive_id, &meta, original_hash.as_deref())? {
+                return Ok(None);
+            }
+            let taken = history_db::find(conn, &archive_id)?.is_some()
+                || history_dir.join(&archive_id).exists()
+                || history_dir.join(TRASH_DIR_NAME).join(&archive_id).exists();
+            let target_id = if taken {
+                Uuid::new_v4().to_string()
+            } else {
+                archive_id.clone()
+            };
+
+            import_entry(
+                conn,
+                history_dir,
+                &mut archive,
+                &archive_id,
+                &target_id,
+                meta,
+            )?;
+            Ok(Some(target_id))
+        })();
+
+        match outcome {
+            Ok(Some(target_id)) => {
+                if &target_id != archive_id {
+                    result.renamed.push(RenamedEntry {
+                        archive_id: archive_id.clone(),
+                        new_id: target_id.clone(),
+                    });
+                }
+                result.imported.push(target_id);
+            }
+            Ok(None) => result.skipped.push(archive_id.clone()),
+            Err(error) => result.failed.push(FailedImport {
+                archive_id: archive_id.clone(),
+                error,
+            }),
+        }
+    }
+
+    // Blobs stored for entries that failed part way
+    blob_store::collect_garbage(conn, history_dir)?;
+
+    Ok(result)
+}
+
+/// Whether a live entry already has the original image with `hash`, which
+/// identifies a capture across machines. Legacy entries keep their original
+/// outside the blob store, so those only match under the same id, as do
+/// entries whose original was not archived because it lives outside history.
+fn has_capture(
+    conn: &Connection,
+    archive_id: &str,
+    meta: &ScreenshotMeta,
+    hash: Option<&str>,
+) -> Result<bool, String> {
+    if let Some(hash) = hash {
+        if blob_store::has_original(conn, hash)? {
+            return Ok(true);
+        }
+    }
+    let Some(existing) = history_db::find(conn, archive_id)? else {
+        return Ok(false);
+    };
+    let existing_original = Path::new(&existing.original_path);
+    Ok(match hash {
+        Some(hash) => {
+            blob_store::hash_of_path(existing_original).is_none()
+                && blob_store::hash_file(existing_original).ok().as_deref() == Some(hash)
+        }
+        None => existing.original_path == meta.original_path,
+    })
+}
+
+/// Extract one entry under `target_id`, pointing its paths at this history
+fn import_entry(
+    conn: &Connection,
+    history_dir: &Path,
+    archive: &mut ZipArchive<File>,
+    archive_id: &str,
+    target_id: &str,
+    mut meta: ScreenshotMeta,
+) -> Result<(), String> {
+    let staging_dir = history_dir.join(STAGING_DIR_NAME).join(target_id);
+    let entry_dir = history_dir.join(target_id);
+    let prefix = format!("{}{}/", ENTRIES_PREFIX, archive_id);
+
+    let result = (|| -> Result<(), String> {
+        fs::create_dir_all(&staging_dir)
+            .map_err(|e| format!("Failed to create screenshot directory: {}", e))?;
+
+        let names: Vec<String> = archive
+            .file_names()
+            .filter(|name| name.starts_with(&prefix) && !name.ends_with('/'))
+            .map(str::to_string)
+            .collect();
+        for name in names {
+            let relative = safe_relative_path(&name[prefix.len()..])?;
+            // Originals go to the blob store so later imports dedupe on them
+            if relative == Path::new("meta.json") || name == meta.original_path {
+                continue;
+            }
+            let dest = staging_dir.join(&relative);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            let contents = read_archive_file(archive, &name)?;
+            history_crypto::write_atomic(history_dir, &dest, &contents)
+                .map_err(|e| format!("Failed to write {}: {}", dest.display(), e))?;
+        }
+
+        meta.id = target_id.to_string();
+        let original_name = meta.original_path.clone();
+        for path in [
+            Some(&mut meta.original_path),
+            meta.annotated_path.as_mut(),
+            Some(&mut meta.thumbnail_path),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if path.starts_with(BLOBS_PREFIX)
+                || (*path == original_name && path.starts_with(&prefix))
+            {
+                let contents = read_archive_file(archive, path)?;
+                *path = blob_store::store_bytes(history_dir, &contents)?
+                    .to_string_lossy()
+                    .to_string();
+            } else if let Some(relative) = path.strip_prefix(&prefix) {
+                *path = entry_dir
+                    .join(safe_relative_path(relative)?)
+                    .to_string_lossy()
+                    .to_string();
+            }
+        }
+        meta.size_bytes = history::entry_size(&staging_dir, &meta)?;
+
+        history::write_meta(history_dir, &staging_dir, &meta)?;
+
+        fs::rename(&staging_dir, &entry_dir)
+            .map_err(|e| format!("Failed to move screenshot into history: {}", e))?;
+        sync_dir(history_dir).map_err(|e| format!("Failed to sync history directory: {}", e))?;
+
+        history_db::upsert(conn, &meta)?;
+        history_search::index_entry(conn, &entry_dir, &meta)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&staging_dir);
+        let _ = fs::remove_dir_all(&entry_dir);
+        let _ = history_db::delete(conn, target_id);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import_archive, safe_relative_path, write_archive, RenamedEntry};
+    use crate::blob_store;
+    use crate::history::ScreenshotMeta;
+    use crate::history_db;
+    use std::fs;
+    use std::path::Path;
+
+    const SHARED: &str = "4f0e4c1e-0000-4000-8000-000000000001";
+    const LEGACY: &str = "4f0e4c1e-0000-4000-8000-000000000002";
+
+    #[test]
+    fn archives_round_trip_into_another_history() {
+        let root = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let source = root.join("source");
+        fs::create_dir_all(source.join(SHARED)).unwrap();
+        fs::create_dir_all(source.join(LEGACY)).unwrap();
+        fs::write(root.join("capture.png"), b"pixels").unwrap();
+
+        // One entry in the blob store, one saved before it existed
+        let blob = blob_store::store(&source, &root.join("capture.png")).unwrap();
+        let legacy_original = source.join(LEGACY).join("original.png");
+        fs::write(&legacy_original, b"old pixels").unwrap();
+        for id in [SHARED, LEGACY] {
+            fs::write(source.join(id).join("annotations.json"), b"[]").unwrap();
+        }
+        // Both captured at the same second, which must not merge them
+        let blob_path = blob.to_string_lossy().to_string();
+        let legacy_path = legacy_original.to_string_lossy().to_string();
+        let entries = vec![
+            ScreenshotMeta {
+                original_path: blob_path.clone(),
+                thumbnail_path: blob_path.clone(),
+                ticket_id: Some("INC-4".to_string()),
+                pinned: true,
+                tags: vec!["vpn".to_string()],
+                ..ScreenshotMeta::test_entry(SHARED)
+            },
+            ScreenshotMeta {
+                original_path: legacy_path.clone(),
+                thumbnail_path: legacy_path,
+                ..ScreenshotMeta::test_entry(LEGACY)
+            },
+        ];
+
+        let archive_path = root.join("backup.zip");
+        write_archive(&source, &entries, &archive_path).unwrap();
+
+        // The destination already has a different entry with the legacy id
+        let dest = root.join("dest");
+        fs::create_dir_all(dest.join(LEGACY)).unwrap();
+        let conn = history_db::open_in_memory().unwrap();
+        let other = ScreenshotMeta {
+            original_path: dest.join("x.png").to_string_lossy().to_string(),
+            ..ScreenshotMeta::test_entry(LEGACY)
+        };
+        history_db::upsert(&conn, &other).unwrap();
+
+        let result = import_archive(&conn, &dest, &archive_path).unwrap();
+        assert!(result.failed.is_empty(), "{:?}", result.failed);
+        assert_eq!(result.imported.len(), 2);
+        assert_eq!(result.renamed.len(), 1);
+        let RenamedEntry { new_id, .. } = &result.renamed[0];
+
+        let imported = history_db::find(&conn, SHARED).unwrap().unwrap();
+        assert!(imported.original_path.starts_with(&*dest.to_string_lossy()));
+        assert_eq!(fs::read(&imported.original_path).unwrap(), b"pixels");
+        assert_eq!(imported.tags, vec!["vpn"]);
+        assert!(imported.pinned);
+
+        // The legacy original moves into the blob store
+        let renamed = history_db::find(&conn, new_id).unwrap().unwrap();
+        assert!(blob_store::hash_of_path(Path::new(&renamed.original_path)).is_some());
+        assert_eq!(fs::read(&renamed.original_path).unwrap(), b"old pixels");
+        assert!(!dest.join(new_id).join("original.png").exists());
+
+        // Importing the same archive again adds nothing
+        let again = import_archive(&conn, &dest, &archive_path).unwrap();
+        assert!(again.imported.is_empty());
+        assert_eq!(again.skipped.len(), 2);
+
+        // Nor does a copy of the capture saved later under another id
+        let copy_id = uuid::Uuid::new_v4().to_string();
+        fs::create_dir_all(source.join(&copy_id)).unwrap();
+        let copy = ScreenshotMeta {
+            original_path: blob_path.clone(),
+            thumbnail_path: blob_path,
+            created_at: "2026-09-02T08:30:00+00:00".to_string(),
+            ..ScreenshotMeta::test_entry(&copy_id)
+        };
+        let copy_archive = root.join("copy.zip");
+        write_archive(&source, &[copy], &copy_archive).unwrap();
+        let copied = import_archive(&conn, &dest, &copy_archive).unwrap();
+        assert!(copied.imported.is_empty());
+        assert_eq!(copied.skipped, vec![copy_id]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn safe_relative_path_rejects_escapes() {
+        assert!(safe_relative_path("revisions/0001/annotations.json").is_ok());
+        assert!(safe_relative_path("../meta.json").is_err());
+        assert!(safe_relative_path("/etc/passwd").is_err());
+        assert!(safe_relative_path("").is_err());
+    }
+}
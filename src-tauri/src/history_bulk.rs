@@ -0,0 +1,462 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::history::{self, ScreenshotMeta};
+use crate::history_crypto;
+use crate::history_db;
+use crate::history_labels;
+use crate::history_search;
+use crate::history_trash::{self, TRASH_DIR_NAME};
+use crate::upload::{self, UploadRequest};
+
+/// Which entries a bulk operation applies to
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct BulkSelection {
+    /// Specific entries; takes precedence over `search`
+    pub ids: Option<Vec<String>>,
+    /// Gallery search syntax, e.g. `ticket:INC-42 after:2026-10-01`
+    pub search: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkUploadRequest {
+    pub service: String,
+    /// Ticket to attach every entry to; each entry's own ticket when absent
+    pub ticket_id: Option<String>,
+    pub comment: String,
+    pub base_url: String,
+    pub email: String,
+    pub api_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct BulkItemResult {
+    pub id: String,
+    pub success: bool,
+    pub error: Option<String>,
+    /// The exported file or the uploaded attachment URL
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct BulkResult {
+    pub succeeded: usize,
+    pub failed: usize,
+    /// One result per selected entry, in selection order
+    pub items: Vec<BulkItemResult>,
+}
+
+impl BulkResult {
+    fn push(&mut self, id: &str, outcome: Result<Option<String>, String>) {
+        let success = outcome.is_ok();
+        if success {
+            self.succeeded += 1;
+        } else {
+            self.failed += 1;
+        }
+        self.items.push(BulkItemResult {
+            id: id.to_string(),
+            success,
+            output: outcome.as_ref().ok().cloned().flatten(),
+            error: outcome.err(),
+        });
+    }
+}
+
+/// Move the selected entries to the trash
+#[tauri::command]
+pub async fn bulk_delete(selection: BulkSelection) -> Result<BulkResult, String> {
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    let ids = select(&conn, &selection)?;
+    trash_entries(&conn, &history_dir, &ids)
+}
+
+/// Add and remove tags on the selected entries, creating tags that do not
+/// exist yet
+#[tauri::command]
+pub async fn bulk_tag(
+    selection: BulkSelection,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<BulkResult, String> {
+    let normalize = |names: &[String]| {
+        names
+            .iter()
+            .map(|name| history_labels::normalize_label(name))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let (add, remove) = (normalize(&add)?, normalize(&remove)?);
+
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    let ids = select(&conn, &selection)?;
+    update_entries(&conn, &history_dir, &ids, |meta| {
+        history_labels::edit_labels(&mut meta.tags, &add, true);
+        history_labels::edit_labels(&mut meta.tags, &remove, false);
+    })
+}
+
+/// Set or, with an empty or absent `ticket_id`, clear the ticket of the
+/// selected entries
+#[tauri::command]
+pub async fn bulk_set_ticket(
+    selection: BulkSelection,
+    ticket_id: Option<String>,
+) -> Result<BulkResult, String> {
+    let ticket_id = ticket_id
+        .map(|ticket_id| ticket_id.trim().to_string())
+        .filter(|ticket_id| !ticket_id.is_empty());
+
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    let ids = select(&conn, &selection)?;
+    update_entries(&conn, &history_dir, &ids, |meta| {
+        meta.ticket_id = ticket_id.clone();
+    })
+}
+
+/// Write the annotated image of each selected entry, or its original when
+/// it has none, into `output_dir`
+#[tauri::command]
+pub async fn bulk_export(
+    selection: BulkSelection,
+    output_dir: String,
+) -> Result<BulkResult, String> {
+    let output_dir = PathBuf::from(output_dir);
+    fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create export directory: {}", e))?;
+    let conn = history_db::open(&history::get_history_dir()?)?;
+
+    let mut result = BulkResult::default();
+    for id in select(&conn, &selection)? {
+        let outcome = find(&conn, &id)
+            .and_then(|meta| export_entry(&meta, &output_dir))
+            .map(|path| Some(path.to_string_lossy().to_string()));
+        result.push(&id, outcome);
+    }
+
+    Ok(result)
+}
+
+/// Upload each selected entry's image to Jira or Zendesk. Every attempt is
+/// recorded on its entry, as for single uploads.
+#[tauri::command]
+pub async fn bulk_upload(
+    selection: BulkSelection,
+    request: BulkUploadRequest,
+) -> Result<BulkResult, String> {
+    let entries = {
+        let conn = history_db::open(&history::get_history_dir()?)?;
+        select(&conn, &selection)?
+            .into_iter()
+            .map(|id| {
+                let meta = find(&conn, &id);
+                (id, meta)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut result = BulkResult::default();
+    for (id, meta) in entries {
+        let outcome = match meta {
+            Ok(meta) => upload_entry(&meta, &request).await,
+            Err(e) => Err(e),
+        };
+        result.push(&id, outcome);
+    }
+
+    Ok(result)
+}
+
+/// Ids of the selected entries. Listed ids are returned as given, so that
+/// unknown ones are reported per item; a search returns what it matches.
+fn select(conn: &Connection, selection: &BulkSelection) -> Result<Vec<String>, String> {
+    let search = selection.search.as_deref().unwrap_or_default().trim();
+
+    match &selection.ids {
+        Some(ids) => Ok(ids.clone()),
+        // Applying an operation to all of history must be asked for explicitly
+        None if search.is_empty() => Err("Select entries by id or by search".to_string()),
+        None => Ok(history_search::search_all(conn, search)?
+            .into_iter()
+            .map(|meta| meta.id)
+            .collect()),
+    }
+}
+
+fn find(conn: &Connection, id: &str) -> Result<ScreenshotMeta, String> {
+    let id = history::parse_history_id(id)?.to_string();
+    history_db::find(conn, &id)?.ok_or_else(|| format!("History item not found: {}", id))
+}
+
+/// Apply `edit` to every entry in one index transaction, rewriting each
+/// entry's `meta.json` before its changes join the transaction, so that an
+/// entry whose file cannot be written keeps its old index row. Must be
+/// called while holding the history lock.
+fn update_entries(
+    conn: &Connection,
+    history_dir: &Path,
+    ids: &[String],
+    edit: impl Fn(&mut ScreenshotMeta),
+) -> Result<BulkResult, String> {
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| format!("Failed to start bulk update: {}", e))?;
+    let outcomes: Vec<Result<ScreenshotMeta, String>> = ids
+        .iter()
+        .map(|id| {
+            history_db::atomically(&tx, || {
+                let previous = find(&tx, id)?;
+                let mut meta = previous.clone();
+                edit(&mut meta);
+                history_db::upsert(&tx, &meta)?;
+                let meta = find(&tx, &meta.id)?;
+                let entry_dir = history_dir.join(&meta.id);
+                history_search::index_entry(&tx, &entry_dir, &meta)?;
+                history::write_meta(history_dir, &entry_dir, &meta)?;
+                Ok(previous)
+            })
+        })
+        .collect();
+
+    if let Err(e) = tx.commit() {
+        // Put back the files of entries whose index changes were lost
+        for previous in outcomes.iter().flatten() {
+            let _ = history::write_meta(history_dir, &history_dir.join(&previous.id), previous);
+        }
+        return Err(format!("Failed to save bulk update: {}", e));
+    }
+
+    let mut result = BulkResult::default();
+    for (id, outcome) in ids.iter().zip(outcomes) {
+        result.push(id, outcome.map(|_| None));
+    }
+
+    Ok(result)
+}
+
+/// Trash every entry in one index transaction, moving each entry's
+/// directory before its changes join the transaction, so that an entry
+/// whose directory cannot be moved stays in the gallery. Must be called
+/// while holding the history lock.
+fn trash_entries(
+    conn: &Connection,
+    history_dir: &Path,
+    ids: &[String],
+) -> Result<BulkResult, String> {
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| format!("Failed to start bulk delete: {}", e))?;
+    let outcomes: Vec<Result<String, String>> = ids
+        .iter()
+        .map(|id| {
+            history_db::atomically(&tx, || {
+                let id = find(&tx, id)?.id;
+                history_trash::trash_in_index(&tx, &id)?;
+                history_trash::trash_files(history_dir, &id)?;
+                Ok(id)
+            })
+        })
+        .collect();
+
+    if let Err(e) = tx.commit() {
+        // Bring back the directories of entries the index still lists
+        for id in outcomes.iter().flatten() {
+            let trashed = history_dir.join(TRASH_DIR_NAME).join(id);
+            if trashed.is_dir() {
+                let _ = fs::rename(&trashed, history_dir.join(id));
+            }
+        }
+        return Err(format!("Failed to save bulk delete: {}", e));
+    }
+
+    let mut result = BulkResult::default();
+    for (id, outcome) in ids.iter().zip(outcomes) {
+        result.push(id, outcome.map(|_| None));
+    }
+
+    Ok(result)
+}
+
+fn export_entry(meta: &ScreenshotMeta, output_dir: &Path) -> Result<PathBuf, String> {
+    let source = meta.annotated_path.as_ref().unwrap_or(&meta.original_path);
+    let contents = history_crypto::read(Path::new(source))
+        .map_err(|e| format!("Failed to read image: {}", e))?;
+
+    // The ticket makes exports easy to sort; the id keeps names unique
+    let ticket: Option<String> = meta.ticket_id.as_ref().map(|ticket| {
+        ticket
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    });
+    let name = match ticket {
+        Some(ticket) => format!("{}_{}.png", ticket, meta.id),
+        None => format!("{}.png", meta.id),
+    };
+
+    let path = output_dir.join(name);
+    fs::write(&path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+async fn upload_entry(
+    meta: &ScreenshotMeta,
+    request: &BulkUploadRequest,
+) -> Result<Option<String>, String> {
+    let ticket_id = request
+        .ticket_id
+        .clone()
+        .or_else(|| meta.ticket_id.clone())
+        .ok_or_else(|| "Entry has no ticket to upload to".to_string())?;
+
+    let uploaded = upload::upload_screenshot(UploadRequest {
+        service: request.service.clone(),
+        ticket_id,
+        file_path: meta
+            .annotated_path
+            .clone()
+            .unwrap_or_else(|| meta.original_path.clone()),
+        comment: request.comment.clone(),
+        base_url: request.base_url.clone(),
+        email: request.email.clone(),
+        api_token: request.api_token.clone(),
+        history_id: meta.id.clone(),
+    })
+    .await?;
+
+    Ok(Some(uploaded.attachment_url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{select, trash_entries, update_entries, BulkSelection};
+    use crate::history::{self, ScreenshotMeta};
+    use crate::history_db;
+    use crate::history_trash::TRASH_DIR_NAME;
+    use std::fs;
+
+    const FIRST: &str = "11111111-1111-4111-8111-111111111111";
+    const SECOND: &str = "22222222-2222-4222-8222-222222222222";
+    const MISSING: &str = "33333333-3333-4333-8333-333333333333";
+
+    #[test]
+    fn bulk_updates_report_each_item_and_apply_in_one_pass() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let conn = history_db::open_in_memory().unwrap();
+        for id in [FIRST, SECOND] {
+            fs::create_dir_all(dir.join(id)).unwrap();
+            fs::write(dir.join(id).join("original.png"), id).unwrap();
+            let entry = ScreenshotMeta {
+                tags: vec!["outage".to_string()],
+                ..ScreenshotMeta::test_entry(id)
+            };
+            history_db::upsert(&conn, &entry).unwrap();
+        }
+        assert!(select(&conn, &BulkSelection::default()).is_err());
+
+        let ids = vec![FIRST.to_string(), MISSING.to_string(), SECOND.to_string()];
+        let result = update_entries(&conn, &dir, &ids, |meta| {
+            meta.ticket_id = Some("INC-7".to_string());
+            meta.tags.clear();
+        })
+        .unwrap();
+        assert_eq!((result.succeeded, result.failed), (2, 1));
+        assert!(!result.items[1].success);
+        assert!(result.items[1].error.as_ref().unwrap().contains(MISSING));
+
+        let updated = history_db::find(&conn, SECOND).unwrap().unwrap();
+        assert_eq!(updated.ticket_id.as_deref(), Some("INC-7"));
+        assert!(updated.tags.is_empty());
+        let on_disk = history::read_meta(&dir, &dir.join(SECOND)).unwrap();
+        assert_eq!(on_disk.ticket_id.as_deref(), Some("INC-7"));
+
+        let result = trash_entries(&conn, &dir, &ids).unwrap();
+        assert_eq!((result.succeeded, result.failed), (2, 1));
+        assert!(history_db::list_oldest_first(&conn).unwrap().is_empty());
+        assert!(!dir.join(FIRST).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_item_leaves_no_partial_writes() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let conn = history_db::open_in_memory().unwrap();
+        for id in [FIRST, SECOND] {
+            fs::create_dir_all(dir.join(id)).unwrap();
+            history_db::upsert(&conn, &ScreenshotMeta::test_entry(id)).unwrap();
+        }
+        // Fails the first entry after its trash row has been written
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER keep_first BEFORE DELETE ON screenshots WHEN OLD.id = '{}' BEGIN
+                 SELECT RAISE(ABORT, 'kept');
+             END;",
+            FIRST
+        ))
+        .unwrap();
+
+        let ids = vec![FIRST.to_string(), SECOND.to_string()];
+        let result = trash_entries(&conn, &dir, &ids).unwrap();
+        assert_eq!((result.succeeded, result.failed), (1, 1));
+        assert!(result.items[0].error.as_ref().unwrap().contains("kept"));
+
+        let trashed: Vec<String> = conn
+            .prepare("SELECT id FROM trash")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(trashed, vec![SECOND]);
+        assert!(history_db::find(&conn, FIRST).unwrap().is_some());
+        assert!(dir.join(FIRST).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_file_write_keeps_the_index_row() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let conn = history_db::open_in_memory().unwrap();
+        for id in [FIRST, SECOND] {
+            history_db::upsert(&conn, &ScreenshotMeta::test_entry(id)).unwrap();
+        }
+        // Only the first entry has a directory to write meta.json into
+        fs::create_dir_all(dir.join(FIRST)).unwrap();
+
+        let ids = vec![FIRST.to_string(), SECOND.to_string()];
+        let result = update_entries(&conn, &dir, &ids, |meta| {
+            meta.ticket_id = Some("INC-9".to_string());
+        })
+        .unwrap();
+        assert_eq!((result.succeeded, result.failed), (1, 1));
+        assert!(result.items[1].error.as_ref().unwrap().contains("metadata"));
+        let kept = history_db::find(&conn, SECOND).unwrap().unwrap();
+        assert_eq!(kept.ticket_id, None);
+
+        // A file where the trash directory belongs stops every move
+        fs::write(dir.join(TRASH_DIR_NAME), b"").unwrap();
+        let result = trash_entries(&conn, &dir, &ids[..1]).unwrap();
+        assert_eq!((result.succeeded, result.failed), (0, 1));
+        assert!(history_db::find(&conn, FIRST).unwrap().is_some());
+        assert!(dir.join(FIRST).is_dir());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
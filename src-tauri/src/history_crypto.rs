@@ -0,0 +1,444 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::credentials;
+use crate::fs_util;
+use crate::history;
+use crate::history_db;
+
+/// Keychain entry holding the history encryption key; the credential
+/// commands refuse this name
+pub(crate) const KEY_CREDENTIAL: &str = "history_encryption_key";
+/// Prefix of every encrypted file, followed by the nonce and ciphertext
+const MAGIC: &[u8] = b"SAENC\x01";
+const NONCE_LEN: usize = 12;
+
+pub(crate) type HistoryKey = [u8; 32];
+
+/// The key is read from the keychain once per run
+static KEY_CACHE: Mutex<Option<HistoryKey>> = Mutex::new(None);
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct EncryptionStatus {
+    pub enabled: bool,
+    /// Files not yet converted to the current mode, e.g. after an
+    /// interrupted migration. Running `set_history_encryption` again
+    /// finishes the job.
+    pub pending_files: usize,
+}
+
+#[tauri::command]
+pub async fn get_history_encryption() -> Result<EncryptionStatus, String> {
+    status(&history::get_history_dir()?)
+}
+
+/// Turn encrypted history on or off, converting the index and every stored
+/// file. Safe to re-run after an interruption.
+#[tauri::command]
+pub async fn set_history_encryption(enabled: bool) -> Result<EncryptionStatus, String> {
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+
+    if enabled {
+        let key = create_key()?;
+        let current = index_key(&history_dir)?;
+        history_db::convert_database(&history_dir, current.as_ref(), Some(&key))?;
+        convert_files(&history_dir, &key, true)?;
+    } else {
+        if let Some(key) = index_key(&history_dir)? {
+            history_db::convert_database(&history_dir, Some(&key), None)?;
+        }
+        if let Some(key) = load_key()? {
+            convert_files(&history_dir, &key, false)?;
+            credentials::remove_credential(KEY_CREDENTIAL)?;
+            *KEY_CACHE
+                .lock()
+                .map_err(|_| "History key cache is poisoned".to_string())? = None;
+        }
+    }
+
+    // Every file changed size by the encryption overhead
+    let conn = history_db::open(&history_dir)?;
+    history::rescan_usage(&conn, &history_dir)?;
+
+    status(&history_dir)
+}
+
+fn status(history_dir: &Path) -> Result<EncryptionStatus, String> {
+    let enabled = is_enabled(history_dir);
+    let mut pending_files = 0;
+    for path in data_files(history_dir)? {
+        if is_sealed_file(&path)? != enabled {
+            pending_files += 1;
+        }
+    }
+
+    Ok(EncryptionStatus {
+        enabled,
+        pending_files,
+    })
+}
+
+/// Whether new history files are written encrypted
+pub(crate) fn is_enabled(history_dir: &Path) -> bool {
+    history_db::is_encrypted(history_dir)
+}
+
+/// The key for the history index, or `None` when history is not encrypted
+pub(crate) fn index_key(history_dir: &Path) -> Result<Option<HistoryKey>, String> {
+    if !is_enabled(history_dir) {
+        return Ok(None);
+    }
+    load_key()?
+        .map(Some)
+        .ok_or_else(|| "History is encrypted but its key is missing from the keychain".to_string())
+}
+
+fn load_key() -> Result<Option<HistoryKey>, String> {
+    let mut cache = KEY_CACHE
+        .lock()
+        .map_err(|_| "History key cache is poisoned".to_string())?;
+    if let Some(key) = *cache {
+        return Ok(Some(key));
+    }
+
+    let Some(encoded) = credentials::read_credential(KEY_CREDENTIAL)? else {
+        return Ok(None);
+    };
+    let key: HistoryKey = BASE64
+        .decode(encoded)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| "History encryption key in the keychain is invalid".to_string())?;
+
+    *cache = Some(key);
+    Ok(Some(key))
+}
+
+/// The existing key, or a new one saved to the keychain
+fn create_key() -> Result<HistoryKey, String> {
+    if let Some(key) = load_key()? {
+        return Ok(key);
+    }
+
+    let key: HistoryKey = Aes256Gcm::generate_key(OsRng).into();
+    credentials::write_credential(KEY_CREDENTIAL, &BASE64.encode(key))?;
+    *KEY_CACHE
+        .lock()
+        .map_err(|_| "History key cache is poisoned".to_string())? = Some(key);
+
+    Ok(key)
+}
+
+fn seal(key: &HistoryKey, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let cipher = Aes256Gcm::new(key.into());
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| "Failed to encrypt history file".to_string())?;
+
+    let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(MAGIC);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+fn is_sealed(contents: &[u8]) -> bool {
+    contents.starts_with(MAGIC)
+}
+
+fn open_sealed(key: &HistoryKey, sealed: &[u8]) -> Result<Vec<u8>, String> {
+    let body = &sealed[MAGIC.len()..];
+    if body.len() < NONCE_LEN {
+        return Err("Encrypted history file is truncated".to_string());
+    }
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+
+    Aes256Gcm::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Failed to decrypt history file: wrong key or corrupt data".to_string())
+}
+
+fn is_sealed_file(path: &Path) -> Result<bool, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut header = Vec::with_capacity(MAGIC.len());
+    file.take(MAGIC.len() as u64)
+        .read_to_end(&mut header)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    Ok(is_sealed(&header))
+}
+
+/// Read a file, decrypting it if it was stored encrypted. Works on any path,
+/// so callers handed history paths by the frontend need no special casing.
+pub(crate) fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let contents = fs::read(path)?;
+    if !is_sealed(&contents) {
+        return Ok(contents);
+    }
+
+    let key = load_key()
+        .map_err(io::Error::other)?
+        .ok_or_else(|| io::Error::other("History encryption key is missing from the keychain"))?;
+    open_sealed(&key, &contents).map_err(io::Error::other)
+}
+
+pub(crate) fn read_to_string(path: &Path) -> io::Result<String> {
+    String::from_utf8(read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// `image::open` for files that may be encrypted
+pub(crate) fn open_image(path: &Path) -> image::ImageResult<image::DynamicImage> {
+    image::load_from_memory(&read(path)?)
+}
+
+/// `image::image_dimensions` for files that may be encrypted
+pub(crate) fn image_dimensions(path: &Path) -> image::ImageResult<(u32, u32)> {
+    image::ImageReader::new(io::Cursor::new(read(path)?))
+        .with_guessed_format()?
+        .into_dimensions()
+}
+
+/// `fs_util::write_atomic` for files inside `history_dir`, encrypting them
+/// when encrypted history is on
+pub(crate) fn write_atomic(history_dir: &Path, path: &Path, contents: &[u8]) -> io::Result<()> {
+    match index_key(history_dir).map_err(io::Error::other)? {
+        Some(key) => fs_util::write_atomic(path, &seal(&key, contents).map_err(io::Error::other)?),
+        None => fs_util::write_atomic(path, contents),
+    }
+}
+
+/// `fs_util::copy_synced` into `history_dir`, encrypting the copy when
+/// encrypted history is on
+pub(crate) fn copy_synced(history_dir: &Path, from: &Path, to: &Path) -> io::Result<u64> {
+    let Some(key) = index_key(history_dir).map_err(io::Error::other)? else {
+        return fs_util::copy_synced(from, to);
+    };
+
+    let sealed = seal(&key, &read(from)?).map_err(io::Error::other)?;
+    let mut file = File::create(to)?;
+    file.write_all(&sealed)?;
+    file.sync_all()?;
+    Ok(sealed.len() as u64)
+}
+
+/// Every stored file that holds screenshot data or metadata. The index is
+/// converted separately; locks and temp files are skipped.
+fn data_files(history_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    fn walk(dir: &Path, top_level: bool, files: &mut Vec<PathBuf>) -> Result<(), String> {
+        for entry in fs::read_dir(dir).map_err(|e| e.to_string())?.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_dir = path.is_dir();
+            let skip = if is_dir {
+                top_level && name == history::STAGING_DIR_NAME
+            } else {
+                name.starts_with('.') || (top_level && history_db::is_database_file(&name))
+            };
+
+            if skip {
+                continue;
+            } else if is_dir {
+                walk(&path, false, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    if history_dir.is_dir() {
+        walk(history_dir, true, &mut files)?;
+    }
+    Ok(files)
+}
+
+/// Whether `key` decrypts the stored files, checked against the first
+/// encrypted one found; `None` when no file is stored encrypted
+pub(crate) fn key_opens_files(
+    history_dir: &Path,
+    key: &HistoryKey,
+) -> Result<Option<bool>, String> {
+    for path in data_files(history_dir)? {
+        let contents =
+            fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if is_sealed(&contents) {
+            return Ok(Some(open_sealed(key, &contents).is_ok()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Encrypt (or decrypt) every stored file that is not already in the target
+/// state, returning how many were converted
+fn convert_files(history_dir: &Path, key: &HistoryKey, encrypt: bool) -> Result<usize, String> {
+    let mut converted = 0;
+    for path in data_files(history_dir)? {
+        let contents =
+            fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if is_sealed(&contents) == encrypt {
+            continue;
+        }
+
+        let converted_contents = if encrypt {
+            seal(key, &contents)?
+        } else {
+            open_sealed(key, &contents)?
+        };
+        fs_util::write_atomic(&path, &converted_contents)
+            .map_err(|e| format!("Failed to rewrite {}: {}", path.display(), e))?;
+        converted += 1;
+    }
+
+    Ok(converted)
+}
+
+/// Resolve a `history://` request path to a file inside `history_dir`
+fn resolve_protocol_path(history_dir: &Path, uri_path: &str) -> Result<PathBuf, String> {
+    let decoded = percent_decode_str(uri_path.trim_start_matches('/'))
+        .decode_utf8()
+        .map_err(|_| "Invalid history path".to_string())?;
+    let history_dir = history_dir
+        .canonicalize()
+        .map_err(|e| format!("Failed to resolve history directory: {}", e))?;
+    let path = Path::new(decoded.as_ref())
+        .canonicalize()
+        .map_err(|_| "History file not found".to_string())?;
+
+    if !path.starts_with(&history_dir) {
+        return Err("Path is outside the history directory".to_string());
+    }
+    Ok(path)
+}
+
+/// The URL the webview loads a history file from, as
+/// `convertFileSrc(path, 'history')` builds it in the frontend
+pub(crate) fn protocol_url(path: &Path) -> String {
+    let encoded = utf8_percent_encode(&path.to_string_lossy(), NON_ALPHANUMERIC).to_string();
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://history.localhost/{}", encoded)
+    } else {
+        format!("history://localhost/{}", encoded)
+    }
+}
+
+/// Serve a history image for the `history://` protocol, decrypting it if
+/// needed. The frontend uses `convertFileSrc(path, 'history')` in place of
+/// the asset protocol, which cannot read encrypted files.
+pub(crate) fn read_for_protocol(uri_path: &str) -> Result<Vec<u8>, String> {
+    let path = resolve_protocol_path(&history::get_history_dir()?, uri_path)?;
+    read(&path).map_err(|e| format!("Failed to read history file: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        convert_files, is_sealed_file, open_sealed, protocol_url, resolve_protocol_path, seal,
+        HistoryKey,
+    };
+    use crate::history_db;
+    use std::fs;
+
+    const KEY: HistoryKey = [7; 32];
+
+    #[test]
+    fn sealed_data_round_trips_and_rejects_the_wrong_key() {
+        let sealed = seal(&KEY, b"customer screenshot").unwrap();
+        assert_ne!(&sealed[..], b"customer screenshot");
+        assert_eq!(open_sealed(&KEY, &sealed).unwrap(), b"customer screenshot");
+        assert!(open_sealed(&[8; 32], &sealed).is_err());
+    }
+
+    #[test]
+    fn convert_files_encrypts_entries_and_blobs_but_not_the_index() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let entry_dir = dir.join("4f0e4c1e-0000-4000-8000-000000000001");
+        let blob_dir = dir.join(".blobs").join("ab");
+        fs::create_dir_all(&entry_dir).unwrap();
+        fs::create_dir_all(&blob_dir).unwrap();
+        fs::write(entry_dir.join("meta.json"), b"{}").unwrap();
+        fs::write(blob_dir.join("ab.png"), b"pixels").unwrap();
+        fs::write(dir.join("history.db"), b"index").unwrap();
+        fs::write(dir.join(".history.lock"), b"").unwrap();
+
+        assert_eq!(convert_files(&dir, &KEY, true).unwrap(), 2);
+        assert!(is_sealed_file(&entry_dir.join("meta.json")).unwrap());
+        assert!(is_sealed_file(&blob_dir.join("ab.png")).unwrap());
+        assert_eq!(fs::read(dir.join("history.db")).unwrap(), b"index");
+
+        // Re-running after an interruption only touches what is left
+        assert_eq!(convert_files(&dir, &KEY, true).unwrap(), 0);
+
+        assert_eq!(convert_files(&dir, &KEY, false).unwrap(), 2);
+        assert_eq!(fs::read(blob_dir.join("ab.png")).unwrap(), b"pixels");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_wrong_key_is_not_mistaken_for_a_corrupt_index() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let entry_dir = dir.join("4f0e4c1e-0000-4000-8000-000000000001");
+        fs::create_dir_all(&entry_dir).unwrap();
+        drop(history_db::open_with_key(&dir, Some(&KEY)).unwrap());
+
+        // Nothing encrypted to check the key against yet
+        let error = history_db::corruption(&dir, Some(&[8; 32])).unwrap_err();
+        assert!(error.contains("does not match"));
+
+        fs::write(entry_dir.join("meta.json"), seal(&KEY, b"{}").unwrap()).unwrap();
+        let error = history_db::corruption(&dir, Some(&[8; 32])).unwrap_err();
+        assert!(error.contains("does not match"));
+        assert_eq!(history_db::corruption(&dir, Some(&KEY)).unwrap(), None);
+
+        // With the right key, an unreadable file really is damaged
+        fs::write(dir.join("history.enc.db"), vec![7u8; 8192]).unwrap();
+        assert!(history_db::corruption(&dir, Some(&KEY)).unwrap().is_some());
+        assert!(history_db::corruption(&dir, Some(&[8; 32])).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn protocol_paths_must_stay_inside_history() {
+        let root = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let history_dir = root.join("history");
+        fs::create_dir_all(&history_dir).unwrap();
+        fs::write(history_dir.join("thumb.png"), b"thumb").unwrap();
+        fs::write(root.join("secret.txt"), b"secret").unwrap();
+
+        let inside = history_dir
+            .join("thumb.png")
+            .to_string_lossy()
+            .replace('/', "%2F");
+        assert!(resolve_protocol_path(&history_dir, &format!("/{}", inside)).is_ok());
+
+        // URLs handed to the frontend resolve back to the same file
+        let thumb = history_dir.join("thumb.png");
+        let url = protocol_url(&thumb);
+        let uri_path = &url[url.find("localhost/").unwrap() + "localhost".len()..];
+        assert_eq!(
+            resolve_protocol_path(&history_dir, uri_path).unwrap(),
+            thumb.canonicalize().unwrap()
+        );
+
+        let escape = history_dir
+            .join("..")
+            .join("secret.txt")
+            .to_string_lossy()
+            .replace('/', "%2F");
+        assert!(resolve_protocol_path(&history_dir, &format!("/{}", escape)).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,1302 @@
+use rusqlite::{
+    params, Connection, DatabaseName, ErrorCode, OpenFlags, OptionalExtension, Row, ToSql,
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::blob_store;
+use crate::fs_util::sync_dir;
+use crate::history::{self, ScreenshotMeta, UploadRecord};
+use crate::history_crypto::{self, HistoryKey};
+use crate::history_schema;
+use crate::history_search::{self, SearchDocument};
+
+const DB_FILE_NAME: &str = "history.db";
+/// Used instead of `DB_FILE_NAME` when history is encrypted, so the file
+/// that exists also records which mode is active
+const ENCRYPTED_DB_FILE_NAME: &str = "history.enc.db";
+const LEGACY_INDEX_FILE_NAME: &str = "index.json";
+const LEGACY_INDEX_MIGRATED_FILE_NAME: &str = "index.json.migrated";
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// First bytes of every unencrypted SQLite database; SQLCipher encrypts them
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// One schema change: SQL to run, or a rewrite of stored values that needs
+/// Rust, such as path handling
+enum Migration {
+    Sql(&'static str),
+    Rust(fn(&Connection) -> Result<(), String>),
+}
+
+impl Migration {
+    fn apply(&self, conn: &Connection) -> Result<(), String> {
+        match self {
+            Migration::Sql(sql) => conn.execute_batch(sql).map_err(|e| e.to_string()),
+            Migration::Rust(rewrite) => rewrite(conn),
+        }
+    }
+}
+
+/// Schema migrations, applied in order. `PRAGMA user_version` records how
+/// many have run, so new migrations must only ever be appended.
+const MIGRATIONS: &[Migration] = &[
+    // 1: initial screenshot metadata table
+    Migration::Sql(
+        "CREATE TABLE screenshots (
+        id TEXT PRIMARY KEY NOT NULL,
+        original_path TEXT NOT NULL,
+        annotated_path TEXT,
+        thumbnail_path TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        ticket_id TEXT,
+        uploaded_url TEXT,
+        size_bytes INTEGER NOT NULL DEFAULT 0,
+        annotation_count INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX idx_screenshots_created_at ON screenshots(created_at);
+    CREATE INDEX idx_screenshots_ticket_id ON screenshots(ticket_id);",
+    ),
+    // 2: full-text search index and redaction counts for `has:redaction`
+    Migration::Sql(
+        "ALTER TABLE screenshots ADD COLUMN redaction_count INTEGER NOT NULL DEFAULT 0;
+    CREATE VIRTUAL TABLE screenshot_search USING fts5(
+        id UNINDEXED,
+        ticket_id,
+        annotation_text,
+        ocr_text,
+        notes,
+        tags,
+        comments,
+        tokenize = 'unicode61',
+        prefix = '2 3'
+    );",
+    ),
+    // 3: user-defined tags and named collections
+    Migration::Sql(
+        "CREATE TABLE tags (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE COLLATE NOCASE
+    );
+    CREATE TABLE screenshot_tags (
+        screenshot_id TEXT NOT NULL REFERENCES screenshots(id) ON DELETE CASCADE,
+        tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+        PRIMARY KEY (screenshot_id, tag_id)
+    );
+    CREATE INDEX idx_screenshot_tags_tag_id ON screenshot_tags(tag_id);
+    CREATE TABLE collections (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+        created_at TEXT NOT NULL
+    );
+    CREATE TABLE collection_items (
+        collection_id INTEGER NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+        screenshot_id TEXT NOT NULL REFERENCES screenshots(id) ON DELETE CASCADE,
+        PRIMARY KEY (collection_id, screenshot_id)
+    );
+    CREATE INDEX idx_collection_items_screenshot_id ON collection_items(screenshot_id);",
+    ),
+    // 4: upload attempts per entry
+    Migration::Sql(
+        "CREATE TABLE uploads (
+        id INTEGER PRIMARY KEY,
+        screenshot_id TEXT NOT NULL REFERENCES screenshots(id) ON DELETE CASCADE,
+        service TEXT NOT NULL,
+        ticket_id TEXT NOT NULL,
+        ticket_url TEXT,
+        attachment_url TEXT,
+        comment TEXT,
+        uploaded_at TEXT NOT NULL,
+        success INTEGER NOT NULL,
+        error TEXT
+    );
+    CREATE INDEX idx_uploads_screenshot_id ON uploads(screenshot_id);",
+    ),
+    // 5: pinned entries and app settings such as the retention policy
+    Migration::Sql(
+        "ALTER TABLE screenshots ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE settings (
+        key TEXT PRIMARY KEY NOT NULL,
+        value TEXT NOT NULL
+    );",
+    ),
+    // 6: content-addressed image blobs shared between entries
+    Migration::Sql(
+        "CREATE TABLE blobs (
+        hash TEXT PRIMARY KEY NOT NULL,
+        size_bytes INTEGER NOT NULL
+    );
+    CREATE TABLE blob_refs (
+        screenshot_id TEXT NOT NULL REFERENCES screenshots(id) ON DELETE CASCADE,
+        role TEXT NOT NULL,
+        hash TEXT NOT NULL REFERENCES blobs(hash),
+        PRIMARY KEY (screenshot_id, role)
+    );
+    CREATE INDEX idx_blob_refs_hash ON blob_refs(hash);",
+    ),
+    // 7: trash for deleted entries and a log of what eviction removed
+    Migration::Sql(
+        "CREATE TABLE trash (
+        id TEXT PRIMARY KEY NOT NULL,
+        meta_json TEXT NOT NULL,
+        trashed_at TEXT NOT NULL
+    );
+    CREATE TABLE trash_blob_refs (
+        screenshot_id TEXT NOT NULL REFERENCES trash(id) ON DELETE CASCADE,
+        role TEXT NOT NULL,
+        hash TEXT NOT NULL REFERENCES blobs(hash),
+        PRIMARY KEY (screenshot_id, role)
+    );
+    CREATE INDEX idx_trash_blob_refs_hash ON trash_blob_refs(hash);
+    CREATE TABLE eviction_log (
+        id INTEGER PRIMARY KEY,
+        screenshot_id TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        ticket_id TEXT,
+        size_bytes INTEGER NOT NULL,
+        reason TEXT NOT NULL,
+        from_trash INTEGER NOT NULL,
+        evicted_at TEXT NOT NULL
+    );",
+    ),
+    // 8: image paths relative to the history directory so it can be moved,
+    // cut at the blob store or the entry's own directory
+    Migration::Rust(make_paths_portable),
+    // 9: a running storage total kept by triggers, from the bytes each live
+    // or trashed entry holds in its own directory plus each blob once
+    Migration::Sql(
+        "ALTER TABLE screenshots ADD COLUMN local_bytes INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE trash ADD COLUMN local_bytes INTEGER NOT NULL DEFAULT 0;
+    UPDATE screenshots SET local_bytes = MAX(0, size_bytes - COALESCE((
+        SELECT SUM(b.size_bytes) FROM blob_refs r JOIN blobs b ON b.hash = r.hash
+        WHERE r.screenshot_id = screenshots.id), 0));
+    UPDATE trash SET local_bytes = MAX(0, COALESCE(json_extract(meta_json, '$.size_bytes'), 0)
+        - COALESCE((SELECT SUM(b.size_bytes) FROM trash_blob_refs r JOIN blobs b ON b.hash = r.hash
+        WHERE r.screenshot_id = trash.id), 0));
+    CREATE TABLE storage_usage (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        used_bytes INTEGER NOT NULL,
+        item_count INTEGER NOT NULL
+    );
+    INSERT INTO storage_usage (id, used_bytes, item_count) VALUES (1,
+        (SELECT COALESCE(SUM(local_bytes), 0) FROM screenshots)
+            + (SELECT COALESCE(SUM(local_bytes), 0) FROM trash)
+            + (SELECT COALESCE(SUM(size_bytes), 0) FROM blobs),
+        (SELECT COUNT(*) FROM screenshots));
+    CREATE TRIGGER screenshots_usage_insert AFTER INSERT ON screenshots BEGIN
+        UPDATE storage_usage SET used_bytes = used_bytes + NEW.local_bytes,
+            item_count = item_count + 1;
+    END;
+    CREATE TRIGGER screenshots_usage_update AFTER UPDATE OF local_bytes ON screenshots BEGIN
+        UPDATE storage_usage SET used_bytes = used_bytes - OLD.local_bytes + NEW.local_bytes;
+    END;
+    CREATE TRIGGER screenshots_usage_delete AFTER DELETE ON screenshots BEGIN
+        UPDATE storage_usage SET used_bytes = used_bytes - OLD.local_bytes,
+            item_count = item_count - 1;
+    END;
+    CREATE TRIGGER trash_usage_insert AFTER INSERT ON trash BEGIN
+        UPDATE storage_usage SET used_bytes = used_bytes + NEW.local_bytes;
+    END;
+    CREATE TRIGGER trash_usage_update AFTER UPDATE OF local_bytes ON trash BEGIN
+        UPDATE storage_usage SET used_bytes = used_bytes - OLD.local_bytes + NEW.local_bytes;
+    END;
+    CREATE TRIGGER trash_usage_delete AFTER DELETE ON trash BEGIN
+        UPDATE storage_usage SET used_bytes = used_bytes - OLD.local_bytes;
+    END;
+    CREATE TRIGGER blobs_usage_insert AFTER INSERT ON blobs BEGIN
+        UPDATE storage_usage SET used_bytes = used_bytes + NEW.size_bytes;
+    END;
+    CREATE TRIGGER blobs_usage_update AFTER UPDATE OF size_bytes ON blobs BEGIN
+        UPDATE storage_usage SET used_bytes = used_bytes - OLD.size_bytes + NEW.size_bytes;
+    END;
+    CREATE TRIGGER blobs_usage_delete AFTER DELETE ON blobs BEGIN
+        UPDATE storage_usage SET used_bytes = used_bytes - OLD.size_bytes;
+    END;",
+    ),
+    // 10: perceptual hashes of originals for finding similar screenshots,
+    // NULL when the original could not be decoded
+    Migration::Sql(
+        "CREATE TABLE image_hashes (
+        screenshot_id TEXT PRIMARY KEY NOT NULL REFERENCES screenshots(id) ON DELETE CASCADE,
+        phash INTEGER
+    );",
+    ),
+    // 11: free-form notes and custom field values per entry
+    Migration::Sql(
+        "ALTER TABLE screenshots ADD COLUMN notes TEXT;
+    CREATE TABLE screenshot_fields (
+        screenshot_id TEXT NOT NULL REFERENCES screenshots(id) ON DELETE CASCADE,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (screenshot_id, key)
+    );
+    CREATE INDEX idx_screenshot_fields_key ON screenshot_fields(key, value);",
+    ),
+];
+
+/// Schema version that introduced `screenshot_search`; databases upgraded
+/// past it need their existing entries indexed once
+const SEARCH_INDEX_VERSION: usize = 2;
+
+const INSERT_COLUMNS: &str = "id, original_path, annotated_path, thumbnail_path, created_at, \
+     ticket_id, uploaded_url, size_bytes, annotation_count, redaction_count, pinned, notes";
+const INSERT_PLACEHOLDERS: &str = "?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12";
+const UPDATE_ASSIGNMENTS: &str = "original_path = excluded.original_path, \
+     annotated_path = excluded.annotated_path, thumbnail_path = excluded.thumbnail_path, \
+     created_at = excluded.created_at, ticket_id = excluded.ticket_id, \
+     uploaded_url = excluded.uploaded_url, size_bytes = excluded.size_bytes, \
+     annotation_count = excluded.annotation_count, redaction_count = excluded.redaction_count, \
+     pinned = excluded.pinned, notes = excluded.notes";
+
+/// Separates names in the `group_concat` label columns below
+const LABEL_SEPARATOR: char = '\u{1f}';
+
+/// Columns for `row_to_meta`, qualified so queries can join other tables
+pub(crate) const SELECT_COLUMNS: &str =
+    "s.id, s.original_path, s.annotated_path, s.thumbnail_path, s.created_at, \
+     s.ticket_id, s.uploaded_url, s.size_bytes, s.annotation_count, s.redaction_count, s.pinned, \
+     (SELECT group_concat(t.name, char(31)) FROM screenshot_tags st \
+      JOIN tags t ON t.id = st.tag_id WHERE st.screenshot_id = s.id), \
+     (SELECT group_concat(c.name, char(31)) FROM collection_items ci \
+      JOIN collections c ON c.id = ci.collection_id WHERE ci.screenshot_id = s.id), \
+     (SELECT json_group_array(json_object('service', u.service, 'ticket_id', u.ticket_id, \
+      'ticket_url', u.ticket_url, 'attachment_url', u.attachment_url, 'comment', u.comment, \
+      'uploaded_at', u.uploaded_at, 'success', json(CASE WHEN u.success THEN 'true' ELSE 'false' END), \
+      'error', u.error)) FROM (SELECT * FROM uploads WHERE screenshot_id = s.id ORDER BY id) u), \
+     s.notes, \
+     (SELECT json_group_object(f.key, f.value) FROM screenshot_fields f WHERE f.screenshot_id = s.id)";
+
+/// Open the history database inside `history_dir`, applying pending
+/// migrations and importing a legacy `index.json` if one is still present.
+pub(crate) fn open(history_dir: &Path) -> Result<Connection, String> {
+    let key = history_crypto::index_key(history_dir)?;
+    open_with_key(history_dir, key.as_ref())
+}
+
+/// `open` with an explicit key, for callers that resolved it already
+pub(crate) fn open_with_key(
+    history_dir: &Path,
+    key: Option<&HistoryKey>,
+) -> Result<Connection, String> {
+    let mut conn = Connection::open(history_dir.join(database_file_name(key.is_some())))
+        .map_err(|e| format!("Failed to open history database: {}", e))?;
+
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", key_pragma(key))
+            .map_err(|e| format!("Failed to unlock history database: {}", e))?;
+    }
+
+    conn.busy_timeout(BUSY_TIMEOUT)
+        .map_err(|e| format!("Failed to configure history database: {}", e))?;
+    conn.pragma_update(None, "journal_mode", "WAL")
+        .map_err(|e| format!("Failed to configure history database: {}", e))?;
+    conn.pragma_update(None, "foreign_keys", "ON")
+        .map_err(|e| format!("Failed to configure history database: {}", e))?;
+
+    let previous_version = migrate(&mut conn)?;
+
+    let imported = if history_dir.join(LEGACY_INDEX_FILE_NAME).exists() {
+        import_legacy_index(&mut conn, history_dir)?
+    } else {
+        0
+    };
+
+    if previous_version < SEARCH_INDEX_VERSION || imported > 0 {
+        history_search::reindex_all(&conn, history_dir)?;
+    }
+
+    Ok(conn)
+}
+
+fn database_file_name(encrypted: bool) -> &'static str {
+    if encrypted {
+        ENCRYPTED_DB_FILE_NAME
+    } else {
+        DB_FILE_NAME
+    }
+}
+
+/// SQLCipher raw key syntax, which skips its passphrase derivation
+fn key_pragma(key: &HistoryKey) -> String {
+    let hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("x'{}'", hex)
+}
+
+/// Whether the history index is encrypted
+pub(crate) fn is_encrypted(history_dir: &Path) -> bool {
+    history_dir.join(ENCRYPTED_DB_FILE_NAME).exists()
+}
+
+/// Database files, their WAL files and set-aside copies
+pub(crate) fn is_database_file(name: &str) -> bool {
+    name.starts_with(DB_FILE_NAME) || name.starts_with(ENCRYPTED_DB_FILE_NAME)
+}
+
+/// Convert the index to encrypted form when `to_key` is set, or to plain
+/// SQLite when it is not. The converted copy is renamed into place before
+/// the old files are removed, so an interrupted conversion always leaves
+/// one complete index. Must be called while holding the history lock.
+pub(crate) fn convert_database(
+    history_dir: &Path,
+    from_key: Option<&HistoryKey>,
+    to_key: Option<&HistoryKey>,
+) -> Result<(), String> {
+    let from_name = database_file_name(from_key.is_some());
+    let to_name = database_file_name(to_key.is_some());
+    if from_name == to_name {
+        return Ok(());
+    }
+
+    let target = history_dir.join(to_name);
+    if !target.exists() {
+        let conn = open_with_key(history_dir, from_key)?;
+        let version: i64 = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(|e| format!("Failed to read history schema version: {}", e))?;
+
+        let tmp = history_dir.join(format!("{}.tmp", to_name));
+        if tmp.exists() {
+            fs::remove_file(&tmp)
+                .map_err(|e| format!("Failed to remove {}: {}", tmp.display(), e))?;
+        }
+
+        let map_err = |e: rusqlite::Error| format!("Failed to convert history database: {}", e);
+        conn.execute(
+            "ATTACH DATABASE ?1 AS converted KEY ?2",
+            params![
+                tmp.to_string_lossy(),
+                to_key.map(key_pragma).unwrap_or_default()
+            ],
+        )
+        .map_err(map_err)?;
+        conn.query_row("SELECT sqlcipher_export('converted')", [], |_| Ok(()))
+            .map_err(map_err)?;
+        conn.pragma_update(
+            Some(DatabaseName::Attached("converted")),
+            "user_version",
+            version,
+        )
+        .map_err(map_err)?;
+        conn.execute("DETACH DATABASE converted", [])
+            .map_err(map_err)?;
+        drop(conn);
+
+        fs::rename(&tmp, &target)
+            .map_err(|e| format!("Failed to replace history database: {}", e))?;
+        sync_dir(history_dir).map_err(|e| format!("Failed to sync history directory: {}", e))?;
+    }
+
+    for name in [
+        from_name.to_string(),
+        format!("{}-wal", from_name),
+        format!("{}-shm", from_name),
+    ] {
+        let path = history_dir.join(&name);
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {}", name, e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A migrated, empty in-memory database
+pub(crate) fn open_in_memory() -> Result<Connection, String> {
+    let mut conn = Connection::open_in_memory()
+        .map_err(|e| format!("Failed to open history database: {}", e))?;
+    conn.pragma_update(None, "foreign_keys", "ON")
+        .map_err(|e| format!("Failed to configure history database: {}", e))?;
+    migrate(&mut conn)?;
+    Ok(conn)
+}
+
+/// Why the history database is corrupt, or `None` when it passes SQLite's
+/// consistency check or does not exist yet. Other failures, such as a
+/// locked database, are errors so that nothing is rebuilt over them.
+pub(crate) fn corruption(
+    history_dir: &Path,
+    key: Option<&HistoryKey>,
+) -> Result<Option<String>, String> {
+    let path = history_dir.join(database_file_name(key.is_some()));
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let check = || -> rusqlite::Result<String> {
+        let conn = Connection::open_with_flags(
+            &path,
+            OpenFlags::default().difference(OpenFlags::SQLITE_OPEN_CREATE),
+        )?;
+        if let Some(key) = key {
+            conn.pragma_update(None, "key", key_pragma(key))?;
+        }
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.query_row("PRAGMA quick_check", [], |row| row.get(0))
+    };
+
+    match check() {
+        Ok(result) if result == "ok" => Ok(None),
+        Ok(result) => Ok(Some(format!("Consistency check failed: {}", result))),
+        Err(rusqlite::Error::SqliteFailure(e, message))
+            if matches!(e.code, ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase) =>
+        {
+            // SQLCipher reports a wrong key the same way as a damaged file,
+            // so the file only counts as damaged once the key has been shown
+            // to open the encrypted files stored alongside it
+            if let (Some(key), ErrorCode::NotADatabase) = (key, e.code) {
+                if has_plain_header(&path)?
+                    || history_crypto::key_opens_files(history_dir, key)? != Some(true)
+                {
+                    return Err("History key does not match the history database".to_string());
+                }
+            }
+            Ok(Some(message.unwrap_or_else(|| e.to_string())))
+        }
+        Err(e) => Err(format!("Failed to check history database: {}", e)),
+    }
+}
+
+/// Whether a database file starts with the header of an unencrypted one
+fn has_plain_header(path: &Path) -> Result<bool, String> {
+    let mut header = Vec::with_capacity(SQLITE_HEADER.len());
+    fs::File::open(path)
+        .and_then(|file| {
+            file.take(SQLITE_HEADER.len() as u64)
+                .read_to_end(&mut header)
+        })
+        .map_err(|e| format!("Failed to read history database: {}", e))?;
+    Ok(header == SQLITE_HEADER)
+}
+
+/// Move an unreadable database (and its WAL files) aside so a fresh one can
+/// be rebuilt from `meta.json` files
+pub(crate) fn set_aside_database(history_dir: &Path) -> Result<(), String> {
+    let suffix = format!("corrupt-{}", chrono::Utc::now().timestamp());
+    let db_name = database_file_name(is_encrypted(history_dir));
+    for name in [
+        db_name.to_string(),
+        format!("{}-wal", db_name),
+        format!("{}-shm", db_name),
+    ] {
+        let path = history_dir.join(&name);
+        if path.exists() {
+            fs::rename(&path, history_dir.join(format!("{}.{}", name, suffix)))
+                .map_err(|e| format!("Failed to move aside {}: {}", name, e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Apply pending migrations, returning the schema version found on open
+fn migrate(conn: &mut Connection) -> Result<usize, String> {
+    let current: usize = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(|e| format!("Failed to read history schema version: {}", e))?;
+
+    if current > MIGRATIONS.len() {
+        return Err(format!(
+            "History database schema version {} is newer than this app supports",
+            current
+        ));
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start migration: {}", e))?;
+        migration
+            .apply(&tx)
+            .map_err(|e| format!("History migration {} failed: {}", i + 1, e))?;
+        tx.pragma_update(None, "user_version", i + 1)
+            .map_err(|e| format!("History migration {} failed: {}", i + 1, e))?;
+        tx.commit()
+            .map_err(|e| format!("History migration {} failed: {}", i + 1, e))?;
+    }
+
+    Ok(current)
+}
+
+/// Cut stored image paths at the entry's directory or the blob store.
+/// Paths outside history are left as they are.
+fn make_paths_portable(conn: &Connection) -> Result<(), String> {
+    let rows: Vec<(String, String, Option<String>, String)> = conn
+        .prepare("SELECT id, original_path, annotated_path, thumbnail_path FROM screenshots")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?
+            .collect()
+        })
+        .map_err(|e| format!("Failed to read image paths: {}", e))?;
+
+    for (id, original, annotated, thumbnail) in rows {
+        let portable = |path: String| history::relative_path(&id, &path).unwrap_or(path);
+        conn.execute(
+            "UPDATE screenshots SET original_path = ?2, annotated_path = ?3, thumbnail_path = ?4
+             WHERE id = ?1",
+            (
+                &id,
+                portable(original),
+                annotated.map(portable),
+                portable(thumbnail),
+            ),
+        )
+        .map_err(|e| format!("Failed to update image paths: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// One-time import of the JSON index and per-entry `meta.json` files used
+/// before the database existed. The old index is renamed afterwards so the
+/// import never runs twice.
+fn import_legacy_index(conn: &mut Connection, history_dir: &Path) -> Result<usize, String> {
+    let index_path = history_dir.join(LEGACY_INDEX_FILE_NAME);
+    let records: Vec<serde_json::Value> = history_crypto::read_to_string(&index_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    // Records are upgraded one by one, so a bad one only loses itself
+    let mut entries: Vec<ScreenshotMeta> = records
+        .into_iter()
+        .filter_map(|record| history_schema::upgrade(record).ok())
+        .collect();
+
+    // Entries missing from the index can still be recovered from meta.json.
+    // Any left unreadable are reported by `verify_history`.
+    if let Ok(dirs) = fs::read_dir(history_dir) {
+        for dir in dirs.flatten() {
+            if let Ok(meta) = history::read_meta(history_dir, &dir.path()) {
+                if !entries.iter().any(|m| m.id == meta.id) {
+                    entries.push(meta);
+                }
+            }
+        }
+    }
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Failed to start history import: {}", e))?;
+    let mut imported = 0;
+    for meta in &entries {
+        // Skip index rows whose files were already removed from disk
+        if !history_dir.join(&meta.id).is_dir() {
+            continue;
+        }
+        let stored = portable(&tx, meta);
+        imported += tx
+            .execute(
+                &format!(
+                    "INSERT OR IGNORE INTO screenshots ({}) VALUES ({})",
+                    INSERT_COLUMNS, INSERT_PLACEHOLDERS
+                ),
+                meta_params(&stored),
+            )
+            .map_err(|e| format!("Failed to import history entry {}: {}", meta.id, e))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Failed to import history: {}", e))?;
+
+    // Another app instance may have finished the same import first
+    match fs::rename(
+        &index_path,
+        history_dir.join(LEGACY_INDEX_MIGRATED_FILE_NAME),
+    ) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("Failed to retire legacy history index: {}", e)),
+    }
+
+    Ok(imported)
+}
+
+/// Run `write` as one unit of index changes, rolled back if it fails. A
+/// savepoint rather than a transaction, so it also nests inside one.
+pub(crate) fn atomically<T>(
+    conn: &Connection,
+    write: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    conn.execute_batch("SAVEPOINT atomically")
+        .map_err(|e| format!("Failed to start history update: {}", e))?;
+
+    match write() {
+        Ok(value) => {
+            conn.execute_batch("RELEASE atomically")
+                .map_err(|e| format!("Failed to save history update: {}", e))?;
+            Ok(value)
+        }
+        Err(e) => {
+            let _ = conn.execute_batch("ROLLBACK TO atomically; RELEASE atomically");
+            Err(e)
+        }
+    }
+}
+
+/// Insert or update an entry together with its tag and collection
+/// membership, upload records and blob references, all or nothing. Existing
+/// rows are updated in place so that rows referencing the entry are not
+/// cascaded away.
+pub(crate) fn upsert(conn: &Connection, meta: &ScreenshotMeta) -> Result<(), String> {
+    atomically(conn, || upsert_rows(conn, meta))
+}
+
+fn upsert_rows(conn: &Connection, meta: &ScreenshotMeta) -> Result<(), String> {
+    conn.execute(
+        &format!(
+            "INSERT INTO screenshots ({}) VALUES ({}) ON CONFLICT(id) DO UPDATE SET {}",
+            INSERT_COLUMNS, INSERT_PLACEHOLDERS, UPDATE_ASSIGNMENTS
+        ),
+        meta_params(&portable(conn, meta)),
+    )
+    .map_err(|e| format!("Failed to write history entry: {}", e))?;
+
+    sync_labels(conn, meta)?;
+    sync_uploads(conn, meta)?;
+    sync_fields(conn, meta)?;
+    blob_store::sync_refs(conn, meta)?;
+
+    // What the entry holds beyond its blobs, which count once each
+    conn.execute(
+        "UPDATE screenshots SET local_bytes = MAX(0, size_bytes - COALESCE((
+             SELECT SUM(b.size_bytes) FROM blob_refs r JOIN blobs b ON b.hash = r.hash
+             WHERE r.screenshot_id = screenshots.id), 0))
+         WHERE id = ?1",
+        params![meta.id],
+    )
+    .map_err(|e| format!("Failed to write history entry: {}", e))?;
+
+    Ok(())
+}
+
+/// Replace the entry's upload rows with the records in `meta`
+fn sync_uploads(conn: &Connection, meta: &ScreenshotMeta) -> Result<(), String> {
+    let map_err = |e: rusqlite::Error| format!("Failed to write upload history: {}", e);
+
+    conn.execute(
+        "DELETE FROM uploads WHERE screenshot_id = ?1",
+        params![meta.id],
+    )
+    .map_err(map_err)?;
+    for upload in &meta.uploads {
+        conn.execute(
+            "INSERT INTO uploads (screenshot_id, service, ticket_id, ticket_url, attachment_url,
+                 comment, uploaded_at, success, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                meta.id,
+                upload.service,
+                upload.ticket_id,
+                upload.ticket_url,
+                upload.attachment_url,
+                upload.comment,
+                upload.uploaded_at,
+                upload.success,
+                upload.error
+            ],
+        )
+        .map_err(map_err)?;
+    }
+
+    Ok(())
+}
+
+/// Replace the entry's custom field rows with the values in `meta`
+fn sync_fields(conn: &Connection, meta: &ScreenshotMeta) -> Result<(), String> {
+    let map_err = |e: rusqlite::Error| format!("Failed to write custom fields: {}", e);
+
+    conn.execute(
+        "DELETE FROM screenshot_fields WHERE screenshot_id = ?1",
+        params![meta.id],
+    )
+    .map_err(map_err)?;
+    for (key, value) in &meta.fields {
+        conn.execute(
+            "INSERT INTO screenshot_fields (screenshot_id, key, value) VALUES (?1, ?2, ?3)",
+            params![meta.id, key, value],
+        )
+        .map_err(map_err)?;
+    }
+
+    Ok(())
+}
+
+/// Make the entry's tag and collection rows match `meta`, creating any
+/// labels that do not exist yet
+fn sync_labels(conn: &Connection, meta: &ScreenshotMeta) -> Result<(), String> {
+    let map_err = |e: rusqlite::Error| format!("Failed to write history labels: {}", e);
+
+    conn.execute(
+        "DELETE FROM screenshot_tags WHERE screenshot_id = ?1",
+        params![meta.id],
+    )
+    .map_err(map_err)?;
+    for tag in &meta.tags {
+        conn.execute(
+            "INSERT OR IGNORE INTO tags (name) VALUES (?1)",
+            params![tag],
+        )
+        .map_err(map_err)?;
+        conn.execute(
+            "INSERT OR IGNORE INTO screenshot_tags (screenshot_id, tag_id)
+             SELECT ?1, id FROM tags WHERE name = ?2",
+            params![meta.id, tag],
+        )
+        .map_err(map_err)?;
+    }
+
+    conn.execute(
+        "DELETE FROM collection_items WHERE screenshot_id = ?1",
+        params![meta.id],
+    )
+    .map_err(map_err)?;
+    for collection in &meta.collections {
+        conn.execute(
+            "INSERT OR IGNORE INTO collections (name, created_at) VALUES (?1, ?2)",
+            params![collection, chrono::Utc::now().to_rfc3339()],
+        )
+        .map_err(map_err)?;
+        conn.execute(
+            "INSERT OR IGNORE INTO collection_items (collection_id, screenshot_id)
+             SELECT id, ?1 FROM collections WHERE name = ?2",
+            params![meta.id, collection],
+        )
+        .map_err(map_err)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn delete(conn: &Connection, id: &str) -> Result<(), String> {
+    conn.execute("DELETE FROM screenshots WHERE id = ?1", params![id])
+        .map_err(|e| format!("Failed to delete history entry: {}", e))?;
+    conn.execute("DELETE FROM screenshot_search WHERE id = ?1", params![id])
+        .map_err(|e| format!("Failed to delete history entry: {}", e))?;
+
+    Ok(())
+}
+
+pub(crate) fn find(conn: &Connection, id: &str) -> Result<Option<ScreenshotMeta>, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM screenshots s WHERE s.id = ?1",
+            SELECT_COLUMNS
+        ),
+        params![id],
+        row_to_meta,
+    )
+    .optional()
+    .map(|meta| meta.map(|meta| resolved(conn, meta)))
+    .map_err(|e| format!("Failed to read history entry: {}", e))
+}
+
+/// Bytes used by history and the number of gallery entries, from the
+/// running total kept by the index
+pub(crate) fn storage_usage(conn: &Connection) -> Result<(u64, usize), String> {
+    conn.query_row(
+        "SELECT used_bytes, item_count FROM storage_usage WHERE id = 1",
+        [],
+        |row| {
+            Ok((
+                row.get::<_, i64>(0)?.max(0) as u64,
+                row.get::<_, i64>(1)?.max(0) as usize,
+            ))
+        },
+    )
+    .map_err(|e| format!("Failed to read storage usage: {}", e))
+}
+
+/// Recompute the running total from the per-entry and per-blob sizes
+pub(crate) fn recount_storage_usage(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "UPDATE storage_usage SET
+             used_bytes = (SELECT COALESCE(SUM(local_bytes), 0) FROM screenshots)
+                 + (SELECT COALESCE(SUM(local_bytes), 0) FROM trash)
+                 + (SELECT COALESCE(SUM(size_bytes), 0) FROM blobs),
+             item_count = (SELECT COUNT(*) FROM screenshots)
+         WHERE id = 1",
+        [],
+    )
+    .map_err(|e| format!("Failed to recount storage usage: {}", e))?;
+
+    Ok(())
+}
+
+pub(crate) fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>, String> {
+    conn.query_row(
+        "SELECT value FROM settings WHERE key = ?1",
+        params![key],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to read setting {}: {}", key, e))
+}
+
+pub(crate) fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+    )
+    .map_err(|e| format!("Failed to save setting {}: {}", key, e))?;
+
+    Ok(())
+}
+
+/// Replace the full-text search document for an entry
+pub(crate) fn replace_search_document(
+    conn: &Connection,
+    id: &str,
+    doc: &SearchDocument,
+) -> Result<(), String> {
+    conn.execute("DELETE FROM screenshot_search WHERE id = ?1", params![id])
+        .map_err(|e| format!("Failed to update search index: {}", e))?;
+    conn.execute(
+        "INSERT INTO screenshot_search (id, ticket_id, annotation_text, ocr_text, notes, tags, comments)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            id,
+            doc.ticket_id,
+            doc.annotation_text,
+            doc.ocr_text,
+            doc.notes,
+            doc.tags,
+            doc.comments
+        ],
+    )
+    .map_err(|e| format!("Failed to update search index: {}", e))?;
+
+    Ok(())
+}
+
+/// Run a `SELECT` over `screenshots s` with the given joins/filters/order
+pub(crate) fn query_entries(
+    conn: &Connection,
+    clauses: &str,
+    params: &[&dyn ToSql],
+) -> Result<Vec<ScreenshotMeta>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM screenshots s {}",
+            SELECT_COLUMNS, clauses
+        ))
+        .map_err(|e| format!("Failed to query history: {}", e))?;
+
+    let rows = stmt
+        .query_map(params, row_to_meta)
+        .map_err(|e| format!("Failed to query history: {}", e))?;
+
+    rows.map(|row| row.map(|meta| resolved(conn, meta)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read history entry: {}", e))
+}
+
+/// Count the rows a `query_entries` call with the same joins/filters would match
+pub(crate) fn count_entries(
+    conn: &Connection,
+    clauses: &str,
+    params: &[&dyn ToSql],
+) -> Result<usize, String> {
+    conn.query_row(
+        &format!("SELECT COUNT(*) FROM screenshots s {}", clauses),
+        params,
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|count| count as usize)
+    .map_err(|e| format!("Failed to count history entries: {}", e))
+}
+
+/// All entries, oldest first
+pub(crate) fn list_oldest_first(conn: &Connection) -> Result<Vec<ScreenshotMeta>, String> {
+    query_entries(conn, "ORDER BY s.created_at ASC", &[])
+}
+
+/// The history directory of a file-backed connection, `None` in memory
+fn history_root(conn: &Connection) -> Option<PathBuf> {
+    conn.path()
+        .filter(|path| !path.is_empty())
+        .and_then(|path| Path::new(path).parent())
+        .map(Path::to_path_buf)
+}
+
+/// `meta` as stored in the database, with portable paths. In-memory
+/// databases have no history directory to resolve against and keep paths
+/// as they are.
+pub(crate) fn portable(conn: &Connection, meta: &ScreenshotMeta) -> ScreenshotMeta {
+    match history_root(conn) {
+        Some(_) => history::portable_paths(meta.clone()),
+        None => meta.clone(),
+    }
+}
+
+/// A stored `meta` with its paths resolved against the history directory
+pub(crate) fn resolved(conn: &Connection, meta: ScreenshotMeta) -> ScreenshotMeta {
+    match history_root(conn) {
+        Some(root) => history::resolved_paths(&root, meta),
+        None => meta,
+    }
+}
+
+fn meta_params(meta: &ScreenshotMeta) -> impl rusqlite::Params + '_ {
+    (
+        &meta.id,
+        &meta.original_path,
+        &meta.annotated_path,
+        &meta.thumbnail_path,
+        &meta.created_at,
+        &meta.ticket_id,
+        &meta.uploaded_url,
+        meta.size_bytes as i64,
+        meta.annotation_count as i64,
+        meta.redaction_count as i64,
+        meta.pinned,
+        &meta.notes,
+    )
+}
+
+fn row_to_meta(row: &Row) -> rusqlite::Result<ScreenshotMeta> {
+    Ok(ScreenshotMeta {
+        id: row.get(0)?,
+        original_path: row.get(1)?,
+        annotated_path: row.get(2)?,
+        thumbnail_path: row.get(3)?,
+        created_at: row.get(4)?,
+        ticket_id: row.get(5)?,
+        uploaded_url: row.get(6)?,
+        size_bytes: row.get::<_, i64>(7)? as u64,
+        annotation_count: row.get::<_, i64>(8)? as usize,
+        redaction_count: row.get::<_, i64>(9)? as usize,
+        pinned: row.get(10)?,
+        tags: split_labels(row.get(11)?),
+        collections: split_labels(row.get(12)?),
+        uploads: parse_uploads(row.get(13)?),
+        notes: row.get(14)?,
+        fields: parse_fields(row.get(15)?),
+    })
+}
+
+fn parse_uploads(json: Option<String>) -> Vec<UploadRecord> {
+    json.and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn parse_fields(json: Option<String>) -> BTreeMap<String, String> {
+    json.and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn split_labels(joined: Option<String>) -> Vec<String> {
+    let mut labels: Vec<String> = joined
+        .unwrap_or_default()
+        .split(LABEL_SEPARATOR)
+        .filter(|label| !label.is_empty())
+        .map(str::to_string)
+        .collect();
+    labels.sort_by_key(|label| label.to_lowercase());
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        convert_database, corruption, delete, find, get_setting, import_legacy_index, is_encrypted,
+        migrate, open_in_memory, open_with_key, recount_storage_usage, set_setting, storage_usage,
+        upsert, DB_FILE_NAME, LEGACY_INDEX_FILE_NAME, LEGACY_INDEX_MIGRATED_FILE_NAME, MIGRATIONS,
+    };
+    use crate::history::{ScreenshotMeta, UploadRecord};
+    use rusqlite::Connection;
+    use std::fs;
+
+    fn memory_db() -> Connection {
+        open_in_memory().unwrap()
+    }
+
+    #[test]
+    fn migrate_records_schema_version_and_is_idempotent() {
+        let mut conn = memory_db();
+        migrate(&mut conn).unwrap();
+        let version: usize = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn upsert_replaces_and_delete_removes() {
+        let conn = memory_db();
+        let mut entry = ScreenshotMeta::test_entry("a");
+        upsert(&conn, &entry).unwrap();
+
+        entry.ticket_id = Some("PROJ-2".to_string());
+        upsert(&conn, &entry).unwrap();
+        assert_eq!(
+            find(&conn, "a").unwrap().unwrap().ticket_id.as_deref(),
+            Some("PROJ-2")
+        );
+
+        delete(&conn, "a").unwrap();
+        assert!(find(&conn, "a").unwrap().is_none());
+    }
+
+    #[test]
+    fn upsert_keeps_labels_in_sync() {
+        let conn = memory_db();
+        let mut entry = ScreenshotMeta {
+            tags: vec!["vpn".to_string(), "Customer-A".to_string()],
+            collections: vec!["Incident 42".to_string()],
+            ..ScreenshotMeta::test_entry("a")
+        };
+        upsert(&conn, &entry).unwrap();
+
+        let stored = find(&conn, "a").unwrap().unwrap();
+        assert_eq!(stored.tags, vec!["Customer-A", "vpn"]);
+        assert_eq!(stored.collections, vec!["Incident 42"]);
+
+        entry.tags = vec!["VPN".to_string()];
+        entry.collections.clear();
+        upsert(&conn, &entry).unwrap();
+        let stored = find(&conn, "a").unwrap().unwrap();
+        assert_eq!(stored.tags, vec!["vpn"]);
+        assert!(stored.collections.is_empty());
+
+        // Labels outlive their entries; membership does not
+        delete(&conn, "a").unwrap();
+        let members: i64 = conn
+            .query_row("SELECT COUNT(*) FROM screenshot_tags", [], |row| row.get(0))
+            .unwrap();
+        let tags: i64 = conn
+            .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!((members, tags), (0, 2));
+    }
+
+    #[test]
+    fn upsert_round_trips_upload_records() {
+        let conn = memory_db();
+        let mut entry = ScreenshotMeta {
+            ticket_id: Some("PROJ-1".to_string()),
+            ..ScreenshotMeta::test_entry("a")
+        };
+        entry.uploads = vec![
+            UploadRecord {
+                service: "jira".to_string(),
+                ticket_id: "PROJ-1".to_string(),
+                ticket_url: None,
+                attachment_url: None,
+                comment: None,
+                uploaded_at: "2026-09-01T10:05:00+00:00".to_string(),
+                success: false,
+                error: Some("UPLOAD_AUTH_FAILED".to_string()),
+            },
+            UploadRecord {
+                service: "jira".to_string(),
+                ticket_id: "PROJ-1".to_string(),
+                ticket_url: Some("https://example.atlassian.net/browse/PROJ-1".to_string()),
+                attachment_url: Some("https://example.atlassian.net/a/1".to_string()),
+                comment: Some("Error on login".to_string()),
+                uploaded_at: "2026-09-01T10:06:00+00:00".to_string(),
+                success: true,
+                error: None,
+            },
+        ];
+        upsert(&conn, &entry).unwrap();
+
+        assert_eq!(find(&conn, "a").unwrap().unwrap().uploads, entry.uploads);
+    }
+
+    #[test]
+    fn legacy_import_merges_index_and_meta_files() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::create_dir_all(dir.join("b")).unwrap();
+
+        let indexed = ScreenshotMeta {
+            ticket_id: Some("PROJ-1".to_string()),
+            ..ScreenshotMeta::test_entry("a")
+        };
+        let unindexed = ScreenshotMeta::test_entry("b");
+        let deleted = ScreenshotMeta::test_entry("gone");
+        fs::write(
+            dir.join(LEGACY_INDEX_FILE_NAME),
+            serde_json::to_string(&vec![&indexed, &deleted]).unwrap(),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("b").join("meta.json"),
+            serde_json::to_string(&unindexed).unwrap(),
+        )
+        .unwrap();
+
+        let mut conn = memory_db();
+        assert_eq!(import_legacy_index(&mut conn, &dir).unwrap(), 2);
+        assert!(find(&conn, "a").unwrap().is_some());
+        assert!(find(&conn, "b").unwrap().is_some());
+        assert!(find(&conn, "gone").unwrap().is_none());
+        assert!(!dir.join(LEGACY_INDEX_FILE_NAME).exists());
+        assert!(dir.join(LEGACY_INDEX_MIGRATED_FILE_NAME).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn storage_usage_tracks_saves_and_deletes() {
+        let conn = memory_db();
+        let mut a = ScreenshotMeta::test_entry("a");
+        upsert(&conn, &a).unwrap();
+        upsert(&conn, &ScreenshotMeta::test_entry("b")).unwrap();
+        assert_eq!(storage_usage(&conn).unwrap(), (20, 2));
+
+        a.size_bytes = 4096;
+        upsert(&conn, &a).unwrap();
+        delete(&conn, "b").unwrap();
+        assert_eq!(storage_usage(&conn).unwrap(), (4096, 1));
+
+        recount_storage_usage(&conn).unwrap();
+        assert_eq!(storage_usage(&conn).unwrap(), (4096, 1));
+    }
+
+    #[test]
+    fn migration_makes_stored_paths_relative() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        for migration in &MIGRATIONS[..7] {
+            migration.apply(&conn).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 7).unwrap();
+        conn.execute(
+            "INSERT INTO screenshots (id, original_path, annotated_path, thumbnail_path, created_at)
+             VALUES ('a', '/history/a/original.png', ?1, 'a/thumbnail.png', ''),
+                 ('b', 'C:\\Users\\me\\history\\b\\original.png', NULL,
+                  'C:\\Users\\me\\history\\.blobs\\cd\\thumb.png', ''),
+                 ('c', '/elsewhere/original.png', NULL, '/elsewhere/thumbnail.png', '')",
+            [format!("/history/.blobs/ab/{}.png", "ab".repeat(32))],
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+        let paths: Vec<(String, Option<String>, String)> = conn
+            .prepare(
+                "SELECT original_path, annotated_path, thumbnail_path FROM screenshots ORDER BY id",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                (
+                    "a/original.png".to_string(),
+                    Some(format!(".blobs/ab/{}.png", "ab".repeat(32))),
+                    "a/thumbnail.png".to_string(),
+                ),
+                (
+                    "b/original.png".to_string(),
+                    None,
+                    ".blobs/cd/thumb.png".to_string(),
+                ),
+                (
+                    "/elsewhere/original.png".to_string(),
+                    None,
+                    "/elsewhere/thumbnail.png".to_string(),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_upsert_leaves_nothing_behind() {
+        let conn = memory_db();
+        conn.execute_batch(
+            "CREATE TRIGGER refuse_uploads BEFORE INSERT ON uploads BEGIN
+                 SELECT RAISE(ABORT, 'refused');
+             END;",
+        )
+        .unwrap();
+
+        let mut entry = ScreenshotMeta {
+            tags: vec!["vpn".to_string()],
+            ..ScreenshotMeta::test_entry("a")
+        };
+        entry.uploads = vec![UploadRecord {
+            service: "jira".to_string(),
+            ticket_id: "ENG-1".to_string(),
+            ticket_url: None,
+            attachment_url: None,
+            comment: None,
+            uploaded_at: "2026-09-01T11:00:00+00:00".to_string(),
+            success: false,
+            error: None,
+        }];
+
+        assert!(upsert(&conn, &entry).unwrap_err().contains("refused"));
+        assert!(find(&conn, "a").unwrap().is_none());
+        let tagged: i64 = conn
+            .query_row("SELECT COUNT(*) FROM screenshot_tags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tagged, 0);
+        assert_eq!(storage_usage(&conn).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn only_damaged_databases_count_as_corrupt() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(corruption(&dir, None).unwrap(), None);
+
+        // A schema from a newer build is not corruption and must not be rebuilt
+        let conn = Connection::open(dir.join(DB_FILE_NAME)).unwrap();
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        drop(conn);
+        assert_eq!(corruption(&dir, None).unwrap(), None);
+        assert!(open_with_key(&dir, None).unwrap_err().contains("newer"));
+
+        fs::write(dir.join(DB_FILE_NAME), vec![7u8; 8192]).unwrap();
+        assert!(corruption(&dir, None).unwrap().is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_database_encrypts_and_decrypts_the_index() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let key = [3u8; 32];
+
+        let conn = open_with_key(&dir, None).unwrap();
+        let entry = ScreenshotMeta {
+            ticket_id: Some("INC-7".to_string()),
+            ..ScreenshotMeta::test_entry("a")
+        };
+        upsert(&conn, &entry).unwrap();
+        set_setting(&conn, "retention_policy", "{}").unwrap();
+        drop(conn);
+
+        convert_database(&dir, None, Some(&key)).unwrap();
+        assert!(is_encrypted(&dir));
+        assert!(!dir.join(DB_FILE_NAME).exists());
+        assert!(open_with_key(&dir, Some(&[4u8; 32]))
+            .and_then(|conn| find(&conn, "a"))
+            .is_err());
+
+        let conn = open_with_key(&dir, Some(&key)).unwrap();
+        assert_eq!(
+            find(&conn, "a").unwrap().unwrap().ticket_id.as_deref(),
+            Some("INC-7")
+        );
+        drop(conn);
+
+        convert_database(&dir, Some(&key), None).unwrap();
+        assert!(!is_encrypted(&dir));
+        let conn = open_with_key(&dir, None).unwrap();
+        assert_eq!(
+            get_setting(&conn, "retention_policy").unwrap().as_deref(),
+            Some("{}")
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,319 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::history::{self, ScreenshotMeta};
+use crate::history_db;
+
+const FIELDS_SETTING_KEY: &str = "custom_fields";
+const MAX_VALUE_CHARS: usize = 200;
+/// Comment template variables every entry has; custom fields cannot use
+/// these keys
+const BUILTIN_VARIABLES: &[&str] = &["id", "ticket_id", "created_at", "notes"];
+
+/// A user-defined field recorded with each entry, such as customer name,
+/// environment or severity
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CustomField {
+    /// Lowercase letters, digits and `_`; used in searches
+    /// (`field:severity=high`) and comment templates (`{{severity}}`)
+    pub key: String,
+    /// Shown in the editor; defaults to the key
+    #[serde(default)]
+    pub label: String,
+    /// Allowed values, e.g. severities; empty for free text
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+#[tauri::command]
+pub async fn get_custom_fields() -> Result<Vec<CustomField>, String> {
+    let conn = history_db::open(&history::get_history_dir()?)?;
+    load_fields(&conn)
+}
+
+/// Replace the set of custom fields. Values already stored for a removed
+/// field are kept on their entries and stay searchable.
+#[tauri::command]
+pub async fn set_custom_fields(fields: Vec<CustomField>) -> Result<Vec<CustomField>, String> {
+    let fields = normalize_fields(fields)?;
+
+    let conn = history_db::open(&history::get_history_dir()?)?;
+    let json = serde_json::to_string(&fields)
+        .map_err(|e| format!("Failed to serialize custom fields: {}", e))?;
+    history_db::set_setting(&conn, FIELDS_SETTING_KEY, &json)?;
+
+    Ok(fields)
+}
+
+/// An upload comment with the entry's `{{variables}}` filled in, as
+/// `upload_screenshot` would post it
+#[tauri::command]
+pub async fn preview_upload_comment(id: String, comment: String) -> Result<String, String> {
+    render_entry_comment(&comment, &id)
+}
+
+pub(crate) fn load_fields(conn: &Connection) -> Result<Vec<CustomField>, String> {
+    match history_db::get_setting(conn, FIELDS_SETTING_KEY)? {
+        Some(json) => {
+            serde_json::from_str(&json).map_err(|e| format!("Failed to read custom fields: {}", e))
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+fn normalize_fields(fields: Vec<CustomField>) -> Result<Vec<CustomField>, String> {
+    let mut keys = HashSet::new();
+
+    fields
+        .into_iter()
+        .map(|field| {
+            let key = field.key.trim().to_lowercase();
+            if key.is_empty()
+                || !key
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            {
+                return Err(format!(
+                    "Invalid field key '{}': use letters, digits and _",
+                    field.key
+                ));
+            }
+            if BUILTIN_VARIABLES.contains(&key.as_str()) {
+                return Err(format!("Field key '{}' is reserved", key));
+            }
+            if !keys.insert(key.clone()) {
+                return Err(format!("Duplicate field key '{}'", key));
+            }
+
+            let label = match field.label.trim() {
+                "" => key.clone(),
+                label => label.to_string(),
+            };
+            let mut options: Vec<String> = Vec::new();
+            for option in field.options {
+                let option = normalize_value(&label, &option)?;
+                if !option.is_empty() && !options.iter().any(|o| o.eq_ignore_ascii_case(&option)) {
+                    options.push(option);
+                }
+            }
+
+            Ok(CustomField {
+                key,
+                label,
+                options,
+            })
+        })
+        .collect()
+}
+
+/// Merge `updates` into an entry's field values. Empty values remove the
+/// field; values of fields with options are stored as the matching option.
+pub(crate) fn apply_values(
+    fields: &[CustomField],
+    values: &mut BTreeMap<String, String>,
+    updates: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    for (key, value) in updates {
+        let key = key.trim().to_lowercase();
+        let Some(field) = fields.iter().find(|field| field.key == key) else {
+            // Clearing a value whose field has since been removed is fine
+            if value.trim().is_empty() {
+                values.remove(&key);
+                continue;
+            }
+            return Err(format!("Unknown custom field: {}", key));
+        };
+
+        let value = normalize_value(&field.label, value)?;
+        if value.is_empty() {
+            values.remove(&key);
+            continue;
+        }
+        let value = if field.options.is_empty() {
+            value
+        } else {
+            field
+                .options
+                .iter()
+                .find(|option| option.eq_ignore_ascii_case(&value))
+                .cloned()
+                .ok_or_else(|| {
+                    format!(
+                        "{} must be one of: {}",
+                        field.label,
+                        field.options.join(", ")
+                    )
+                })?
+        };
+        values.insert(key, value);
+    }
+
+    Ok(())
+}
+
+fn normalize_value(label: &str, value: &str) -> Result<String, String> {
+    let value = value.trim();
+
+    if value.chars().count() > MAX_VALUE_CHARS {
+        return Err(format!(
+            "{} must be at most {} characters",
+            label, MAX_VALUE_CHARS
+        ));
+    }
+    if value.chars().any(char::is_control) {
+        return Err(format!("{} must be a single line", label));
+    }
+
+    Ok(value.to_string())
+}
+
+/// Notes as stored, `None` when blank
+pub(crate) fn normalize_notes(notes: &str) -> Option<String> {
+    Some(notes.trim().to_string()).filter(|notes| !notes.is_empty())
+}
+
+/// Notes and field values, for the full-text index
+pub(crate) fn search_text(meta: &ScreenshotMeta) -> String {
+    meta.notes
+        .iter()
+        .chain(meta.fields.values())
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Fill `{{variable}}` placeholders from an entry. Defined fields without
+/// a value and blank built-ins become empty; unknown names are left as
+/// written so typos show up in the preview.
+pub(crate) fn render_comment(
+    template: &str,
+    meta: &ScreenshotMeta,
+    fields: &[CustomField],
+) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let placeholder = &rest[start..start + len + 4];
+        let name = placeholder[2..placeholder.len() - 2].trim().to_lowercase();
+
+        rendered.push_str(&rest[..start]);
+        match variable(&name, meta, fields) {
+            Some(value) => rendered.push_str(&value),
+            None => rendered.push_str(placeholder),
+        }
+        rest = &rest[start + placeholder.len()..];
+    }
+    rendered.push_str(rest);
+
+    rendered
+}
+
+fn variable(name: &str, meta: &ScreenshotMeta, fields: &[CustomField]) -> Option<String> {
+    match name {
+        "id" => Some(meta.id.clone()),
+        "ticket_id" => Some(meta.ticket_id.clone().unwrap_or_default()),
+        "created_at" => Some(meta.created_at.clone()),
+        "notes" => Some(meta.notes.clone().unwrap_or_default()),
+        key => meta.fields.get(key).cloned().or_else(|| {
+            fields
+                .iter()
+                .any(|field| field.key == key)
+                .then(String::new)
+        }),
+    }
+}
+
+/// `render_comment` for the history entry `id`
+pub(crate) fn render_entry_comment(template: &str, id: &str) -> Result<String, String> {
+    if !template.contains("{{") {
+        return Ok(template.to_string());
+    }
+
+    let meta = history::find_history_entry(id)?;
+    let conn = history_db::open(&history::get_history_dir()?)?;
+    Ok(render_comment(template, &meta, &load_fields(&conn)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_values, normalize_fields, render_comment, CustomField};
+    use crate::history::ScreenshotMeta;
+    use std::collections::BTreeMap;
+
+    fn fields() -> Vec<CustomField> {
+        normalize_fields(vec![
+            CustomField {
+                key: " Customer ".to_string(),
+                label: "Customer name".to_string(),
+                options: Vec::new(),
+            },
+            CustomField {
+                key: "severity".to_string(),
+                label: String::new(),
+                options: vec!["Low".to_string(), "High".to_string(), "high".to_string()],
+            },
+        ])
+        .unwrap()
+    }
+
+    fn updates(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn field_values_are_checked_against_their_definitions() {
+        let fields = fields();
+        assert_eq!(fields[0].key, "customer");
+        assert_eq!(fields[1].label, "severity");
+        assert_eq!(fields[1].options, vec!["Low", "High"]);
+
+        let mut values = BTreeMap::new();
+        apply_values(
+            &fields,
+            &mut values,
+            &updates(&[("customer", " Acme "), ("SEVERITY", "high")]),
+        )
+        .unwrap();
+        assert_eq!(values["customer"], "Acme");
+        assert_eq!(values["severity"], "High");
+
+        assert!(apply_values(&fields, &mut values, &updates(&[("severity", "urgent")])).is_err());
+        assert!(apply_values(&fields, &mut values, &updates(&[("region", "eu")])).is_err());
+        assert!(apply_values(&fields, &mut values, &updates(&[("customer", "a\nb")])).is_err());
+
+        apply_values(&fields, &mut values, &updates(&[("customer", "")])).unwrap();
+        assert!(!values.contains_key("customer"));
+
+        let reserved = CustomField {
+            key: "notes".to_string(),
+            label: String::new(),
+            options: Vec::new(),
+        };
+        assert!(normalize_fields(vec![reserved]).is_err());
+    }
+
+    #[test]
+    fn comment_templates_use_entry_fields() {
+        let meta = ScreenshotMeta {
+            created_at: "2026-10-01T09:00:00+00:00".to_string(),
+            ticket_id: Some("ENG-7".to_string()),
+            fields: updates(&[("customer", "Acme")]),
+            ..ScreenshotMeta::test_entry("11111111-1111-4111-8111-111111111111")
+        };
+
+        let rendered = render_comment(
+            "{{ ticket_id }} for {{Customer}} ({{severity}}) {{notes}}{{typo}} {{",
+            &meta,
+            &fields(),
+        );
+        assert_eq!(rendered, "ENG-7 for Acme () {{typo}} {{");
+    }
+}
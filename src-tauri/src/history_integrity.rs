@@ -0,0 +1,296 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::blob_store;
+use crate::history::{self, ScreenshotMeta};
+use crate::history_crypto;
+use crate::history_db;
+use crate::history_search;
+use crate::history_trash;
+
+/// Broken entries are moved here rather than deleted, so nothing is lost
+const QUARANTINE_DIR_NAME: &str = ".quarantine";
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct IntegrityReport {
+    pub checked_entries: usize,
+    /// Index rows whose directory no longer exists
+    pub removed_from_index: Vec<String>,
+    /// Directories with a valid `meta.json` that were missing from the index
+    pub reindexed: Vec<String>,
+    pub quarantined: Vec<QuarantinedEntry>,
+    /// Trashed entries whose stored metadata cannot be read. They are left
+    /// in place and hidden from the trash list.
+    #[serde(default)]
+    pub unreadable_trash: Vec<QuarantinedEntry>,
+    /// The database was corrupt and was rebuilt from `meta.json` files
+    pub index_rebuilt: bool,
+    /// Why the database was found corrupt
+    #[serde(default)]
+    pub index_problem: Option<String>,
+    /// False for `verify_history`, where nothing is changed
+    pub applied: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct QuarantinedEntry {
+    pub name: String,
+    pub reason: String,
+}
+
+/// Report drift between history directories, `meta.json` files and the
+/// index without changing anything.
+#[tauri::command]
+pub async fn verify_history() -> Result<IntegrityReport, String> {
+    let history_dir = history::get_history_dir()?;
+    let key = history_crypto::index_key(&history_dir)?;
+
+    match history_db::corruption(&history_dir, key.as_ref())? {
+        None => {
+            let conn = history_db::open_with_key(&history_dir, key.as_ref())?;
+            reconcile(&history_dir, &conn, false)
+        }
+        Some(problem) => {
+            // Plan against an empty index: every valid entry would be reindexed
+            let conn = history_db::open_in_memory()?;
+            let mut report = reconcile(&history_dir, &conn, false)?;
+            report.index_rebuilt = true;
+            report.index_problem = Some(problem);
+            Ok(report)
+        }
+    }
+}
+
+/// Reconcile history directories, `meta.json` files and the index, rebuilding
+/// the index if it is corrupt and quarantining corrupt entries. Any other
+/// failure to open the index (a lock, a newer schema, a missing or wrong key)
+/// is returned as is, since a rebuild would lose settings, trash and logs.
+#[tauri::command]
+pub async fn repair_history() -> Result<IntegrityReport, String> {
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+
+    // Resolved up front so a missing key fails the repair instead of
+    // replacing an encrypted index with a plain one
+    let key = history_crypto::index_key(&history_dir)?;
+    let index_problem = history_db::corruption(&history_dir, key.as_ref())?;
+    if index_problem.is_some() {
+        history_db::set_aside_database(&history_dir)?;
+    }
+    let conn = history_db::open_with_key(&history_dir, key.as_ref())?;
+
+    let mut report = reconcile(&history_dir, &conn, true)?;
+    report.index_rebuilt = index_problem.is_some();
+    report.index_problem = index_problem;
+    history_trash::reconcile(&conn, &history_dir)?;
+    blob_store::collect_garbage(&conn, &history_dir)?;
+    history::rescan_usage(&conn, &history_dir)?;
+    Ok(report)
+}
+
+fn reconcile(
+    history_dir: &Path,
+    conn: &Connection,
+    apply: bool,
+) -> Result<IntegrityReport, String> {
+    let mut report = IntegrityReport {
+        applied: apply,
+        ..Default::default()
+    };
+
+    let indexed: HashSet<String> = history_db::list_oldest_first(conn)?
+        .into_iter()
+        .map(|meta| meta.id)
+        .collect();
+    let mut seen = HashSet::new();
+
+    let mut dirs: Vec<_> = fs::read_dir(history_dir)
+        .map_err(|e| format!("Failed to read history directory: {}", e))?
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| !history::is_internal_entry(name))
+        // Other folders the user keeps here are left alone
+        .filter(|name| {
+            uuid::Uuid::parse_str(name).is_ok()
+                || history_dir
+                    .join(name)
+                    .join(history::META_FILE_NAME)
+                    .exists()
+        })
+        .collect();
+    dirs.sort();
+
+    for name in dirs {
+        report.checked_entries += 1;
+        let entry_dir = history_dir.join(&name);
+
+        let problem = match check_entry(history_dir, &entry_dir, &name) {
+            Ok(meta) => {
+                seen.insert(name.clone());
+                if !indexed.contains(&name) {
+                    if apply {
+                        history_db::upsert(conn, &meta)?;
+                        history_search::index_entry(conn, &entry_dir, &meta)?;
+                    }
+                    report.reindexed.push(name);
+                }
+                continue;
+            }
+            Err(reason) => reason,
+        };
+
+        if apply {
+            history_db::delete(conn, &name)?;
+            quarantine(history_dir, &name)?;
+        }
+        report.quarantined.push(QuarantinedEntry {
+            name,
+            reason: problem,
+        });
+    }
+
+    let mut missing: Vec<String> = indexed
+        .into_iter()
+        .filter(|id| !seen.contains(id))
+        .filter(|id| !report.quarantined.iter().any(|q| &q.name == id))
+        .collect();
+    missing.sort();
+
+    for id in &missing {
+        if apply {
+            history_db::delete(conn, id)?;
+        }
+    }
+    report.removed_from_index = missing;
+
+    report.unreadable_trash = history_trash::list_unreadable(conn)?
+        .into_iter()
+        .map(|(name, reason)| QuarantinedEntry { name, reason })
+        .collect();
+
+    Ok(report)
+}
+
+/// Validate a single entry directory and return its metadata
+fn check_entry(history_dir: &Path, entry_dir: &Path, name: &str) -> Result<ScreenshotMeta, String> {
+    if uuid::Uuid::parse_str(name).is_err() {
+        return Err("Not a history entry".to_string());
+    }
+
+    let meta = history::read_meta(history_dir, entry_dir)?;
+
+    if meta.id != name {
+        return Err("meta.json belongs to a different entry".to_string());
+    }
+
+    // Originals live in the blob store for newer entries and in the entry
+    // directory for older ones; `original_path` points at either
+    if !Path::new(&meta.original_path).is_file() {
+        return Err("Original image is missing".to_string());
+    }
+
+    Ok(meta)
+}
+
+fn quarantine(history_dir: &Path, name: &str) -> Result<(), String> {
+    let quarantine_dir = history_dir.join(QUARANTINE_DIR_NAME);
+    fs::create_dir_all(&quarantine_dir)
+        .map_err(|e| format!("Failed to create quarantine directory: {}", e))?;
+
+    let mut dest = quarantine_dir.join(name);
+    if dest.exists() {
+        dest = quarantine_dir.join(format!("{}-{}", name, chrono::Utc::now().timestamp()));
+    }
+
+    fs::rename(history_dir.join(name), &dest)
+        .map_err(|e| format!("Failed to quarantine {}: {}", name, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reconcile, QUARANTINE_DIR_NAME};
+    use crate::history::ScreenshotMeta;
+    use crate::history_db;
+    use std::fs;
+    use std::path::Path;
+
+    const GOOD: &str = "11111111-1111-4111-8111-111111111111";
+    const UNINDEXED: &str = "22222222-2222-4222-8222-222222222222";
+    const CORRUPT: &str = "33333333-3333-4333-8333-333333333333";
+    const DELETED: &str = "44444444-4444-4444-8444-444444444444";
+
+    fn write_entry(dir: &Path, id: &str) {
+        fs::create_dir_all(dir.join(id)).unwrap();
+        fs::write(dir.join(id).join("original.png"), b"png").unwrap();
+        let meta = ScreenshotMeta {
+            original_path: dir
+                .join(id)
+                .join("original.png")
+                .to_string_lossy()
+                .to_string(),
+            ..ScreenshotMeta::test_entry(id)
+        };
+        fs::write(
+            dir.join(id).join("meta.json"),
+            serde_json::to_string(&meta).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn reconcile_reports_then_repairs_drift() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        write_entry(&dir, GOOD);
+        write_entry(&dir, UNINDEXED);
+        fs::create_dir_all(dir.join(CORRUPT)).unwrap();
+        fs::write(dir.join(CORRUPT).join("meta.json"), b"{ not json").unwrap();
+        fs::create_dir_all(dir.join(".staging")).unwrap();
+        fs::create_dir_all(dir.join("Exports")).unwrap();
+
+        let conn = history_db::open_in_memory().unwrap();
+        for id in [GOOD, CORRUPT, DELETED] {
+            let meta = ScreenshotMeta {
+                original_path: dir
+                    .join(id)
+                    .join("original.png")
+                    .to_string_lossy()
+                    .to_string(),
+                ..ScreenshotMeta::test_entry(id)
+            };
+            history_db::upsert(&conn, &meta).unwrap();
+        }
+
+        let plan = reconcile(&dir, &conn, false).unwrap();
+        assert!(!plan.applied);
+        assert_eq!(plan.checked_entries, 3);
+        assert_eq!(plan.reindexed, vec![UNINDEXED]);
+        assert_eq!(plan.removed_from_index, vec![DELETED]);
+        assert_eq!(plan.quarantined.len(), 1);
+        assert_eq!(plan.quarantined[0].name, CORRUPT);
+        assert!(plan.quarantined[0]
+            .reason
+            .starts_with("Unreadable meta.json"));
+        // Verification alone changes nothing
+        assert!(history_db::find(&conn, DELETED).unwrap().is_some());
+        assert!(dir.join(CORRUPT).exists());
+
+        let applied = reconcile(&dir, &conn, true).unwrap();
+        assert!(applied.applied);
+        assert!(history_db::find(&conn, UNINDEXED).unwrap().is_some());
+        assert!(history_db::find(&conn, DELETED).unwrap().is_none());
+        assert!(history_db::find(&conn, CORRUPT).unwrap().is_none());
+        assert!(dir.join(QUARANTINE_DIR_NAME).join(CORRUPT).exists());
+        assert!(dir.join("Exports").exists());
+
+        let clean = reconcile(&dir, &conn, false).unwrap();
+        assert!(clean.reindexed.is_empty());
+        assert!(clean.removed_from_index.is_empty());
+        assert!(clean.quarantined.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
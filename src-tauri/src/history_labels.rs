@@ -0,0 +1,435 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::history::{self, ScreenshotMeta};
+use crate::history_db;
+
+const MAX_LABEL_CHARS: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct LabelSummary {
+    pub name: String,
+    pub item_count: usize,
+}
+
+/// Tags and collections share the same shape: a named row plus a
+/// membership table keyed by screenshot id
+#[derive(Clone, Copy)]
+enum LabelKind {
+    Tag,
+    Collection,
+}
+
+impl LabelKind {
+    fn noun(self) -> &'static str {
+        match self {
+            LabelKind::Tag => "tag",
+            LabelKind::Collection => "collection",
+        }
+    }
+
+    fn table(self) -> &'static str {
+        match self {
+            LabelKind::Tag => "tags",
+            LabelKind::Collection => "collections",
+        }
+    }
+
+    /// Membership table and its foreign key column
+    fn members(self) -> (&'static str, &'static str) {
+        match self {
+            LabelKind::Tag => ("screenshot_tags", "tag_id"),
+            LabelKind::Collection => ("collection_items", "collection_id"),
+        }
+    }
+
+    fn labels_mut(self, meta: &mut ScreenshotMeta) -> &mut Vec<String> {
+        match self {
+            LabelKind::Tag => &mut meta.tags,
+            LabelKind::Collection => &mut meta.collections,
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn list_tags() -> Result<Vec<LabelSummary>, String> {
+    let conn = history_db::open(&history::get_history_dir()?)?;
+    list_labels(&conn, LabelKind::Tag)
+}
+
+#[tauri::command]
+pub async fn create_tag(name: String) -> Result<String, String> {
+    create(LabelKind::Tag, &name).await
+}
+
+/// Rename a tag on every entry that carries it
+#[tauri::command]
+pub async fn rename_tag(name: String, new_name: String) -> Result<(), String> {
+    rename(LabelKind::Tag, &name, &new_name).await
+}
+
+/// Delete a tag and remove it from every entry
+#[tauri::command]
+pub async fn delete_tag(name: String) -> Result<(), String> {
+    delete(LabelKind::Tag, &name).await
+}
+
+/// Add tags to history entries, creating tags that do not exist yet
+#[tauri::command]
+pub async fn assign_tags(ids: Vec<String>, tags: Vec<String>) -> Result<(), String> {
+    update_membership(LabelKind::Tag, &ids, &tags, true).await
+}
+
+#[tauri::command]
+pub async fn remove_tags(ids: Vec<String>, tags: Vec<String>) -> Result<(), String> {
+    update_membership(LabelKind::Tag, &ids, &tags, false).await
+}
+
+#[tauri::command]
+pub async fn list_collections() -> Result<Vec<LabelSummary>, String> {
+    let conn = history_db::open(&history::get_history_dir()?)?;
+    list_labels(&conn, LabelKind::Collection)
+}
+
+#[tauri::command]
+pub async fn create_collection(name: String) -> Result<String, String> {
+    create(LabelKind::Collection, &name).await
+}
+
+#[tauri::command]
+pub async fn rename_collection(name: String, new_name: String) -> Result<(), String> {
+    rename(LabelKind::Collection, &name, &new_name).await
+}
+
+/// Delete a collection. The entries in it are kept.
+#[tauri::command]
+pub async fn delete_collection(name: String) -> Result<(), String> {
+    delete(LabelKind::Collection, &name).await
+}
+
+#[tauri::command]
+pub async fn add_to_collection(name: String, ids: Vec<String>) -> Result<(), String> {
+    update_membership(LabelKind::Collection, &ids, &[name], true).await
+}
+
+#[tauri::command]
+pub async fn remove_from_collection(name: String, ids: Vec<String>) -> Result<(), String> {
+    update_membership(LabelKind::Collection, &ids, &[name], false).await
+}
+
+async fn create(kind: LabelKind, name: &str) -> Result<String, String> {
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    create_label(&conn, kind, name)
+}
+
+async fn rename(kind: LabelKind, name: &str, new_name: &str) -> Result<(), String> {
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    rename_label(&conn, &history_dir, kind, name, new_name)
+}
+
+async fn delete(kind: LabelKind, name: &str) -> Result<(), String> {
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    delete_label(&conn, &history_dir, kind, name)
+}
+
+async fn update_membership(
+    kind: LabelKind,
+    ids: &[String],
+    names: &[String],
+    add: bool,
+) -> Result<(), String> {
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    set_membership(&conn, &history_dir, kind, ids, names, add)
+}
+
+/// Trim a tag or collection name and reject ones that cannot be stored or
+/// typed into a search filter
+pub(crate) fn normalize_label(name: &str) -> Result<String, String> {
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err("Name must not be empty".to_string());
+    }
+    if name.chars().count() > MAX_LABEL_CHARS {
+        return Err(format!(
+            "Name must be at most {} characters",
+            MAX_LABEL_CHARS
+        ));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("Name must not contain control characters".to_string());
+    }
+
+    Ok(name.to_string())
+}
+
+/// Add or remove normalized label names, ignoring case
+pub(crate) fn edit_labels(labels: &mut Vec<String>, names: &[String], add: bool) {
+    for name in names {
+        let position = labels.iter().position(|l| l.eq_ignore_ascii_case(name));
+        match (add, position) {
+            (true, None) => labels.push(name.clone()),
+            (false, Some(i)) => {
+                labels.remove(i);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn list_labels(conn: &Connection, kind: LabelKind) -> Result<Vec<LabelSummary>, String> {
+    let (members, key) = kind.members();
+    let map_err = |e: rusqlite::Error| format!("Failed to list {}: {}", kind.table(), e);
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT l.name, COUNT(m.screenshot_id) FROM {} l
+             LEFT JOIN {} m ON m.{} = l.id
+             GROUP BY l.id ORDER BY l.name COLLATE NOCASE",
+            kind.table(),
+            members,
+            key
+        ))
+        .map_err(map_err)?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(LabelSummary {
+                name: row.get(0)?,
+                item_count: row.get::<_, i64>(1)? as usize,
+            })
+        })
+        .map_err(map_err)?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(map_err)
+}
+
+fn find_label(conn: &Connection, kind: LabelKind, name: &str) -> Result<Option<i64>, String> {
+    conn.query_row(
+        &format!("SELECT id FROM {} WHERE name = ?1", kind.table()),
+        params![name],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to read {}: {}", kind.noun(), e))
+}
+
+fn member_ids(conn: &Connection, kind: LabelKind, label_id: i64) -> Result<Vec<String>, String> {
+    let (members, key) = kind.members();
+    let map_err = |e: rusqlite::Error| format!("Failed to read {} members: {}", kind.noun(), e);
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT screenshot_id FROM {} WHERE {} = ?1",
+            members, key
+        ))
+        .map_err(map_err)?;
+
+    let rows = stmt
+        .query_map(params![label_id], |row| row.get(0))
+        .map_err(map_err)?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(map_err)
+}
+
+fn create_label(conn: &Connection, kind: LabelKind, name: &str) -> Result<String, String> {
+    let name = normalize_label(name)?;
+    if find_label(conn, kind, &name)?.is_some() {
+        return Err(format!("A {} named {} already exists", kind.noun(), name));
+    }
+
+    match kind {
+        LabelKind::Tag => conn.execute("INSERT INTO tags (name) VALUES (?1)", params![name]),
+        LabelKind::Collection => conn.execute(
+            "INSERT INTO collections (name, created_at) VALUES (?1, ?2)",
+            params![name, chrono::Utc::now().to_rfc3339()],
+        ),
+    }
+    .map_err(|e| format!("Failed to create {}: {}", kind.noun(), e))?;
+
+    Ok(name)
+}
+
+fn rename_label(
+    conn: &Connection,
+    history_dir: &Path,
+    kind: LabelKind,
+    name: &str,
+    new_name: &str,
+) -> Result<(), String> {
+    let new_name = normalize_label(new_name)?;
+    let label_id = find_label(conn, kind, name.trim())?
+        .ok_or_else(|| format!("No {} named {}", kind.noun(), name.trim()))?;
+
+    // Names are case-insensitive, so a change of case renames in place
+    if let Some(existing) = find_label(conn, kind, &new_name)? {
+        if existing != label_id {
+            return Err(format!(
+                "A {} named {} already exists",
+                kind.noun(),
+                new_name
+            ));
+        }
+    }
+
+    conn.execute(
+        &format!("UPDATE {} SET name = ?1 WHERE id = ?2", kind.table()),
+        params![new_name, label_id],
+    )
+    .map_err(|e| format!("Failed to rename {}: {}", kind.noun(), e))?;
+
+    for id in member_ids(conn, kind, label_id)? {
+        history::refresh_entry(conn, history_dir, &id)?;
+    }
+
+    Ok(())
+}
+
+fn delete_label(
+    conn: &Connection,
+    history_dir: &Path,
+    kind: LabelKind,
+    name: &str,
+) -> Result<(), String> {
+    let label_id = find_label(conn, kind, name.trim())?
+        .ok_or_else(|| format!("No {} named {}", kind.noun(), name.trim()))?;
+    let members = member_ids(conn, kind, label_id)?;
+
+    conn.execute(
+        &format!("DELETE FROM {} WHERE id = ?1", kind.table()),
+        params![label_id],
+    )
+    .map_err(|e| format!("Failed to delete {}: {}", kind.noun(), e))?;
+
+    for id in members {
+        history::refresh_entry(conn, history_dir, &id)?;
+    }
+
+    Ok(())
+}
+
+fn set_membership(
+    conn: &Connection,
+    history_dir: &Path,
+    kind: LabelKind,
+    ids: &[String],
+    names: &[String],
+    add: bool,
+) -> Result<(), String> {
+    let names = names
+        .iter()
+        .map(|name| normalize_label(name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for id in ids {
+        let id = history::parse_history_id(id)?.to_string();
+        let mut meta = history_db::find(conn, &id)?
+            .ok_or_else(|| format!("History item not found: {}", id))?;
+
+        edit_labels(kind.labels_mut(&mut meta), &names, add);
+
+        history_db::upsert(conn, &meta)?;
+        history::refresh_entry(conn, history_dir, &id)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        create_label, delete_label, list_labels, normalize_label, rename_label, set_membership,
+        LabelKind, LabelSummary,
+    };
+    use crate::history::ScreenshotMeta;
+    use crate::history_db;
+    use std::fs;
+
+    const ENTRY: &str = "11111111-1111-4111-8111-111111111111";
+
+    fn stored_meta_json(dir: &std::path::Path) -> ScreenshotMeta {
+        serde_json::from_str(&fs::read_to_string(dir.join(ENTRY).join("meta.json")).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn normalize_label_trims_and_rejects_bad_names() {
+        assert_eq!(normalize_label("  vpn ").unwrap(), "vpn");
+        assert!(normalize_label("   ").is_err());
+        assert!(normalize_label("a\u{1f}b").is_err());
+        assert!(normalize_label(&"x".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn label_changes_update_entries_and_meta_files() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join(ENTRY)).unwrap();
+        let conn = history_db::open_in_memory().unwrap();
+        history_db::upsert(&conn, &ScreenshotMeta::test_entry(ENTRY)).unwrap();
+
+        create_label(&conn, LabelKind::Tag, "unused").unwrap();
+        assert!(create_label(&conn, LabelKind::Tag, "UNUSED").is_err());
+
+        let ids = vec![ENTRY.to_string()];
+        set_membership(
+            &conn,
+            &dir,
+            LabelKind::Tag,
+            &ids,
+            &["vpn".to_string(), "Customer-A".to_string()],
+            true,
+        )
+        .unwrap();
+        set_membership(
+            &conn,
+            &dir,
+            LabelKind::Collection,
+            &ids,
+            &["Incident 42".to_string()],
+            true,
+        )
+        .unwrap();
+        assert_eq!(stored_meta_json(&dir).tags, vec!["Customer-A", "vpn"]);
+        assert_eq!(stored_meta_json(&dir).collections, vec!["Incident 42"]);
+
+        rename_label(&conn, &dir, LabelKind::Tag, "vpn", "network").unwrap();
+        assert!(rename_label(&conn, &dir, LabelKind::Tag, "network", "unused").is_err());
+        assert_eq!(stored_meta_json(&dir).tags, vec!["Customer-A", "network"]);
+
+        delete_label(&conn, &dir, LabelKind::Tag, "customer-a").unwrap();
+        delete_label(&conn, &dir, LabelKind::Collection, "Incident 42").unwrap();
+        let entry = history_db::find(&conn, ENTRY).unwrap().unwrap();
+        assert_eq!(entry.tags, vec!["network"]);
+        assert!(entry.collections.is_empty());
+        assert_eq!(stored_meta_json(&dir).tags, vec!["network"]);
+
+        assert_eq!(
+            list_labels(&conn, LabelKind::Tag).unwrap(),
+            vec![
+                LabelSummary {
+                    name: "network".to_string(),
+                    item_count: 1
+                },
+                LabelSummary {
+                    name: "unused".to_string(),
+                    item_count: 0
+                },
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::fs_util;
+use crate::history;
+use crate::history_db;
+
+const APP_DIR_NAME: &str = "com.screenshot-annotate";
+/// Records a history location other than the default. Kept outside history
+/// itself, which is what it points to.
+const LOCATION_FILE_NAME: &str = "history_location.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LocationConfig {
+    history_dir: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryLocation {
+    pub path: String,
+    pub default_path: String,
+    pub is_default: bool,
+}
+
+#[tauri::command]
+pub async fn get_history_location() -> Result<HistoryLocation, String> {
+    current_location()
+}
+
+/// Move history to `path`, or back to the default location when `path` is
+/// `None`. The target must be missing or an empty directory.
+#[tauri::command]
+pub async fn set_history_location(path: Option<String>) -> Result<HistoryLocation, String> {
+    let default = default_dir()?;
+    let from = configured_dir()?.unwrap_or_else(|| default.clone());
+    let to = match path.as_deref().map(str::trim) {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => default.clone(),
+    };
+
+    if !to.is_absolute() {
+        return Err("History location must be an absolute path".to_string());
+    }
+    if comparable(&to) == comparable(&from) {
+        return current_location();
+    }
+
+    // A chosen location that has gone away has nothing left to move
+    if from.is_dir() {
+        move_history(&from, &to, || save_location(&to, &default))?;
+    } else {
+        fs::create_dir_all(&to)
+            .map_err(|e| format!("Failed to create history directory: {}", e))?;
+        save_location(&to, &default)?;
+    }
+
+    current_location()
+}
+
+/// Where history lives when no other location was chosen
+pub(crate) fn default_dir() -> Result<PathBuf, String> {
+    Ok(app_dir()?.join("history"))
+}
+
+/// The chosen history location, or `None` for the default
+pub(crate) fn configured_dir() -> Result<Option<PathBuf>, String> {
+    let path = app_dir()?.join(LOCATION_FILE_NAME);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read history location: {}", e)),
+    };
+    let config: LocationConfig = serde_json::from_str(&contents)
+        .map_err(|e| format!("Failed to parse history location: {}", e))?;
+
+    Ok(Some(PathBuf::from(config.history_dir)))
+}
+
+fn app_dir() -> Result<PathBuf, String> {
+    let app_support =
+        dirs::data_local_dir().ok_or_else(|| "Failed to get local data directory".to_string())?;
+    Ok(app_support.join(APP_DIR_NAME))
+}
+
+fn current_location() -> Result<HistoryLocation, String> {
+    let default = default_dir()?;
+    let path = configured_dir()?.unwrap_or_else(|| default.clone());
+
+    Ok(HistoryLocation {
+        is_default: comparable(&path) == comparable(&default),
+        path: path.to_string_lossy().to_string(),
+        default_path: default.to_string_lossy().to_string(),
+    })
+}
+
+fn save_location(history_dir: &Path, default: &Path) -> Result<(), String> {
+    let path = app_dir()?.join(LOCATION_FILE_NAME);
+
+    if comparable(history_dir) == comparable(default) {
+        return match fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("Failed to reset history location: {}", e)),
+        };
+    }
+
+    let config = LocationConfig {
+        history_dir: history_dir.to_string_lossy().to_string(),
+    };
+    let json = serde_json::to_string_pretty(&config)
+        .map_err(|e| format!("Failed to serialize history location: {}", e))?;
+    fs::create_dir_all(app_dir()?)
+        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+    fs_util::write_atomic(&path, json.as_bytes())
+        .map_err(|e| format!("Failed to save history location: {}", e))
+}
+
+/// `path` with symlinks resolved as far as it exists, so that two spellings
+/// of the same directory compare equal
+fn comparable(path: &Path) -> PathBuf {
+    if let Ok(canonical) = path.canonicalize() {
+        return canonical;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => comparable(parent).join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Move every file of history from `from` to `to`, calling `commit` to
+/// record the new location once the data is complete there. Paths in
+/// `meta.json` files and the index are made portable first so nothing has
+/// to be rewritten after the move.
+fn move_history(
+    from: &Path,
+    to: &Path,
+    commit: impl FnOnce() -> Result<(), String>,
+) -> Result<(), String> {
+    let (from_cmp, to_cmp) = (comparable(from), comparable(to));
+    if to_cmp.starts_with(&from_cmp) || from_cmp.starts_with(&to_cmp) {
+        return Err("History cannot be moved into or out of itself".to_string());
+    }
+    let target_in_use = to.exists()
+        && fs::read_dir(to)
+            .map_err(|e| format!("Failed to read {}: {}", to.display(), e))?
+            .next()
+            .is_some();
+    if target_in_use {
+        return Err(format!("{} is not empty", to.display()));
+    }
+
+    let _lock = history::lock_history(from)?;
+    {
+        // Opening applies the migration that makes the index portable
+        let conn = history_db::open(from)?;
+        for meta in history_db::list_oldest_first(&conn)? {
+            let entry_dir = from.join(&meta.id);
+            if entry_dir.is_dir() {
+                history::write_meta(from, &entry_dir, &meta)?;
+            }
+        }
+    }
+
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    // A rename is instant but only works within one volume. If the new
+    // location cannot be recorded, the data goes back where the app will
+    // still look for it.
+    if fs::rename(from, to).is_ok() {
+        return commit().map_err(|e| match fs::rename(to, from) {
+            Ok(()) => e,
+            Err(undo) => format!(
+                "{}; history was left at {} and could not be moved back: {}",
+                e,
+                to.display(),
+                undo
+            ),
+        });
+    }
+
+    if let Err(e) = copy_dir(from, to) {
+        let _ = fs::remove_dir_all(to);
+        return Err(format!("Failed to copy history to {}: {}", to.display(), e));
+    }
+    commit()?;
+
+    // A leftover copy is harmless now that the new location is recorded
+    let _ = fs::remove_dir_all(from);
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dest)?;
+        } else {
+            fs_util::copy_synced(&entry.path(), &dest)?;
+        }
+    }
+    fs_util::sync_dir(to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::move_history;
+    use std::fs;
+
+    #[test]
+    fn move_history_refuses_non_empty_and_nested_targets() {
+        let root = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let from = root.join("history");
+        let occupied = root.join("occupied");
+        fs::create_dir_all(&from).unwrap();
+        fs::create_dir_all(&occupied).unwrap();
+        fs::write(occupied.join("notes.txt"), b"keep").unwrap();
+
+        assert!(move_history(&from, &occupied, || Ok(())).is_err());
+        assert!(move_history(&from, &from.join("nested"), || Ok(())).is_err());
+        assert!(occupied.join("notes.txt").exists());
+
+        // Recording the new location failed: the data stays where it was
+        fs::write(from.join("marker"), b"entry").unwrap();
+        let to = root.join("moved").join("history");
+        assert_eq!(
+            move_history(&from, &to, || Err("no settings".to_string())).unwrap_err(),
+            "no settings"
+        );
+        assert!(from.join("marker").exists());
+        assert!(!to.exists());
+
+        move_history(&from, &to, || Ok(())).unwrap();
+        assert!(!from.exists());
+        assert!(to.is_dir());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,542 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::annotations;
+use crate::blob_store;
+use crate::export;
+use crate::fs_util::sync_dir;
+use crate::history::{self, ScreenshotMeta};
+use crate::history_crypto::{self, copy_synced, write_atomic};
+use crate::history_db;
+use crate::history_search;
+
+/// Earlier annotation states of an entry, one numbered directory each
+const REVISIONS_DIR_NAME: &str = "revisions";
+/// Describes the revision stored alongside it (in the entry root for the
+/// current one)
+const REVISION_FILE_NAME: &str = "revision.json";
+const ANNOTATIONS_FILE_NAME: &str = "annotations.json";
+
+/// What the editor needs to continue working on a saved entry
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryEditSession {
+    pub id: String,
+    /// The original served through the `history` protocol, which decrypts
+    /// it when history is encrypted, so no plain copy is written to disk
+    pub original_url: String,
+    pub annotations_json: String,
+    pub revision: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AnnotationRevision {
+    pub revision: u32,
+    pub saved_at: String,
+    pub annotation_count: usize,
+    pub annotated_path: Option<String>,
+    /// Set when this revision was created by restoring an earlier one
+    #[serde(default)]
+    pub restored_from: Option<u32>,
+    /// Whether this is the entry's live state rather than an archived one
+    #[serde(default)]
+    pub current: bool,
+}
+
+/// Load a saved entry back into an editing session
+#[tauri::command]
+pub async fn open_history_entry(id: String) -> Result<HistoryEditSession, String> {
+    let meta = history::find_history_entry(&id)?;
+    let entry_dir = history::get_history_dir()?.join(&meta.id);
+
+    let annotations_json = history_crypto::read_to_string(&entry_dir.join(ANNOTATIONS_FILE_NAME))
+        .map_err(|e| format!("Failed to read annotations: {}", e))?;
+
+    Ok(HistoryEditSession {
+        revision: current_revision(&entry_dir, &meta).revision,
+        original_url: history_crypto::protocol_url(Path::new(&meta.original_path)),
+        id: meta.id,
+        annotations_json,
+    })
+}
+
+/// Save an edited entry as a new revision. The previous annotations and
+/// annotated image are kept under `revisions/` and can be restored.
+#[tauri::command]
+pub async fn save_history_revision(
+    id: String,
+    annotated_path: Option<String>,
+    thumbnail_path: Option<String>,
+    annotations_json: String,
+) -> Result<ScreenshotMeta, String> {
+    let normalized_id = history::parse_history_id(&id)?.to_string();
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    save_revision(
+        &conn,
+        &history_dir,
+        &normalized_id,
+        &annotations_json,
+        annotated_path.as_deref().map(Path::new),
+        thumbnail_path.as_deref().map(Path::new),
+        None,
+    )
+}
+
+/// All revisions of an entry, oldest first, ending with the current one
+#[tauri::command]
+pub async fn list_history_revisions(id: String) -> Result<Vec<AnnotationRevision>, String> {
+    let meta = history::find_history_entry(&id)?;
+    let entry_dir = history::get_history_dir()?.join(&meta.id);
+
+    list_revisions(&entry_dir, &meta)
+}
+
+/// Make an earlier revision current again. The restore is saved as a new
+/// revision, so it can itself be undone.
+#[tauri::command]
+pub async fn restore_history_revision(id: String, revision: u32) -> Result<ScreenshotMeta, String> {
+    let normalized_id = history::parse_history_id(&id)?.to_string();
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    restore_revision(&conn, &history_dir, &normalized_id, revision)
+}
+
+fn revision_dir(entry_dir: &Path, revision: u32) -> PathBuf {
+    entry_dir
+        .join(REVISIONS_DIR_NAME)
+        .join(format!("{:04}", revision))
+}
+
+fn read_revision_file(dir: &Path) -> Option<AnnotationRevision> {
+    history_crypto::read_to_string(&dir.join(REVISION_FILE_NAME))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+}
+
+/// The live revision. Entries saved before revisions existed have no
+/// `revision.json` and are treated as revision 1.
+fn current_revision(entry_dir: &Path, meta: &ScreenshotMeta) -> AnnotationRevision {
+    let mut current = read_revision_file(entry_dir).unwrap_or_else(|| AnnotationRevision {
+        revision: 1,
+        saved_at: meta.created_at.clone(),
+        annotation_count: meta.annotation_count,
+        annotated_path: None,
+        restored_from: None,
+        current: true,
+    });
+    current.annotated_path = meta.annotated_path.clone();
+    current.current = true;
+    current
+}
+
+fn list_revisions(
+    entry_dir: &Path,
+    meta: &ScreenshotMeta,
+) -> Result<Vec<AnnotationRevision>, String> {
+    let current = current_revision(entry_dir, meta);
+    let mut revisions = Vec::new();
+
+    let revisions_root = entry_dir.join(REVISIONS_DIR_NAME);
+    if revisions_root.is_dir() {
+        for dir in fs::read_dir(&revisions_root)
+            .map_err(|e| format!("Failed to read revisions: {}", e))?
+            .flatten()
+        {
+            // Skip half-written archives (".0003.tmp") left by a crash
+            if history::is_internal_entry(&dir.file_name().to_string_lossy()) {
+                continue;
+            }
+            // A save that failed after archiving leaves a copy of the
+            // current revision behind
+            if let Some(mut revision) = read_revision_file(&dir.path())
+                .filter(|revision| revision.revision < current.revision)
+            {
+                let annotated = dir.path().join("annotated.png");
+                revision.annotated_path = annotated
+                    .is_file()
+                    .then(|| annotated.to_string_lossy().to_string());
+                revision.current = false;
+                revisions.push(revision);
+            }
+        }
+    }
+
+    revisions.sort_by_key(|r| r.revision);
+    revisions.push(current);
+    Ok(revisions)
+}
+
+/// Copy the live annotations, annotated image and thumbnail into
+/// `revisions/NNNN`. The images are copied rather than referenced so the
+/// blob store can drop them once no entry uses them.
+fn archive_current(
+    history_dir: &Path,
+    entry_dir: &Path,
+    current: &AnnotationRevision,
+    thumbnail: &Path,
+) -> Result<(), String> {
+    let revisions_root = entry_dir.join(REVISIONS_DIR_NAME);
+    let final_dir = revision_dir(entry_dir, current.revision);
+    let staging_dir = revisions_root.join(format!(".{:04}.tmp", current.revision));
+
+    // Already archived by a save that was interrupted before it finished
+    if final_dir.is_dir() {
+        return Ok(());
+    }
+
+    let _ = fs::remove_dir_all(&staging_dir);
+    fs::create_dir_all(&staging_dir)
+        .map_err(|e| format!("Failed to create revision directory: {}", e))?;
+
+    copy_synced(
+        history_dir,
+        &entry_dir.join(ANNOTATIONS_FILE_NAME),
+        &staging_dir.join(ANNOTATIONS_FILE_NAME),
+    )
+    .map_err(|e| format!("Failed to archive annotations: {}", e))?;
+
+    if let Some(annotated) = current.annotated_path.as_deref().map(Path::new) {
+        if annotated.is_file() {
+            copy_synced(history_dir, annotated, &staging_dir.join("annotated.png"))
+                .map_err(|e| format!("Failed to archive annotated image: {}", e))?;
+        }
+    }
+    if thumbnail.is_file() {
+        copy_synced(history_dir, thumbnail, &staging_dir.join("thumbnail.png"))
+            .map_err(|e| format!("Failed to archive thumbnail: {}", e))?;
+    }
+
+    let archived = AnnotationRevision {
+        annotated_path: None,
+        current: false,
+        ..current.clone()
+    };
+    let revision_json = serde_json::to_string_pretty(&archived)
+        .map_err(|e| format!("Failed to serialize revision: {}", e))?;
+    write_atomic(
+        history_dir,
+        &staging_dir.join(REVISION_FILE_NAME),
+        revision_json.as_bytes(),
+    )
+    .map_err(|e| format!("Failed to write revision: {}", e))?;
+
+    fs::rename(&staging_dir, &final_dir)
+        .map_err(|e| format!("Failed to archive revision: {}", e))?;
+    sync_dir(&revisions_root).map_err(|e| format!("Failed to sync revisions: {}", e))
+}
+
+/// Remove an image that lived in the entry directory itself, as entries
+/// saved before the blob store did, once nothing points at it any more
+fn remove_legacy_image(entry_dir: &Path, path: Option<&str>) -> Result<(), String> {
+    let Some(path) = path.map(Path::new) else {
+        return Ok(());
+    };
+    if path.parent() == Some(entry_dir) && path.is_file() {
+        fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+    }
+
+    Ok(())
+}
+
+fn save_revision(
+    conn: &Connection,
+    history_dir: &Path,
+    id: &str,
+    annotations_json: &str,
+    annotated: Option<&Path>,
+    thumbnail: Option<&Path>,
+    restored_from: Option<u32>,
+) -> Result<ScreenshotMeta, String> {
+    let mut meta =
+        history_db::find(conn, id)?.ok_or_else(|| format!("History item not found: {}", id))?;
+    let entry_dir = history_dir.join(id);
+
+    let annotations =
+        annotations::validate_for_image(annotations_json, Path::new(&meta.original_path))?;
+
+    let current = current_revision(&entry_dir, &meta);
+    archive_current(
+        history_dir,
+        &entry_dir,
+        &current,
+        Path::new(&meta.thumbnail_path),
+    )?;
+
+    let previous = meta.clone();
+    meta.annotated_path = annotated
+        .map(|source| blob_store::store(history_dir, source))
+        .transpose()?
+        .map(|path| path.to_string_lossy().to_string());
+    if let Some(source) = thumbnail {
+        meta.thumbnail_path = blob_store::store(history_dir, source)?
+            .to_string_lossy()
+            .to_string();
+    }
+    meta.annotation_count = annotations.len();
+    meta.redaction_count = annotations.iter().filter(|a| a.is_redaction()).count();
+
+    // Build the new live files in staging, then swap them in together with
+    // the index update so a failure leaves the previous revision intact
+    let staging_dir = history_dir
+        .join(history::STAGING_DIR_NAME)
+        .join(format!("{}.revision", id));
+    let _ = fs::remove_dir_all(&staging_dir);
+    fs::create_dir_all(&staging_dir)
+        .map_err(|e| format!("Failed to create staging directory: {}", e))?;
+
+    let result = (|| -> Result<(), String> {
+        write_atomic(
+            history_dir,
+            &staging_dir.join(ANNOTATIONS_FILE_NAME),
+            annotations_json.as_bytes(),
+        )
+        .map_err(|e| format!("Failed to write annotations: {}", e))?;
+
+        let next = AnnotationRevision {
+            revision: current.revision + 1,
+            saved_at: chrono::Utc::now().to_rfc3339(),
+            annotation_count: meta.annotation_count,
+            annotated_path: None,
+            restored_from,
+            current: false,
+        };
+        let revision_json = serde_json::to_string_pretty(&next)
+            .map_err(|e| format!("Failed to serialize revision: {}", e))?;
+        write_atomic(
+            history_dir,
+            &staging_dir.join(REVISION_FILE_NAME),
+            revision_json.as_bytes(),
+        )
+        .map_err(|e| format!("Failed to write revision: {}", e))?;
+
+        meta.size_bytes = history::entry_size(&entry_dir, &meta)?;
+        history::write_meta(history_dir, &staging_dir, &meta)?;
+
+        history_db::atomically(conn, || {
+            history_db::upsert(conn, &meta)?;
+            for name in [
+                ANNOTATIONS_FILE_NAME,
+                REVISION_FILE_NAME,
+                history::META_FILE_NAME,
+            ] {
+                fs::rename(staging_dir.join(name), entry_dir.join(name))
+                    .map_err(|e| format!("Failed to save revision: {}", e))?;
+            }
+            sync_dir(&entry_dir).map_err(|e| format!("Failed to sync entry directory: {}", e))?;
+            history_search::index_entry(conn, &entry_dir, &meta)
+        })
+    })();
+    let _ = fs::remove_dir_all(&staging_dir);
+    result?;
+
+    // Only now that nothing points at them can replaced images go
+    remove_legacy_image(&entry_dir, previous.annotated_path.as_deref())?;
+    if thumbnail.is_some() {
+        remove_legacy_image(&entry_dir, Some(&previous.thumbnail_path))?;
+    }
+    meta.size_bytes = history::entry_size(&entry_dir, &meta)?;
+    history_db::upsert(conn, &meta)?;
+    blob_store::collect_garbage(conn, history_dir)?;
+    history::refresh_entry(conn, history_dir, id)
+}
+
+fn restore_revision(
+    conn: &Connection,
+    history_dir: &Path,
+    id: &str,
+    revision: u32,
+) -> Result<ScreenshotMeta, String> {
+    let source_dir = revision_dir(&history_dir.join(id), revision);
+    if read_revision_file(&source_dir).is_none() {
+        return Err(format!("Revision {} not found", revision));
+    }
+
+    let annotations_json = history_crypto::read_to_string(&source_dir.join(ANNOTATIONS_FILE_NAME))
+        .map_err(|e| format!("Failed to read revision annotations: {}", e))?;
+    let annotated = source_dir.join("annotated.png");
+    let annotated = annotated.is_file().then_some(annotated.as_path());
+
+    // Revisions archived without a thumbnail get one drawn from the image
+    // they restore
+    let mut regenerated = None;
+    let mut thumbnail = source_dir.join("thumbnail.png");
+    if !thumbnail.is_file() {
+        let meta =
+            history_db::find(conn, id)?.ok_or_else(|| format!("History item not found: {}", id))?;
+        let image =
+            history_crypto::open_image(annotated.unwrap_or_else(|| Path::new(&meta.original_path)))
+                .map_err(|e| format!("Failed to open revision image: {}", e))?;
+        thumbnail = std::env::temp_dir().join(format!("sa_{}.png", Uuid::new_v4()));
+        export::save_thumbnail(&image.to_rgba8(), &thumbnail)?;
+        regenerated = Some(thumbnail.clone());
+    }
+
+    let result = save_revision(
+        conn,
+        history_dir,
+        id,
+        &annotations_json,
+        annotated,
+        Some(&thumbnail),
+        Some(revision),
+    );
+    if let Some(path) = regenerated {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{list_revisions, restore_revision, revision_dir, save_revision};
+    use crate::history::ScreenshotMeta;
+    use crate::history_db;
+    use std::fs;
+    use std::path::Path;
+
+    const ENTRY: &str = "11111111-1111-4111-8111-111111111111";
+    const ARROW: &str = r##"{"id": "a", "type": "arrow", "color": "#FF0000", "thickness": 3,
+        "createdAt": 1, "start": {"x": 10, "y": 10}, "end": {"x": 90, "y": 40}}"##;
+    const REDACTION: &str = r##"{"id": "b", "type": "redact", "color": "#000000", "thickness": 0,
+        "createdAt": 2, "origin": {"x": 0, "y": 60}, "width": 40, "height": 10,
+        "style": "blackbox", "reason": "email"}"##;
+
+    #[test]
+    fn revisions_are_archived_listed_and_restored() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let entry_dir = dir.join(ENTRY);
+        fs::create_dir_all(&entry_dir).unwrap();
+        image::RgbaImage::new(100, 80)
+            .save(entry_dir.join("original.png"))
+            .unwrap();
+        fs::write(entry_dir.join("annotated.png"), b"first").unwrap();
+        fs::write(entry_dir.join("thumbnail.png"), b"first thumbnail").unwrap();
+        fs::write(entry_dir.join("annotations.json"), format!("[{}]", ARROW)).unwrap();
+        let edited = dir.join("edited.png");
+        fs::write(&edited, b"second").unwrap();
+        let edited_thumbnail = dir.join("edited_thumbnail.png");
+        fs::write(&edited_thumbnail, b"second thumbnail").unwrap();
+
+        let conn = history_db::open_in_memory().unwrap();
+        let meta = ScreenshotMeta {
+            original_path: entry_dir.join("original.png").to_string_lossy().to_string(),
+            annotated_path: Some(
+                entry_dir
+                    .join("annotated.png")
+                    .to_string_lossy()
+                    .to_string(),
+            ),
+            thumbnail_path: entry_dir
+                .join("thumbnail.png")
+                .to_string_lossy()
+                .to_string(),
+            annotation_count: 1,
+            ..ScreenshotMeta::test_entry(ENTRY)
+        };
+        history_db::upsert(&conn, &meta).unwrap();
+
+        // Annotations that do not fit the typed model are refused
+        for invalid in [r#"[{"type":"arrow"}]"#, r#"{"type":"redact"}"#] {
+            assert!(save_revision(&conn, &dir, ENTRY, invalid, None, None, None).is_err());
+        }
+
+        let saved = save_revision(
+            &conn,
+            &dir,
+            ENTRY,
+            &format!("[{}, {}]", ARROW, REDACTION),
+            Some(&edited),
+            Some(&edited_thumbnail),
+            None,
+        )
+        .unwrap();
+        assert_eq!(saved.annotation_count, 2);
+        assert_eq!(saved.redaction_count, 1);
+        assert_eq!(
+            fs::read(saved.annotated_path.as_ref().unwrap()).unwrap(),
+            b"second"
+        );
+        // The legacy in-entry copies were archived and then removed
+        assert!(!entry_dir.join("annotated.png").exists());
+        assert!(!entry_dir.join("thumbnail.png").exists());
+
+        let revisions = list_revisions(&entry_dir, &saved).unwrap();
+        assert_eq!(
+            revisions
+                .iter()
+                .map(|r| (r.revision, r.annotation_count, r.current))
+                .collect::<Vec<_>>(),
+            vec![(1, 1, false), (2, 2, true)]
+        );
+        assert_eq!(
+            fs::read(revisions[0].annotated_path.as_ref().unwrap()).unwrap(),
+            b"first"
+        );
+
+        let restored = restore_revision(&conn, &dir, ENTRY, 1).unwrap();
+        assert_eq!(restored.annotation_count, 1);
+        assert_eq!(restored.redaction_count, 0);
+        assert_eq!(
+            fs::read(restored.annotated_path.as_ref().unwrap()).unwrap(),
+            b"first"
+        );
+        assert_eq!(
+            fs::read(&restored.thumbnail_path).unwrap(),
+            b"first thumbnail"
+        );
+        let revisions = list_revisions(&entry_dir, &restored).unwrap();
+        assert_eq!(revisions.len(), 3);
+        assert_eq!(revisions[2].restored_from, Some(1));
+        assert!(restore_revision(&conn, &dir, ENTRY, 9).is_err());
+
+        // A save that fails to commit leaves the live revision and its
+        // images as they were
+        conn.execute_batch(
+            "CREATE TRIGGER refuse_saves BEFORE UPDATE ON screenshots BEGIN
+                 SELECT RAISE(ABORT, 'refused');
+             END;",
+        )
+        .unwrap();
+        let annotations_before = fs::read(entry_dir.join("annotations.json")).unwrap();
+        assert!(save_revision(
+            &conn,
+            &dir,
+            ENTRY,
+            &format!("[{}]", REDACTION),
+            Some(&edited),
+            Some(&edited_thumbnail),
+            None,
+        )
+        .unwrap_err()
+        .contains("refused"));
+        conn.execute_batch("DROP TRIGGER refuse_saves").unwrap();
+        assert_eq!(
+            fs::read(entry_dir.join("annotations.json")).unwrap(),
+            annotations_before
+        );
+        let unchanged = history_db::find(&conn, ENTRY).unwrap().unwrap();
+        assert_eq!(unchanged.annotated_path, restored.annotated_path);
+        assert!(Path::new(&unchanged.thumbnail_path).exists());
+        assert_eq!(list_revisions(&entry_dir, &unchanged).unwrap().len(), 3);
+
+        // Revisions archived without a thumbnail get one drawn from the
+        // image they restore
+        fs::remove_file(revision_dir(&entry_dir, 2).join("thumbnail.png")).unwrap();
+        fs::remove_file(revision_dir(&entry_dir, 2).join("annotated.png")).unwrap();
+        let restored = restore_revision(&conn, &dir, ENTRY, 2).unwrap();
+        assert_eq!(restored.redaction_count, 1);
+        assert_eq!(
+            image::image_dimensions(&restored.thumbnail_path).unwrap(),
+            (200, 160)
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
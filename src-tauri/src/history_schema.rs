@@ -0,0 +1,122 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::history::ScreenshotMeta;
+
+/// Version of stored entry metadata written by this build. Metadata from
+/// before versioning has no `schema_version` and counts as version 1.
+pub(crate) const META_SCHEMA_VERSION: u32 = 3;
+const VERSION_FIELD: &str = "schema_version";
+
+type Migration = fn(&mut Map<String, Value>);
+
+/// Upgrades applied in order to metadata older than `META_SCHEMA_VERSION`;
+/// entry `i` takes version `i + 1` to `i + 2`. Append only.
+const META_MIGRATIONS: &[Migration] = &[
+    // 1 -> 2: fields added after the first release, missing from old files
+    |meta| {
+        for (field, default) in [
+            ("redaction_count", Value::from(0)),
+            ("pinned", Value::Bool(false)),
+            ("tags", Value::Array(Vec::new())),
+            ("collections", Value::Array(Vec::new())),
+            ("uploads", Value::Array(Vec::new())),
+        ] {
+            meta.entry(field).or_insert(default);
+        }
+    },
+    // 2 -> 3: notes and custom field values
+    |meta| {
+        meta.entry("notes").or_insert(Value::Null);
+        meta.entry("fields").or_insert(Value::Object(Map::new()));
+    },
+];
+
+/// Entry metadata as stored in `meta.json`, the trash and archives
+#[derive(Serialize)]
+pub(crate) struct VersionedMeta<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    meta: &'a ScreenshotMeta,
+}
+
+pub(crate) fn versioned(meta: &ScreenshotMeta) -> VersionedMeta<'_> {
+    VersionedMeta {
+        schema_version: META_SCHEMA_VERSION,
+        meta,
+    }
+}
+
+/// Parse stored entry metadata of any known version, upgrading it first
+pub(crate) fn parse_meta(json: &str) -> Result<ScreenshotMeta, String> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| format!("Metadata is not valid JSON: {}", e))?;
+    upgrade(value)
+}
+
+/// `parse_meta` for metadata that is already parsed, e.g. from `index.json`
+pub(crate) fn upgrade(value: Value) -> Result<ScreenshotMeta, String> {
+    let Value::Object(mut meta) = value else {
+        return Err("Metadata is not a JSON object".to_string());
+    };
+
+    let version = match meta.remove(VERSION_FIELD) {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .filter(|version| *version >= 1)
+            .ok_or_else(|| format!("Invalid metadata schema version: {}", version))?
+            as u32,
+    };
+    if version > META_SCHEMA_VERSION {
+        return Err(format!(
+            "Metadata schema version {} is newer than this app supports",
+            version
+        ));
+    }
+
+    for migration in &META_MIGRATIONS[version as usize - 1..] {
+        migration(&mut meta);
+    }
+
+    serde_json::from_value(Value::Object(meta))
+        .map_err(|e| format!("Metadata is incomplete: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_meta, versioned, META_MIGRATIONS, META_SCHEMA_VERSION};
+    use crate::history::ScreenshotMeta;
+
+    #[test]
+    fn old_metadata_is_upgraded_and_newer_metadata_is_refused() {
+        assert_eq!(META_MIGRATIONS.len() as u32, META_SCHEMA_VERSION - 1);
+
+        let first_release = r#"{
+            "id": "11111111-1111-4111-8111-111111111111",
+            "original_path": "/history/11111111-1111-4111-8111-111111111111/original.png",
+            "annotated_path": null,
+            "thumbnail_path": "/history/11111111-1111-4111-8111-111111111111/thumbnail.png",
+            "created_at": "2024-01-01T00:00:00Z",
+            "ticket_id": "ENG-1",
+            "uploaded_url": null,
+            "size_bytes": 100,
+            "annotation_count": 2
+        }"#;
+        let meta = parse_meta(first_release).unwrap();
+        assert_eq!(meta.ticket_id.as_deref(), Some("ENG-1"));
+        assert!(meta.tags.is_empty() && !meta.pinned);
+
+        let current = serde_json::to_string(&versioned(&meta)).unwrap();
+        assert!(current.contains(&format!("\"schema_version\":{}", META_SCHEMA_VERSION)));
+        let reread: ScreenshotMeta = parse_meta(&current).unwrap();
+        assert_eq!(reread.id, meta.id);
+
+        let future = current.replace(
+            &format!("\"schema_version\":{}", META_SCHEMA_VERSION),
+            &format!("\"schema_version\":{}", META_SCHEMA_VERSION + 1),
+        );
+        assert!(parse_meta(&future).unwrap_err().contains("newer"));
+        assert!(parse_meta("{\"id\": \"x\"}").is_err());
+    }
+}
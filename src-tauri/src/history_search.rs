@@ -0,0 +1,729 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64, Engine as _};
+use rusqlite::{Connection, ToSql};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::annotations::Annotation;
+use crate::history::ScreenshotMeta;
+use crate::history_crypto;
+use crate::history_db;
+use crate::history_fields;
+
+/// Text recognized on screen by the frontend OCR pass, stored per entry
+pub(crate) const OCR_TEXT_FILE_NAME: &str = "ocr.txt";
+
+/// Page size `search_all` walks results in
+const SEARCH_ALL_PAGE_SIZE: usize = 500;
+
+// Relative bm25 weights for the indexed columns (`id` is unindexed)
+const RANK_EXPRESSION: &str = "bm25(screenshot_search, 0.0, 10.0, 5.0, 1.0, 3.0, 5.0, 2.0)";
+
+/// The text indexed for one history entry
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct SearchDocument {
+    pub ticket_id: String,
+    pub annotation_text: String,
+    pub ocr_text: String,
+    pub notes: String,
+    pub tags: String,
+    pub comments: String,
+}
+
+/// A parsed `get_history` search string, e.g.
+/// `login error ticket:PROJ-12 tag:vpn after:2026-09-01 has:redaction`
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct SearchQuery {
+    /// Free-text terms, matched as prefixes against the full-text index
+    pub terms: Vec<String>,
+    /// Ticket id prefix
+    pub ticket: Option<String>,
+    /// Inclusive lower bound on the capture date (`YYYY-MM-DD`)
+    pub after: Option<String>,
+    /// Exclusive upper bound on the capture date (`YYYY-MM-DD`)
+    pub before: Option<String>,
+    pub has: Vec<HasFilter>,
+    /// Negated `has:` filters, written `no:upload`
+    pub lacks: Vec<HasFilter>,
+    /// Exact tag names (case-insensitive); entries must carry all of them
+    pub tags: Vec<String>,
+    /// Exact collection names (case-insensitive); entries must be in all of them
+    pub collections: Vec<String>,
+    /// Custom field keys with an optional value prefix, written
+    /// `field:severity=high`, or `field:customer` for any value
+    pub fields: Vec<(String, Option<String>)>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum HasFilter {
+    Redaction,
+    Upload,
+    Ticket,
+    Annotations,
+    Pinned,
+}
+
+pub(crate) fn parse_query(input: &str) -> Result<SearchQuery, String> {
+    let mut query = SearchQuery::default();
+
+    for token in tokenize(input) {
+        let Some((key, value)) = token.split_once(':') else {
+            query.terms.push(token);
+            continue;
+        };
+
+        match key.to_lowercase().as_str() {
+            "ticket" if !value.is_empty() => query.ticket = Some(value.to_string()),
+            "after" => query.after = Some(parse_date(value)?),
+            "before" => query.before = Some(parse_date(value)?),
+            "tag" if !value.is_empty() => query.tags.push(value.to_string()),
+            "collection" | "in" if !value.is_empty() => query.collections.push(value.to_string()),
+            "field" if !value.is_empty() => {
+                let (field, prefix) = match value.split_once('=') {
+                    Some((field, prefix)) => (field, Some(prefix.to_string())),
+                    None => (value, None),
+                };
+                query.fields.push((
+                    field.to_lowercase(),
+                    prefix.filter(|prefix| !prefix.is_empty()),
+                ));
+            }
+            "has" => query.has.push(parse_has_filter(key, value)?),
+            "no" => query.lacks.push(parse_has_filter(key, value)?),
+            // Not a recognized filter (e.g. a URL); search for it as text
+            _ => query.terms.push(token),
+        }
+    }
+
+    Ok(query)
+}
+
+fn parse_has_filter(key: &str, value: &str) -> Result<HasFilter, String> {
+    match value.to_lowercase().as_str() {
+        "redaction" | "redactions" => Ok(HasFilter::Redaction),
+        "upload" | "uploaded" => Ok(HasFilter::Upload),
+        "ticket" => Ok(HasFilter::Ticket),
+        "annotation" | "annotations" => Ok(HasFilter::Annotations),
+        "pin" | "pinned" => Ok(HasFilter::Pinned),
+        other => Err(format!("Unknown search filter: {}:{}", key, other)),
+    }
+}
+
+/// Split on whitespace, keeping double-quoted phrases together
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in input.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+pub(crate) fn parse_date(value: &str) -> Result<String, String> {
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.format("%Y-%m-%d").to_string())
+        .map_err(|_| format!("Invalid date '{}': expected YYYY-MM-DD", value))
+}
+
+/// Build an FTS5 expression requiring every term, each as a prefix match
+fn fts_match_expression(terms: &[String]) -> Option<String> {
+    let parts: Vec<String> = terms
+        .iter()
+        .map(|term| term.trim())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" AND "))
+    }
+}
+
+/// Ordering for a page of results
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum SortKey {
+    /// Best full-text match first; only meaningful with free-text terms
+    Relevance,
+    Date,
+    Size,
+    Ticket,
+    AnnotationCount,
+}
+
+impl SortKey {
+    pub(crate) fn parse(value: &str) -> Result<SortKey, String> {
+        match value.to_lowercase().as_str() {
+            "relevance" => Ok(SortKey::Relevance),
+            "date" | "created_at" => Ok(SortKey::Date),
+            "size" | "size_bytes" => Ok(SortKey::Size),
+            "ticket" | "ticket_id" => Ok(SortKey::Ticket),
+            "annotations" | "annotation_count" => Ok(SortKey::AnnotationCount),
+            other => Err(format!("Unknown sort order: {}", other)),
+        }
+    }
+
+    /// Tickets read naturally A-Z; everything else newest/largest first
+    pub(crate) fn default_descending(self) -> bool {
+        self != SortKey::Ticket
+    }
+
+    fn expression(self) -> &'static str {
+        match self {
+            SortKey::Relevance => RANK_EXPRESSION,
+            SortKey::Date => "s.created_at",
+            SortKey::Size => "s.size_bytes",
+            SortKey::Ticket => "COALESCE(s.ticket_id, '') COLLATE NOCASE",
+            SortKey::AnnotationCount => "s.annotation_count",
+        }
+    }
+
+    fn cursor_value(self, meta: &ScreenshotMeta) -> serde_json::Value {
+        match self {
+            SortKey::Relevance => serde_json::Value::Null,
+            SortKey::Date => meta.created_at.clone().into(),
+            SortKey::Size => meta.size_bytes.into(),
+            SortKey::Ticket => meta.ticket_id.clone().unwrap_or_default().into(),
+            SortKey::AnnotationCount => meta.annotation_count.into(),
+        }
+    }
+}
+
+/// Where the previous page ended. Column sorts resume after the last row
+/// seen (so inserts and deletes do not shift pages); relevance ranks are
+/// only stable within one query, so it resumes by offset instead.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Cursor {
+    #[serde(default)]
+    offset: usize,
+    #[serde(default)]
+    value: serde_json::Value,
+    #[serde(default)]
+    id: String,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        BASE64.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(value: &str) -> Result<Cursor, String> {
+        BASE64
+            .decode(value)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| "Invalid history cursor".to_string())
+    }
+}
+
+/// One page of results plus what the gallery needs to fetch the next one
+#[derive(Debug)]
+pub(crate) struct SearchPage {
+    pub items: Vec<ScreenshotMeta>,
+    /// Matches across all pages
+    pub total_count: usize,
+    pub next_cursor: Option<String>,
+}
+
+/// Run a parsed query, newest first or by relevance when there are free
+/// text terms
+pub(crate) fn search(
+    conn: &Connection,
+    query: &SearchQuery,
+    limit: usize,
+) -> Result<Vec<ScreenshotMeta>, String> {
+    Ok(search_page(conn, query, None, None, None, limit)?.items)
+}
+
+/// Every entry matching `search`, paging through the whole result
+pub(crate) fn search_all(conn: &Connection, search: &str) -> Result<Vec<ScreenshotMeta>, String> {
+    let query = parse_query(search)?;
+    let mut entries = Vec::new();
+    let mut cursor: Option<String> = None;
+
+    loop {
+        let page = search_page(
+            conn,
+            &query,
+            None,
+            None,
+            cursor.as_deref(),
+            SEARCH_ALL_PAGE_SIZE,
+        )?;
+        entries.extend(page.items);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => return Ok(entries),
+        }
+    }
+}
+
+/// Run a parsed query and return one page. `sort` defaults to relevance when
+/// there are free-text terms and to date otherwise.
+pub(crate) fn search_page(
+    conn: &Connection,
+    query: &SearchQuery,
+    sort: Option<SortKey>,
+    descending: Option<bool>,
+    cursor: Option<&str>,
+    limit: usize,
+) -> Result<SearchPage, String> {
+    let mut joins = String::new();
+    let mut conditions: Vec<String> = Vec::new();
+    let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+
+    let fts = fts_match_expression(&query.terms);
+    if let Some(expression) = fts.clone() {
+        joins.push_str("JOIN screenshot_search ON screenshot_search.id = s.id ");
+        conditions.push("screenshot_search MATCH ?".to_string());
+        params.push(Box::new(expression));
+    }
+
+    if let Some(ref ticket) = query.ticket {
+        conditions.push("s.ticket_id LIKE ? ESCAPE '\\'".to_string());
+        params.push(Box::new(format!("{}%", escape_like(ticket))));
+    }
+
+    if let Some(ref after) = query.after {
+        conditions.push("s.created_at >= ?".to_string());
+        params.push(Box::new(after.clone()));
+    }
+
+    if let Some(ref before) = query.before {
+        conditions.push("s.created_at < ?".to_string());
+        params.push(Box::new(before.clone()));
+    }
+
+    for filter in &query.has {
+        conditions.push(has_condition(*filter).to_string());
+    }
+    for filter in &query.lacks {
+        conditions.push(format!("NOT ({})", has_condition(*filter)));
+    }
+
+    for tag in &query.tags {
+        conditions.push(
+            "EXISTS (SELECT 1 FROM screenshot_tags st JOIN tags t ON t.id = st.tag_id \
+             WHERE st.screenshot_id = s.id AND t.name = ?)"
+                .to_string(),
+        );
+        params.push(Box::new(tag.clone()));
+    }
+
+    for collection in &query.collections {
+        conditions.push(
+            "EXISTS (SELECT 1 FROM collection_items ci \
+             JOIN collections c ON c.id = ci.collection_id \
+             WHERE ci.screenshot_id = s.id AND c.name = ?)"
+                .to_string(),
+        );
+        params.push(Box::new(collection.clone()));
+    }
+
+    for (field, prefix) in &query.fields {
+        let mut condition = "EXISTS (SELECT 1 FROM screenshot_fields f \
+             WHERE f.screenshot_id = s.id AND f.key = ?"
+            .to_string();
+        params.push(Box::new(field.clone()));
+        if let Some(prefix) = prefix {
+            condition.push_str(" AND f.value LIKE ? ESCAPE '\\'");
+            params.push(Box::new(format!("{}%", escape_like(prefix))));
+        }
+        condition.push(')');
+        conditions.push(condition);
+    }
+
+    let mut filter_clauses = joins.clone();
+    if !conditions.is_empty() {
+        filter_clauses.push_str(&format!("WHERE {} ", conditions.join(" AND ")));
+    }
+    let filter_refs: Vec<&dyn ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    let total_count = history_db::count_entries(conn, &filter_clauses, &filter_refs)?;
+
+    let sort = match sort {
+        Some(SortKey::Relevance) | None if fts.is_some() => SortKey::Relevance,
+        Some(SortKey::Relevance) | None => SortKey::Date,
+        Some(key) => key,
+    };
+    let descending = descending.unwrap_or_else(|| sort.default_descending());
+    let cursor = cursor.map(Cursor::decode).transpose()?;
+
+    let mut offset = 0;
+    if let Some(ref cursor) = cursor {
+        if sort == SortKey::Relevance {
+            offset = cursor.offset;
+        } else {
+            // Resume strictly after the last row of the previous page
+            let value = || -> Result<Box<dyn ToSql>, String> {
+                match (sort, &cursor.value) {
+                    (SortKey::Size | SortKey::AnnotationCount, serde_json::Value::Number(n)) => {
+                        Ok(Box::new(n.as_i64().unwrap_or_default()))
+                    }
+                    (SortKey::Date | SortKey::Ticket, serde_json::Value::String(s)) => {
+                        Ok(Box::new(s.clone()))
+                    }
+                    _ => Err("History cursor does not match the sort order".to_string()),
+                }
+            };
+            conditions.push(format!(
+                "({expr} {op} ? OR ({expr} = ? AND s.id {op} ?))",
+                expr = sort.expression(),
+                op = if descending { "<" } else { ">" }
+            ));
+            params.push(value()?);
+            params.push(value()?);
+            params.push(Box::new(cursor.id.clone()));
+        }
+    }
+
+    let mut clauses = joins;
+    if !conditions.is_empty() {
+        clauses.push_str(&format!("WHERE {} ", conditions.join(" AND ")));
+    }
+    let direction = if descending { "DESC" } else { "ASC" };
+    if sort == SortKey::Relevance {
+        clauses.push_str(&format!(
+            "ORDER BY {}, s.created_at DESC, s.id DESC ",
+            RANK_EXPRESSION
+        ));
+    } else {
+        clauses.push_str(&format!(
+            "ORDER BY {expr} {dir}, s.id {dir} ",
+            expr = sort.expression(),
+            dir = direction
+        ));
+    }
+    // Fetch one extra row to learn whether another page follows
+    clauses.push_str("LIMIT ? OFFSET ?");
+    params.push(Box::new(limit as i64 + 1));
+    params.push(Box::new(offset as i64));
+
+    let param_refs: Vec<&dyn ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    let mut items = history_db::query_entries(conn, &clauses, &param_refs)?;
+
+    let next_cursor = if items.len() > limit {
+        items.truncate(limit);
+        let last = items.last();
+        Some(
+            Cursor {
+                offset: offset + limit,
+                value: last.map(|m| sort.cursor_value(m)).unwrap_or_default(),
+                id: last.map(|m| m.id.clone()).unwrap_or_default(),
+            }
+            .encode(),
+        )
+    } else {
+        None
+    };
+
+    Ok(SearchPage {
+        items,
+        total_count,
+        next_cursor,
+    })
+}
+
+fn has_condition(filter: HasFilter) -> &'static str {
+    match filter {
+        HasFilter::Redaction => "s.redaction_count > 0",
+        HasFilter::Upload => "s.uploaded_url IS NOT NULL",
+        HasFilter::Ticket => "s.ticket_id IS NOT NULL AND s.ticket_id != ''",
+        HasFilter::Annotations => "s.annotation_count > 0",
+        HasFilter::Pinned => "s.pinned != 0",
+    }
+}
+
+/// An entry's annotations, or `None` when it has no readable
+/// `annotations.json`
+fn read_annotations(entry_dir: &Path) -> Option<Vec<Annotation>> {
+    history_crypto::read_to_string(&entry_dir.join("annotations.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+}
+
+/// (Re)build the search document for an entry from its files and metadata
+pub(crate) fn index_entry(
+    conn: &Connection,
+    entry_dir: &Path,
+    meta: &ScreenshotMeta,
+) -> Result<(), String> {
+    let annotation_text = read_annotations(entry_dir)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|annotation| match annotation {
+            Annotation::Text { text, .. } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let doc = SearchDocument {
+        ticket_id: meta.ticket_id.clone().unwrap_or_default(),
+        annotation_text,
+        ocr_text: history_crypto::read_to_string(&entry_dir.join(OCR_TEXT_FILE_NAME))
+            .unwrap_or_default(),
+        notes: history_fields::search_text(meta),
+        tags: meta.tags.join(" "),
+        comments: meta
+            .uploads
+            .iter()
+            .filter(|upload| upload.success)
+            .filter_map(|upload| upload.comment.as_deref())
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+
+    history_db::replace_search_document(conn, &meta.id, &doc)
+}
+
+/// Index every entry, backfilling redaction counts from `annotations.json`.
+/// Used when the search index is first created and after a legacy import.
+pub(crate) fn reindex_all(conn: &Connection, history_dir: &Path) -> Result<(), String> {
+    for mut meta in history_db::list_oldest_first(conn)? {
+        let entry_dir = history_dir.join(&meta.id);
+
+        if let Some(annotations) = read_annotations(&entry_dir) {
+            let redaction_count = annotations.iter().filter(|a| a.is_redaction()).count();
+            if redaction_count != meta.redaction_count {
+                meta.redaction_count = redaction_count;
+                history_db::upsert(conn, &meta)?;
+            }
+        }
+
+        index_entry(conn, &entry_dir, &meta)?;
+    }
+
+    Ok(())
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fts_match_expression, parse_query, reindex_all, search, search_page, HasFilter,
+        SearchDocument, SearchQuery, SortKey,
+    };
+    use crate::history::ScreenshotMeta;
+    use crate::history_db;
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    fn ids(results: Vec<ScreenshotMeta>) -> Vec<String> {
+        results.into_iter().map(|m| m.id).collect()
+    }
+
+    #[test]
+    fn parse_query_extracts_filters_and_phrases() {
+        let query =
+            parse_query("\"login failed\" vpn ticket:PROJ-12 after:2026-09-01 has:redaction tag:urgent in:\"Incident 42\" field:Severity=high field:customer")
+                .unwrap();
+        assert_eq!(
+            query,
+            SearchQuery {
+                terms: vec!["login failed".to_string(), "vpn".to_string()],
+                ticket: Some("PROJ-12".to_string()),
+                after: Some("2026-09-01".to_string()),
+                before: None,
+                has: vec![HasFilter::Redaction],
+                lacks: Vec::new(),
+                tags: vec!["urgent".to_string()],
+                collections: vec!["Incident 42".to_string()],
+                fields: vec![
+                    ("severity".to_string(), Some("high".to_string())),
+                    ("customer".to_string(), None),
+                ],
+            }
+        );
+
+        assert_eq!(
+            parse_query("https://example.com").unwrap().terms,
+            vec!["https://example.com"]
+        );
+        assert!(parse_query("after:yesterday").is_err());
+        assert!(parse_query("has:unicorns").is_err());
+        assert_eq!(
+            parse_query("no:upload").unwrap().lacks,
+            vec![HasFilter::Upload]
+        );
+    }
+
+    #[test]
+    fn fts_expression_quotes_terms_as_prefixes() {
+        assert_eq!(
+            fts_match_expression(&["err".to_string(), "say \"hi\"".to_string()]).unwrap(),
+            "\"err\"* AND \"say \"\"hi\"\"\"*"
+        );
+        assert!(fts_match_expression(&[]).is_none());
+    }
+
+    #[test]
+    fn reindex_reads_text_labels_and_redactions() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let conn = history_db::open_in_memory().unwrap();
+        history_db::upsert(&conn, &ScreenshotMeta::test_entry("a")).unwrap();
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::write(
+            dir.join("a").join("annotations.json"),
+            r##"[
+                {"id": "1", "type": "text", "color": "#000000", "thickness": 2, "createdAt": 1,
+                 "position": {"x": 5, "y": 5}, "text": "Click here", "fontSize": 24},
+                {"id": "2", "type": "redact", "color": "#000000", "thickness": 0, "createdAt": 2,
+                 "origin": {"x": 0, "y": 0}, "width": 10, "height": 10,
+                 "style": "blackbox", "reason": "email"},
+                {"id": "3", "type": "arrow", "color": "#FF0000", "thickness": 3, "createdAt": 3,
+                 "start": {"x": 0, "y": 0}, "end": {"x": 9, "y": 9}}
+            ]"##,
+        )
+        .unwrap();
+
+        reindex_all(&conn, &dir).unwrap();
+        assert_eq!(
+            history_db::find(&conn, "a")
+                .unwrap()
+                .unwrap()
+                .redaction_count,
+            1
+        );
+        let found = search(&conn, &parse_query("click").unwrap(), 20).unwrap();
+        assert_eq!(ids(found), vec!["a"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn search_ranks_text_matches_and_applies_filters() {
+        let conn = history_db::open_in_memory().unwrap();
+
+        let redacted = ScreenshotMeta {
+            ticket_id: Some("PROJ-12".to_string()),
+            redaction_count: 1,
+            tags: vec!["urgent".to_string()],
+            ..ScreenshotMeta::test_entry("a")
+        };
+        let plain = ScreenshotMeta {
+            created_at: "2026-09-05T10:00:00+00:00".to_string(),
+            ticket_id: Some("OPS-7".to_string()),
+            tags: vec!["urgent".to_string(), "network".to_string()],
+            collections: vec!["Incident 42".to_string()],
+            fields: BTreeMap::from([("severity".to_string(), "High".to_string())]),
+            ..ScreenshotMeta::test_entry("b")
+        };
+        let untitled = ScreenshotMeta {
+            created_at: "2026-09-10T10:00:00+00:00".to_string(),
+            ..ScreenshotMeta::test_entry("c")
+        };
+
+        for (entry, ocr) in [
+            (&redacted, "Login failed: invalid password"),
+            (&plain, "VPN disconnected"),
+            (&untitled, "Logout successful"),
+        ] {
+            history_db::upsert(&conn, entry).unwrap();
+            history_db::replace_search_document(
+                &conn,
+                &entry.id,
+                &SearchDocument {
+                    ticket_id: entry.ticket_id.clone().unwrap_or_default(),
+                    ocr_text: ocr.to_string(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        }
+
+        let run = |q: &str| ids(search(&conn, &parse_query(q).unwrap(), 20).unwrap());
+
+        assert_eq!(run(""), vec!["c", "b", "a"]);
+        let mut prefix_matches = run("log");
+        prefix_matches.sort();
+        assert_eq!(prefix_matches, vec!["a", "c"]);
+        assert_eq!(run("logi"), vec!["a"]);
+        assert_eq!(run("ticket:proj"), vec!["a"]);
+        assert_eq!(run("has:redaction"), vec!["a"]);
+        assert_eq!(run("has:ticket after:2026-09-02"), vec!["b"]);
+        assert_eq!(run("before:2026-09-05"), vec!["a"]);
+        assert!(run("ops-7 has:redaction").is_empty());
+        assert_eq!(run("ops"), vec!["b"]);
+        assert_eq!(run("tag:URGENT"), vec!["b", "a"]);
+        assert_eq!(run("tag:urgent tag:network"), vec!["b"]);
+        assert_eq!(run("collection:\"incident 42\""), vec!["b"]);
+        assert_eq!(run("field:severity=hi"), vec!["b"]);
+        assert_eq!(run("field:severity"), vec!["b"]);
+        assert!(run("field:severity=low").is_empty());
+    }
+
+    #[test]
+    fn search_page_walks_sorted_pages_with_cursors() {
+        let conn = history_db::open_in_memory().unwrap();
+        for (id, day, size, ticket) in [
+            ("a", 1, 300, Some("OPS-2")),
+            ("b", 2, 100, None),
+            ("c", 3, 300, Some("ops-1")),
+            ("d", 4, 200, Some("PROJ-9")),
+            ("e", 5, 500, None),
+        ] {
+            let entry = ScreenshotMeta {
+                created_at: format!("2026-09-0{}T10:00:00+00:00", day),
+                ticket_id: ticket.map(str::to_string),
+                size_bytes: size,
+                ..ScreenshotMeta::test_entry(id)
+            };
+            history_db::upsert(&conn, &entry).unwrap();
+        }
+
+        let walk = |q: &str, sort: SortKey, descending: Option<bool>| {
+            let query = parse_query(q).unwrap();
+            let mut seen = Vec::new();
+            let mut cursor: Option<String> = None;
+            loop {
+                let page = search_page(&conn, &query, Some(sort), descending, cursor.as_deref(), 2)
+                    .unwrap();
+                assert!(page.items.len() <= 2);
+                seen.extend(ids(page.items));
+                match page.next_cursor {
+                    Some(next) => cursor = Some(next),
+                    None => return (seen, page.total_count),
+                }
+            }
+        };
+
+        assert_eq!(walk("", SortKey::Date, None), (ids_of("edcba"), 5));
+        assert_eq!(walk("", SortKey::Date, Some(false)), (ids_of("abcde"), 5));
+        // Equal sizes fall back to id order, so no row is skipped or repeated
+        assert_eq!(walk("", SortKey::Size, None), (ids_of("ecadb"), 5));
+        // Tickets sort A-Z by default, ignoring case, with untracked entries first
+        assert_eq!(walk("", SortKey::Ticket, None), (ids_of("becad"), 5));
+        assert_eq!(
+            walk("has:ticket", SortKey::Size, Some(false)),
+            (ids_of("dac"), 3)
+        );
+        assert_eq!(walk("no:ticket", SortKey::Date, None), (ids_of("eb"), 2));
+
+        let query = parse_query("").unwrap();
+        assert!(search_page(&conn, &query, None, None, Some("not a cursor"), 2).is_err());
+    }
+
+    fn ids_of(letters: &str) -> Vec<String> {
+        letters.chars().map(|c| c.to_string()).collect()
+    }
+}
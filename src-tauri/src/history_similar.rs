@@ -0,0 +1,223 @@
+use image::imageops::FilterType;
+use image::DynamicImage;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::history::{self, ScreenshotMeta};
+use crate::history_crypto;
+use crate::history_db;
+
+/// Hashes differing in more bits than this are not shown as similar
+const DEFAULT_MAX_DISTANCE: u32 = 12;
+const HASH_BITS: u32 = 64;
+
+/// What to compare history against: a saved entry or an image on disk,
+/// such as a capture that has not been saved yet
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct SimilarQuery {
+    pub id: Option<String>,
+    pub image_path: Option<String>,
+    pub limit: Option<u32>,
+    /// Largest number of differing hash bits to include, out of 64
+    pub max_distance: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SimilarEntry {
+    pub entry: ScreenshotMeta,
+    /// Differing hash bits; 0 means visually identical
+    pub distance: u32,
+    /// `1.0 - distance / 64`
+    pub similarity: f64,
+}
+
+/// History entries ranked by how closely their original looks like the
+/// query image, most similar first
+#[tauri::command]
+pub async fn find_similar(query: SimilarQuery) -> Result<Vec<SimilarEntry>, String> {
+    let conn = history_db::open(&history::get_history_dir()?)?;
+    let limit = query.limit.unwrap_or(20).clamp(1, 200) as usize;
+    let max_distance = query
+        .max_distance
+        .unwrap_or(DEFAULT_MAX_DISTANCE)
+        .min(HASH_BITS);
+
+    let (target, exclude) = match (query.id, query.image_path) {
+        (Some(id), _) => {
+            let id = history::parse_history_id(&id)?.to_string();
+            let meta = history_db::find(&conn, &id)?
+                .ok_or_else(|| format!("History item not found: {}", id))?;
+            let hash = stored_hash(&conn, &meta)?
+                .ok_or_else(|| "The entry's original image could not be read".to_string())?;
+            (hash, Some(id))
+        }
+        (None, Some(path)) => {
+            let image = history_crypto::open_image(Path::new(&path))
+                .map_err(|e| format!("Failed to open image: {}", e))?;
+            (perceptual_hash(&image), None)
+        }
+        (None, None) => return Err("Pass an entry id or an image path".to_string()),
+    };
+
+    backfill(&conn)?;
+    let matches = rank(
+        &load_hashes(&conn)?,
+        target,
+        exclude.as_deref(),
+        max_distance,
+    );
+
+    let mut similar = Vec::new();
+    for (id, distance) in matches.into_iter().take(limit) {
+        if let Some(entry) = history_db::find(&conn, &id)? {
+            similar.push(SimilarEntry {
+                entry,
+                distance,
+                similarity: 1.0 - distance as f64 / HASH_BITS as f64,
+            });
+        }
+    }
+
+    Ok(similar)
+}
+
+/// Difference hash: one bit per neighbouring pixel pair of a 9x8 grayscale
+/// thumbnail, set when brightness increases to the right. Robust to
+/// scaling and compression, so resized captures of the same dialog match.
+pub(crate) fn perceptual_hash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// Hash an entry's original and store it. An unreadable original is
+/// recorded too, so it is not retried on every search.
+pub(crate) fn index_entry(conn: &Connection, meta: &ScreenshotMeta) -> Result<Option<u64>, String> {
+    let hash = history_crypto::open_image(Path::new(&meta.original_path))
+        .ok()
+        .map(|image| perceptual_hash(&image));
+
+    conn.execute(
+        "INSERT INTO image_hashes (screenshot_id, phash) VALUES (?1, ?2)
+         ON CONFLICT(screenshot_id) DO UPDATE SET phash = excluded.phash",
+        params![meta.id, hash.map(|hash| hash as i64)],
+    )
+    .map_err(|e| format!("Failed to store image hash: {}", e))?;
+
+    Ok(hash)
+}
+
+/// The stored hash of an entry, computing it first if needed
+fn stored_hash(conn: &Connection, meta: &ScreenshotMeta) -> Result<Option<u64>, String> {
+    let stored: Option<Option<i64>> = conn
+        .query_row(
+            "SELECT phash FROM image_hashes WHERE screenshot_id = ?1",
+            params![meta.id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to read image hash: {}", e))?;
+
+    match stored {
+        Some(hash) => Ok(hash.map(|hash| hash as u64)),
+        None => index_entry(conn, meta),
+    }
+}
+
+/// Hash entries saved before hashing existed, or restored from the trash
+fn backfill(conn: &Connection) -> Result<(), String> {
+    let missing = history_db::query_entries(
+        conn,
+        "LEFT JOIN image_hashes h ON h.screenshot_id = s.id
+         WHERE h.screenshot_id IS NULL ORDER BY s.created_at ASC",
+        &[],
+    )?;
+    for meta in &missing {
+        index_entry(conn, meta)?;
+    }
+
+    Ok(())
+}
+
+fn load_hashes(conn: &Connection) -> Result<Vec<(String, u64)>, String> {
+    let mut stmt = conn
+        .prepare("SELECT screenshot_id, phash FROM image_hashes WHERE phash IS NOT NULL")
+        .map_err(|e| format!("Failed to read image hashes: {}", e))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+        })
+        .map_err(|e| format!("Failed to read image hashes: {}", e))?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read image hashes: {}", e))
+}
+
+/// Ids within `max_distance` of `target`, closest first
+fn rank(
+    hashes: &[(String, u64)],
+    target: u64,
+    exclude: Option<&str>,
+    max_distance: u32,
+) -> Vec<(String, u32)> {
+    let mut matches: Vec<(String, u32)> = hashes
+        .iter()
+        .filter(|(id, _)| Some(id.as_str()) != exclude)
+        .map(|(id, hash)| (id.clone(), (hash ^ target).count_ones()))
+        .filter(|(_, distance)| *distance <= max_distance)
+        .collect();
+    matches.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{perceptual_hash, rank};
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    /// A light window with a dark title bar and a dark button
+    fn dialog(width: u32, height: u32, button_x: f32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            let (fx, fy) = (x as f32 / width as f32, y as f32 / height as f32);
+            let title_bar = fy < 0.15;
+            let button = fy > 0.7 && fy < 0.85 && fx > button_x && fx < button_x + 0.2;
+            if title_bar || button {
+                Rgba([40, 40, 40, 255])
+            } else {
+                Rgba([((1.0 - fx) * 200.0) as u8 + 40, 230, 230, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn rescaled_captures_match_and_different_layouts_do_not() {
+        let original = perceptual_hash(&dialog(800, 600, 0.7));
+        let rescaled = perceptual_hash(&dialog(400, 300, 0.7));
+        let different = perceptual_hash(&dialog(800, 600, 0.1));
+
+        assert!((original ^ rescaled).count_ones() <= 2);
+        assert!((original ^ different).count_ones() > 2);
+    }
+
+    #[test]
+    fn rank_orders_by_distance_and_skips_the_query_entry() {
+        let hashes = vec![
+            ("self".to_string(), 0b0000),
+            ("far".to_string(), 0b1111),
+            ("near".to_string(), 0b0001),
+        ];
+
+        let ranked = rank(&hashes, 0b0000, Some("self"), 3);
+        assert_eq!(ranked, vec![("near".to_string(), 1)]);
+    }
+}
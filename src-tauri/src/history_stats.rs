@@ -0,0 +1,455 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::history;
+use crate::history_db;
+
+const CSV_HEADER: &str =
+    "section,key,captures,redacted_captures,redactions,upload_attempts,uploads_succeeded,uploads_failed";
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct StatsRequest {
+    /// `day` or `week` (the default); weeks start on Monday
+    pub period: Option<String>,
+    /// Inclusive start date, `YYYY-MM-DD`
+    pub after: Option<String>,
+    /// Exclusive end date, `YYYY-MM-DD`
+    pub before: Option<String>,
+}
+
+/// Counts for one group of history. Captures are counted by when they were
+/// taken and uploads by when they were attempted, both by local date.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+pub struct StatsRow {
+    /// Period start date, service, ticket id or `total`
+    pub key: String,
+    /// For services, the entries with at least one upload attempt there
+    pub captures: usize,
+    /// Captures with at least one redaction
+    pub redacted_captures: usize,
+    pub redactions: usize,
+    pub upload_attempts: usize,
+    pub uploads_succeeded: usize,
+    pub uploads_failed: usize,
+}
+
+impl StatsRow {
+    fn add_capture(&mut self, capture: &Capture) {
+        self.captures += 1;
+        self.redactions += capture.redactions;
+        if capture.redactions > 0 {
+            self.redacted_captures += 1;
+        }
+    }
+
+    fn add_upload(&mut self, upload: &Upload) {
+        self.upload_attempts += 1;
+        if upload.success {
+            self.uploads_succeeded += 1;
+        } else {
+            self.uploads_failed += 1;
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryStats {
+    pub period: String,
+    pub totals: StatsRow,
+    /// Oldest first, only periods with activity
+    pub periods: Vec<StatsRow>,
+    /// One row per ticket system uploaded to
+    pub services: Vec<StatsRow>,
+    /// Busiest tickets first
+    pub tickets: Vec<StatsRow>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Period {
+    Day,
+    Week,
+}
+
+impl Period {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "day" => Ok(Period::Day),
+            "week" => Ok(Period::Week),
+            _ => Err(format!("Unknown period '{}': expected day or week", value)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Period::Day => "day",
+            Period::Week => "week",
+        }
+    }
+
+    fn start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Day => date,
+            Period::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+        }
+    }
+}
+
+/// What the statistics are computed from
+struct Capture {
+    created_at: DateTime<Utc>,
+    ticket_id: Option<String>,
+    redactions: usize,
+}
+
+struct Upload {
+    screenshot_id: String,
+    service: String,
+    ticket_id: String,
+    uploaded_at: DateTime<Utc>,
+    success: bool,
+}
+
+/// Captures, redactions and uploads per day or week, ticket system and
+/// ticket
+#[tauri::command]
+pub async fn get_history_stats(request: StatsRequest) -> Result<HistoryStats, String> {
+    let conn = history_db::open(&history::get_history_dir()?)?;
+    compute(&conn, &request, &Local)
+}
+
+/// Write the statistics as CSV for spreadsheets and return the file path.
+/// Every group is one row, with `section` saying which grouping it is from.
+#[tauri::command]
+pub async fn export_history_stats(
+    request: StatsRequest,
+    output_path: Option<String>,
+) -> Result<String, String> {
+    let conn = history_db::open(&history::get_history_dir()?)?;
+    let stats = compute(&conn, &request, &Local)?;
+
+    let path = match output_path {
+        Some(path) => PathBuf::from(path),
+        None => std::env::temp_dir().join(format!(
+            "history_stats_{}.csv",
+            Utc::now().format("%Y%m%d_%H%M%S")
+        )),
+    };
+    fs::write(&path, to_csv(&stats)).map_err(|e| format!("Failed to write statistics: {}", e))?;
+
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Statistics with days and weeks on the calendar of `tz`, which is the
+/// machine's local time outside tests
+fn compute<Tz: TimeZone>(
+    conn: &Connection,
+    request: &StatsRequest,
+    tz: &Tz,
+) -> Result<HistoryStats, String> {
+    let period = Period::parse(request.period.as_deref().unwrap_or("week"))?;
+    let parse_date = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(|value| {
+                NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .map_err(|_| format!("Invalid date '{}': expected YYYY-MM-DD", value))
+            })
+            .transpose()
+    };
+    let (after, before) = (parse_date(&request.after)?, parse_date(&request.before)?);
+    let in_range = |time: &DateTime<Utc>| {
+        let date = time.with_timezone(tz).date_naive();
+        after.is_none_or(|after| date >= after) && before.is_none_or(|before| date < before)
+    };
+
+    let captures: Vec<Capture> = load_captures(conn)?
+        .into_iter()
+        .filter(|capture| in_range(&capture.created_at))
+        .collect();
+    let uploads: Vec<Upload> = load_uploads(conn)?
+        .into_iter()
+        .filter(|upload| in_range(&upload.uploaded_at))
+        .collect();
+
+    Ok(aggregate(period, &captures, &uploads, tz))
+}
+
+fn aggregate<Tz: TimeZone>(
+    period: Period,
+    captures: &[Capture],
+    uploads: &[Upload],
+    tz: &Tz,
+) -> HistoryStats {
+    let mut totals = StatsRow {
+        key: "total".to_string(),
+        ..Default::default()
+    };
+    let mut periods: BTreeMap<String, StatsRow> = BTreeMap::new();
+    let mut services: BTreeMap<String, StatsRow> = BTreeMap::new();
+    let mut tickets: BTreeMap<String, StatsRow> = BTreeMap::new();
+
+    for capture in captures {
+        let date = capture.created_at.with_timezone(tz).date_naive();
+        let period_key = period.start(date).to_string();
+        totals.add_capture(capture);
+        group(&mut periods, &period_key).add_capture(capture);
+        if let Some(ticket) = capture.ticket_id.as_deref().filter(|t| !t.is_empty()) {
+            group(&mut tickets, ticket).add_capture(capture);
+        }
+    }
+
+    let mut uploaded_by_service: BTreeMap<&str, HashSet<&str>> = BTreeMap::new();
+    for upload in uploads {
+        uploaded_by_service
+            .entry(&upload.service)
+            .or_default()
+            .insert(&upload.screenshot_id);
+
+        let date = upload.uploaded_at.with_timezone(tz).date_naive();
+        let period_key = period.start(date).to_string();
+        totals.add_upload(upload);
+        group(&mut periods, &period_key).add_upload(upload);
+        group(&mut services, &upload.service).add_upload(upload);
+        if !upload.ticket_id.is_empty() {
+            group(&mut tickets, &upload.ticket_id).add_upload(upload);
+        }
+    }
+    for (service, ids) in uploaded_by_service {
+        if let Some(row) = services.get_mut(service) {
+            row.captures = ids.len();
+        }
+    }
+
+    let mut tickets: Vec<StatsRow> = tickets.into_values().collect();
+    tickets.sort_by(|a, b| {
+        (b.captures + b.upload_attempts)
+            .cmp(&(a.captures + a.upload_attempts))
+            .then_with(|| a.key.cmp(&b.key))
+    });
+
+    HistoryStats {
+        period: period.name().to_string(),
+        totals,
+        periods: periods.into_values().collect(),
+        services: services.into_values().collect(),
+        tickets,
+    }
+}
+
+fn group<'a>(rows: &'a mut BTreeMap<String, StatsRow>, key: &str) -> &'a mut StatsRow {
+    rows.entry(key.to_string()).or_insert_with(|| StatsRow {
+        key: key.to_string(),
+        ..Default::default()
+    })
+}
+
+fn load_captures(conn: &Connection) -> Result<Vec<Capture>, String> {
+    let map_err = |e: rusqlite::Error| format!("Failed to read history statistics: {}", e);
+    let mut stmt = conn
+        .prepare("SELECT created_at, ticket_id, redaction_count FROM screenshots")
+        .map_err(map_err)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })
+        .map_err(map_err)?;
+
+    let mut captures = Vec::new();
+    for row in rows {
+        let (created_at, ticket_id, redactions) = row.map_err(map_err)?;
+        // Entries with an unreadable date cannot be placed in a period
+        if let Some(created_at) = parse_time(&created_at) {
+            captures.push(Capture {
+                created_at,
+                ticket_id,
+                redactions: redactions.max(0) as usize,
+            });
+        }
+    }
+
+    Ok(captures)
+}
+
+fn load_uploads(conn: &Connection) -> Result<Vec<Upload>, String> {
+    let map_err = |e: rusqlite::Error| format!("Failed to read history statistics: {}", e);
+    let mut stmt = conn
+        .prepare("SELECT screenshot_id, service, ticket_id, uploaded_at, success FROM uploads")
+        .map_err(map_err)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, bool>(4)?,
+            ))
+        })
+        .map_err(map_err)?;
+
+    let mut uploads = Vec::new();
+    for row in rows {
+        let (screenshot_id, service, ticket_id, uploaded_at, success) = row.map_err(map_err)?;
+        if let Some(uploaded_at) = parse_time(&uploaded_at) {
+            uploads.push(Upload {
+                screenshot_id,
+                service,
+                ticket_id,
+                uploaded_at,
+                success,
+            });
+        }
+    }
+
+    Ok(uploads)
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+fn to_csv(stats: &HistoryStats) -> String {
+    let mut csv = format!("{}\n", CSV_HEADER);
+    let sections = [
+        ("total", std::slice::from_ref(&stats.totals)),
+        (stats.period.as_str(), stats.periods.as_slice()),
+        ("service", stats.services.as_slice()),
+        ("ticket", stats.tickets.as_slice()),
+    ];
+
+    for (section, rows) in sections {
+        for row in rows {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                section,
+                csv_field(&row.key),
+                row.captures,
+                row.redacted_captures,
+                row.redactions,
+                row.upload_attempts,
+                row.uploads_succeeded,
+                row.uploads_failed
+            ));
+        }
+    }
+
+    csv
+}
+
+/// Quote a field when needed, and keep spreadsheets from reading a ticket
+/// id such as `=HYPERLINK(...)` as a formula
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aggregate, parse_time, to_csv, Capture, Period, Upload};
+    use chrono::{FixedOffset, Utc};
+
+    fn capture(created_at: &str, ticket: Option<&str>, redactions: usize) -> Capture {
+        Capture {
+            created_at: parse_time(created_at).unwrap(),
+            ticket_id: ticket.map(str::to_string),
+            redactions,
+        }
+    }
+
+    fn upload(id: &str, service: &str, ticket: &str, uploaded_at: &str, success: bool) -> Upload {
+        Upload {
+            screenshot_id: id.to_string(),
+            service: service.to_string(),
+            ticket_id: ticket.to_string(),
+            uploaded_at: parse_time(uploaded_at).unwrap(),
+            success,
+        }
+    }
+
+    #[test]
+    fn aggregates_by_week_service_and_ticket() {
+        // Sunday 2026-10-11 belongs to the week starting Monday 2026-10-05
+        let captures = vec![
+            capture("2026-10-11T23:00:00+00:00", Some("ENG-1"), 2),
+            capture("2026-10-12T08:00:00+00:00", Some("ENG-1"), 0),
+            capture("2026-10-13T08:00:00+02:00", None, 1),
+        ];
+        let uploads = vec![
+            upload("a", "jira", "ENG-1", "2026-10-12T09:00:00+00:00", false),
+            upload("a", "jira", "ENG-1", "2026-10-12T09:05:00+00:00", true),
+            upload("b", "zendesk", "4521", "2026-10-14T10:00:00+00:00", true),
+        ];
+
+        let stats = aggregate(Period::Week, &captures, &uploads, &Utc);
+        assert_eq!(stats.totals.captures, 3);
+        assert_eq!(stats.totals.redacted_captures, 2);
+        assert_eq!(stats.totals.redactions, 3);
+        assert_eq!(
+            (stats.totals.upload_attempts, stats.totals.uploads_succeeded),
+            (3, 2)
+        );
+
+        let weeks: Vec<(&str, usize, usize)> = stats
+            .periods
+            .iter()
+            .map(|row| (row.key.as_str(), row.captures, row.upload_attempts))
+            .collect();
+        assert_eq!(weeks, vec![("2026-10-05", 1, 0), ("2026-10-12", 2, 3)]);
+
+        assert_eq!(stats.services[0].key, "jira");
+        assert_eq!(stats.services[0].captures, 1);
+        assert_eq!(stats.services[0].uploads_failed, 1);
+        assert_eq!(stats.tickets[0].key, "ENG-1");
+        assert_eq!(stats.tickets[0].captures, 2);
+
+        let days = aggregate(Period::Day, &captures, &uploads, &Utc);
+        assert_eq!(days.periods.len(), 4);
+
+        // Two hours ahead of UTC, the Sunday 23:00 capture is on Monday
+        let ahead = FixedOffset::east_opt(2 * 3600).unwrap();
+        let local_weeks = aggregate(Period::Week, &captures, &uploads, &ahead);
+        assert_eq!(local_weeks.periods.len(), 1);
+        assert_eq!(local_weeks.periods[0].key, "2026-10-12");
+        assert_eq!(local_weeks.periods[0].captures, 3);
+    }
+
+    #[test]
+    fn csv_has_one_row_per_group_and_escapes_keys() {
+        let captures = vec![capture(
+            "2026-10-12T08:00:00+00:00",
+            Some("=cmd|' /C calc'!A0, \"x\""),
+            0,
+        )];
+        let stats = aggregate(Period::Day, &captures, &[], &Utc);
+
+        let csv = to_csv(&stats);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("section,key,"));
+        assert_eq!(lines[1], "total,total,1,0,0,0,0,0");
+        assert_eq!(lines[2], "day,2026-10-12,1,0,0,0,0,0");
+        assert_eq!(
+            lines[3],
+            "ticket,\"'=cmd|' /C calc'!A0, \"\"x\"\"\",1,0,0,0,0,0"
+        );
+    }
+}
@@ -0,0 +1,481 @@
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::blob_store;
+use crate::fs_util::sync_dir;
+use crate::history::{self, ScreenshotMeta};
+use crate::history_db;
+use crate::history_schema;
+use crate::retention;
+
+/// Deleted entries wait here until restored or purged
+pub(crate) const TRASH_DIR_NAME: &str = ".trash";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashedEntry {
+    pub entry: ScreenshotMeta,
+    pub trashed_at: String,
+    /// When automatic purging will delete it for good
+    pub purge_at: String,
+}
+
+#[tauri::command]
+pub async fn list_trash() -> Result<Vec<TrashedEntry>, String> {
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    let trash_days = retention::load_policy(&conn)?.trash_days;
+    purge_expired(&conn, &history_dir, trash_days, Utc::now())?;
+
+    let mut trashed = list_oldest_first(&conn)?;
+    trashed.reverse();
+
+    Ok(trashed
+        .into_iter()
+        .map(|(meta, trashed_at)| TrashedEntry {
+            purge_at: parse_time(&trashed_at)
+                .map(|time| (time + Duration::days(trash_days as i64)).to_rfc3339())
+                .unwrap_or_default(),
+            entry: trashed_view(&history_dir, meta),
+            trashed_at,
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn restore_from_trash(id: String) -> Result<ScreenshotMeta, String> {
+    let normalized_id = history::parse_history_id(&id)?.to_string();
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    restore_entry(&conn, &history_dir, &normalized_id)
+}
+
+/// Delete one trashed entry for good
+#[tauri::command]
+pub async fn purge_from_trash(id: String) -> Result<(), String> {
+    let normalized_id = history::parse_history_id(&id)?.to_string();
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    purge_entry(&conn, &history_dir, &normalized_id)?;
+    blob_store::collect_garbage(&conn, &history_dir)?;
+
+    Ok(())
+}
+
+/// Delete everything in the trash for good, returning how many entries went
+#[tauri::command]
+pub async fn empty_trash() -> Result<usize, String> {
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    let trashed = list_oldest_first(&conn)?;
+    for (meta, _) in &trashed {
+        purge_entry(&conn, &history_dir, &meta.id)?;
+    }
+    blob_store::collect_garbage(&conn, &history_dir)?;
+
+    Ok(trashed.len())
+}
+
+/// Move an entry from the gallery to the trash. Its images stay referenced
+/// so a restore gets them back. Must be called while holding the history
+/// lock.
+pub(crate) fn move_to_trash(conn: &Connection, history_dir: &Path, id: &str) -> Result<(), String> {
+    // Index first: a crash before the move leaves an unindexed directory
+    // that repair puts back in the gallery
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| format!("Failed to start moving to trash: {}", e))?;
+    trash_in_index(&tx, id)?;
+    tx.commit()
+        .map_err(|e| format!("Failed to move history entry to trash: {}", e))?;
+
+    trash_files(history_dir, id)
+}
+
+/// The index half of `move_to_trash`, for callers that trash several
+/// entries in one transaction and call `trash_files` once it commits
+pub(crate) fn trash_in_index(conn: &Connection, id: &str) -> Result<(), String> {
+    let meta =
+        history_db::find(conn, id)?.ok_or_else(|| format!("History item not found: {}", id))?;
+    let stored = history_db::portable(conn, &meta);
+    let meta_json = serde_json::to_string(&history_schema::versioned(&stored))
+        .map_err(|e| format!("Failed to serialize metadata: {}", e))?;
+
+    // Updated in place rather than replaced, which would skip the triggers
+    // that keep the storage total
+    conn.execute(
+        "INSERT INTO trash (id, meta_json, trashed_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(id) DO UPDATE SET
+             meta_json = excluded.meta_json, trashed_at = excluded.trashed_at",
+        params![meta.id, meta_json, Utc::now().to_rfc3339()],
+    )
+    .map_err(|e| format!("Failed to move history entry to trash: {}", e))?;
+    blob_store::sync_trash_refs(conn, &meta)?;
+    update_local_bytes(conn, id)?;
+    history_db::delete(conn, id)
+}
+
+/// Move a trashed entry's directory into the trash directory
+pub(crate) fn trash_files(history_dir: &Path, id: &str) -> Result<(), String> {
+    let entry_dir = history_dir.join(id);
+    if entry_dir.is_dir() {
+        let trash_dir = history_dir.join(TRASH_DIR_NAME);
+        fs::create_dir_all(&trash_dir)
+            .map_err(|e| format!("Failed to create trash directory: {}", e))?;
+        let dest = trash_dir.join(id);
+        if dest.exists() {
+            fs::remove_dir_all(&dest)
+                .map_err(|e| format!("Failed to replace trashed entry: {}", e))?;
+        }
+        fs::rename(&entry_dir, &dest)
+            .map_err(|e| format!("Failed to move screenshot to trash: {}", e))?;
+        sync_dir(history_dir).map_err(|e| format!("Failed to sync history directory: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Trashed entries with when they were trashed, oldest first. Entries
+/// whose metadata cannot be read are left out rather than failing the
+/// whole list; `list_unreadable` reports them.
+pub(crate) fn list_oldest_first(
+    conn: &Connection,
+) -> Result<Vec<(ScreenshotMeta, String)>, String> {
+    Ok(load_rows(conn)?
+        .into_iter()
+        .filter_map(|(_, meta, trashed_at)| meta.ok().map(|meta| (meta, trashed_at)))
+        .collect())
+}
+
+/// Ids of trashed entries whose metadata cannot be read, with the reason
+pub(crate) fn list_unreadable(conn: &Connection) -> Result<Vec<(String, String)>, String> {
+    Ok(load_rows(conn)?
+        .into_iter()
+        .filter_map(|(id, meta, _)| meta.err().map(|reason| (id, reason)))
+        .collect())
+}
+
+type TrashRow = (String, Result<ScreenshotMeta, String>, String);
+
+fn load_rows(conn: &Connection) -> Result<Vec<TrashRow>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, meta_json, trashed_at FROM trash ORDER BY trashed_at ASC, id ASC")
+        .map_err(|e| format!("Failed to read trash: {}", e))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })
+        .map_err(|e| format!("Failed to read trash: {}", e))?;
+
+    let mut trashed = Vec::new();
+    for row in rows {
+        let (id, meta_json, trashed_at) =
+            row.map_err(|e| format!("Failed to read trash: {}", e))?;
+        let meta =
+            history_schema::parse_meta(&meta_json).map(|meta| history_db::resolved(conn, meta));
+        trashed.push((id, meta, trashed_at));
+    }
+
+    Ok(trashed)
+}
+
+fn find(conn: &Connection, id: &str) -> Result<Option<ScreenshotMeta>, String> {
+    let meta_json: Option<String> = conn
+        .query_row(
+            "SELECT meta_json FROM trash WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to read trash: {}", e))?;
+
+    meta_json
+        .map(|json| {
+            history_schema::parse_meta(&json)
+                .map(|meta| history_db::resolved(conn, meta))
+                .map_err(|e| format!("Failed to read trashed entry: {}", e))
+        })
+        .transpose()
+}
+
+/// Whether the trash has a row for `id`, readable or not
+fn is_trashed(conn: &Connection, id: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM trash WHERE id = ?1)",
+        params![id],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to read trash: {}", e))
+}
+
+/// Put a trashed entry back in the gallery with its labels and uploads.
+/// Must be called while holding the history lock.
+fn restore_entry(
+    conn: &Connection,
+    history_dir: &Path,
+    id: &str,
+) -> Result<ScreenshotMeta, String> {
+    let meta = find(conn, id)?.ok_or_else(|| format!("Trashed item not found: {}", id))?;
+    let entry_dir = history_dir.join(id);
+    if entry_dir.exists() {
+        return Err(format!("History item already exists: {}", id));
+    }
+
+    let trashed_dir = history_dir.join(TRASH_DIR_NAME).join(id);
+    fs::rename(&trashed_dir, &entry_dir)
+        .map_err(|e| format!("Failed to restore screenshot from trash: {}", e))?;
+    sync_dir(history_dir).map_err(|e| format!("Failed to sync history directory: {}", e))?;
+
+    history_db::upsert(conn, &meta)?;
+    conn.execute("DELETE FROM trash WHERE id = ?1", params![id])
+        .map_err(|e| format!("Failed to restore history entry: {}", e))?;
+
+    history::refresh_entry(conn, history_dir, id)
+}
+
+/// Delete a trashed entry for good. Callers collect garbage afterwards.
+/// Must be called while holding the history lock.
+pub(crate) fn purge_entry(conn: &Connection, history_dir: &Path, id: &str) -> Result<(), String> {
+    conn.execute("DELETE FROM trash WHERE id = ?1", params![id])
+        .map_err(|e| format!("Failed to purge trashed entry: {}", e))?;
+
+    let trashed_dir = history_dir.join(TRASH_DIR_NAME).join(id);
+    if trashed_dir.exists() {
+        fs::remove_dir_all(&trashed_dir)
+            .map_err(|e| format!("Failed to delete trashed screenshot: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Purge entries trashed more than `trash_days` ago, returning how many
+/// went. Must be called while holding the history lock.
+pub(crate) fn purge_expired(
+    conn: &Connection,
+    history_dir: &Path,
+    trash_days: u32,
+    now: DateTime<Utc>,
+) -> Result<usize, String> {
+    let expired: Vec<String> = list_oldest_first(conn)?
+        .into_iter()
+        .filter(|(_, trashed_at)| {
+            parse_time(trashed_at)
+                .is_some_and(|time| now - time >= Duration::days(trash_days as i64))
+        })
+        .map(|(meta, _)| meta.id)
+        .collect();
+
+    for id in &expired {
+        purge_entry(conn, history_dir, id)?;
+    }
+    if !expired.is_empty() {
+        blob_store::collect_garbage(conn, history_dir)?;
+    }
+
+    Ok(expired.len())
+}
+
+/// Drop trash rows whose directory is gone and re-add trashed directories
+/// the index lost, e.g. after it was rebuilt. Must be called while holding
+/// the history lock.
+pub(crate) fn reconcile(conn: &Connection, history_dir: &Path) -> Result<(), String> {
+    let trash_dir = history_dir.join(TRASH_DIR_NAME);
+
+    for (meta, _) in list_oldest_first(conn)? {
+        if !trash_dir.join(&meta.id).is_dir() {
+            conn.execute("DELETE FROM trash WHERE id = ?1", params![meta.id])
+                .map_err(|e| format!("Failed to clean up trash: {}", e))?;
+        }
+    }
+
+    if !trash_dir.is_dir() {
+        return Ok(());
+    }
+    for dir in fs::read_dir(&trash_dir)
+        .map_err(|e| format!("Failed to read trash directory: {}", e))?
+        .flatten()
+    {
+        let id = dir.file_name().to_string_lossy().to_string();
+        if is_trashed(conn, &id)? {
+            continue;
+        }
+
+        let meta = history::read_meta(history_dir, &dir.path()).ok();
+        let Some(meta) = meta.filter(|meta| meta.id == id) else {
+            continue;
+        };
+
+        // The original trash time is lost, so the purge period starts over
+        let stored = history_db::portable(conn, &meta);
+        let meta_json = serde_json::to_string(&history_schema::versioned(&stored))
+            .map_err(|e| format!("Failed to serialize metadata: {}", e))?;
+        conn.execute(
+            "INSERT INTO trash (id, meta_json, trashed_at) VALUES (?1, ?2, ?3)",
+            params![meta.id, meta_json, Utc::now().to_rfc3339()],
+        )
+        .map_err(|e| format!("Failed to re-add trashed entry: {}", e))?;
+        blob_store::sync_trash_refs(conn, &meta)?;
+        update_local_bytes(conn, &id)?;
+    }
+
+    Ok(())
+}
+
+/// Correct trashed entry sizes from their directories in the trash
+pub(crate) fn rescan_sizes(conn: &Connection, history_dir: &Path) -> Result<(), String> {
+    for (meta, _) in list_oldest_first(conn)? {
+        let trashed_dir = history_dir.join(TRASH_DIR_NAME).join(&meta.id);
+        let size = if trashed_dir.is_dir() {
+            history::calculate_dir_size(&trashed_dir)?
+        } else {
+            0
+        };
+        conn.execute(
+            "UPDATE trash SET local_bytes = ?1 WHERE id = ?2 AND local_bytes != ?1",
+            params![size as i64, meta.id],
+        )
+        .map_err(|e| format!("Failed to update trash size: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Record what a trashed entry holds beyond its blobs, as `upsert` does
+/// for live entries
+fn update_local_bytes(conn: &Connection, id: &str) -> Result<(), String> {
+    conn.execute(
+        "UPDATE trash SET local_bytes = MAX(0, COALESCE(json_extract(meta_json, '$.size_bytes'), 0)
+             - COALESCE((SELECT SUM(b.size_bytes) FROM trash_blob_refs r
+                 JOIN blobs b ON b.hash = r.hash WHERE r.screenshot_id = trash.id), 0))
+         WHERE id = ?1",
+        params![id],
+    )
+    .map_err(|e| format!("Failed to update trash size: {}", e))?;
+
+    Ok(())
+}
+
+/// Point paths of entries saved before the blob store, which live in the
+/// entry directory, at the entry's place in the trash
+fn trashed_view(history_dir: &Path, mut meta: ScreenshotMeta) -> ScreenshotMeta {
+    let from = history_dir.join(&meta.id);
+    let to = history_dir.join(TRASH_DIR_NAME).join(&meta.id);
+    let relocate = |path: &mut String| {
+        if let Ok(rest) = Path::new(path.as_str()).strip_prefix(&from) {
+            *path = to.join(rest).to_string_lossy().to_string();
+        }
+    };
+
+    relocate(&mut meta.original_path);
+    relocate(&mut meta.thumbnail_path);
+    if let Some(annotated) = meta.annotated_path.as_mut() {
+        relocate(annotated);
+    }
+    meta
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        list_oldest_first, list_unreadable, move_to_trash, purge_expired, restore_entry,
+        TRASH_DIR_NAME,
+    };
+    use crate::blob_store;
+    use crate::history::ScreenshotMeta;
+    use crate::history_db;
+    use chrono::{Duration, Utc};
+    use std::fs;
+
+    const ENTRY: &str = "4f0e4c1e-0000-4000-8000-000000000001";
+
+    #[test]
+    fn trashed_entries_keep_their_images_until_purged() {
+        let dir = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let entry_dir = dir.join(ENTRY);
+        fs::create_dir_all(&entry_dir).unwrap();
+        let capture = dir.join("capture.png");
+        fs::write(&capture, b"evidence").unwrap();
+        let original = blob_store::store(&dir, &capture).unwrap();
+        fs::write(entry_dir.join("annotations.json"), b"[]").unwrap();
+
+        let meta = ScreenshotMeta {
+            original_path: original.to_string_lossy().to_string(),
+            thumbnail_path: original.to_string_lossy().to_string(),
+            ticket_id: Some("INC-9".to_string()),
+            size_bytes: 8,
+            tags: vec!["vpn".to_string()],
+            ..ScreenshotMeta::test_entry(ENTRY)
+        };
+        let conn = history_db::open_in_memory().unwrap();
+        history_db::upsert(&conn, &meta).unwrap();
+
+        move_to_trash(&conn, &dir, ENTRY).unwrap();
+        assert!(history_db::find(&conn, ENTRY).unwrap().is_none());
+        assert!(dir.join(TRASH_DIR_NAME).join(ENTRY).is_dir());
+        blob_store::collect_garbage(&conn, &dir).unwrap();
+        assert!(original.exists());
+
+        let restored = restore_entry(&conn, &dir, ENTRY).unwrap();
+        assert_eq!(restored.tags, vec!["vpn"]);
+        assert!(entry_dir.is_dir());
+        assert!(list_oldest_first(&conn).unwrap().is_empty());
+
+        move_to_trash(&conn, &dir, ENTRY).unwrap();
+        assert_eq!(purge_expired(&conn, &dir, 30, Utc::now()).unwrap(), 0);
+        assert_eq!(
+            purge_expired(&conn, &dir, 30, Utc::now() + Duration::days(31)).unwrap(),
+            1
+        );
+        assert!(!dir.join(TRASH_DIR_NAME).join(ENTRY).exists());
+        assert!(!original.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_trash_rows_are_reported_instead_of_failing_the_list() {
+        let conn = history_db::open_in_memory().unwrap();
+        let legacy = format!(
+            r#"{{"id": "{}", "original_path": "{}/original.png", "annotated_path": null,
+                "thumbnail_path": "{}/thumbnail.png", "created_at": "2026-09-01T10:00:00+00:00",
+                "ticket_id": null, "uploaded_url": null, "size_bytes": 8, "annotation_count": 0}}"#,
+            ENTRY, ENTRY, ENTRY
+        );
+        let broken = "5a0e4c1e-0000-4000-8000-000000000002";
+        for (id, json) in [(ENTRY, legacy.as_str()), (broken, "{\"id\": 7")] {
+            conn.execute(
+                "INSERT INTO trash (id, meta_json, trashed_at) VALUES (?1, ?2, ?3)",
+                rusqlite::params![id, json, Utc::now().to_rfc3339()],
+            )
+            .unwrap();
+        }
+
+        let trashed = list_oldest_first(&conn).unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(trashed[0].0.id, ENTRY);
+
+        let unreadable = list_unreadable(&conn).unwrap();
+        assert_eq!(unreadable.len(), 1);
+        assert_eq!(unreadable[0].0, broken);
+    }
+}
@@ -0,0 +1,284 @@
+use image::ImageFormat;
+use notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+use crate::blob_store;
+use crate::export;
+use crate::history;
+use crate::history_db;
+
+const SETTINGS_KEY: &str = "watch_folders";
+/// Emitted with a `WatchImportEvent` for every new file seen in a watched
+/// folder
+const IMPORTED_EVENT: &str = "history-imported";
+/// Quiet period before a new file is imported, so the tool that saved it
+/// has finished writing
+const DEBOUNCE: Duration = Duration::from_secs(2);
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg"];
+
+/// The running watcher, replaced whenever the settings change
+static WATCHER: Mutex<Option<Debouncer<RecommendedWatcher>>> = Mutex::new(None);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct WatchSettings {
+    pub enabled: bool,
+    /// Folders other tools save screenshots to, e.g. `~/Pictures/Screenshots`
+    pub folders: Vec<String>,
+    /// Delete imported files from the watched folder instead of leaving a copy
+    #[serde(default)]
+    pub move_files: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WatchImportEvent {
+    pub source_path: String,
+    /// The new entry; `None` when the image was already in history or the
+    /// import failed
+    pub id: Option<String>,
+    pub error: Option<String>,
+}
+
+#[tauri::command]
+pub async fn get_watch_settings() -> Result<WatchSettings, String> {
+    let conn = history_db::open(&history::get_history_dir()?)?;
+    load_settings(&conn)
+}
+
+/// Save the watch settings and restart the watcher with them
+#[tauri::command]
+pub async fn set_watch_settings(
+    app: AppHandle,
+    settings: WatchSettings,
+) -> Result<WatchSettings, String> {
+    let history_dir = history::get_history_dir()?;
+    let settings = validate_settings(&history_dir, settings)?;
+
+    let conn = history_db::open(&history_dir)?;
+    let json = serde_json::to_string(&settings)
+        .map_err(|e| format!("Failed to serialize watch settings: {}", e))?;
+    history_db::set_setting(&conn, SETTINGS_KEY, &json)?;
+
+    restart(app, &settings)?;
+    Ok(settings)
+}
+
+/// Start watching the folders in the saved settings
+pub(crate) fn start(app: AppHandle) -> Result<(), String> {
+    let conn = history_db::open(&history::get_history_dir()?)?;
+    restart(app, &load_settings(&conn)?)
+}
+
+fn load_settings(conn: &rusqlite::Connection) -> Result<WatchSettings, String> {
+    match history_db::get_setting(conn, SETTINGS_KEY)? {
+        Some(json) => {
+            serde_json::from_str(&json).map_err(|e| format!("Failed to read watch settings: {}", e))
+        }
+        None => Ok(WatchSettings::default()),
+    }
+}
+
+/// Trim and dedupe folders, rejecting any that cannot be watched
+fn validate_settings(history_dir: &Path, settings: WatchSettings) -> Result<WatchSettings, String> {
+    let mut folders: Vec<String> = Vec::new();
+    for folder in &settings.folders {
+        let folder = folder.trim();
+        let path = Path::new(folder);
+        if !path.is_absolute() {
+            return Err(format!(
+                "Watched folder must be an absolute path: {}",
+                folder
+            ));
+        }
+        if !path.is_dir() {
+            return Err(format!("Watched folder does not exist: {}", folder));
+        }
+        if path.starts_with(history_dir) {
+            return Err("History itself cannot be watched".to_string());
+        }
+        if !folders.iter().any(|existing| existing == folder) {
+            folders.push(folder.to_string());
+        }
+    }
+
+    Ok(WatchSettings {
+        folders,
+        ..settings
+    })
+}
+
+fn restart(app: AppHandle, settings: &WatchSettings) -> Result<(), String> {
+    let mut watcher = WATCHER
+        .lock()
+        .map_err(|_| "Folder watcher is poisoned".to_string())?;
+    // Dropping the previous watcher stops it
+    *watcher = None;
+
+    if !settings.enabled || settings.folders.is_empty() {
+        return Ok(());
+    }
+
+    let move_files = settings.move_files;
+    let mut debouncer = new_debouncer(DEBOUNCE, move |result: DebounceEventResult| {
+        let Ok(events) = result else {
+            return;
+        };
+        for event in events {
+            if is_importable(&event.path) {
+                import_and_notify(&app, &event.path, move_files);
+            }
+        }
+    })
+    .map_err(|e| format!("Failed to start folder watcher: {}", e))?;
+
+    for folder in &settings.folders {
+        debouncer
+            .watcher()
+            .watch(Path::new(folder), RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch {}: {}", folder, e))?;
+    }
+    *watcher = Some(debouncer);
+
+    Ok(())
+}
+
+/// Image files that still exist; events also arrive for deletions and for
+/// the hidden temp files screenshot tools write before renaming
+fn is_importable(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    let image = path.extension().is_some_and(|ext| {
+        IMAGE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())
+    });
+
+    !hidden && image && path.is_file()
+}
+
+fn import_and_notify(app: &AppHandle, source: &Path, move_files: bool) {
+    let result = history::get_history_dir()
+        .and_then(|history_dir| import_file(&history_dir, source, move_files));
+
+    let (id, error) = match result {
+        Ok(id) => (id, None),
+        Err(error) => (None, Some(error)),
+    };
+    let _ = app.emit(
+        IMPORTED_EVENT,
+        WatchImportEvent {
+            source_path: source.to_string_lossy().to_string(),
+            id,
+            error,
+        },
+    );
+}
+
+/// Import one image into history, returning the new entry's id, or `None`
+/// when a live entry already has the same image. PNG files are stored byte
+/// for byte; other formats are converted to PNG like captures. With
+/// `move_files` the source is removed only once a live entry holds it.
+pub(crate) fn import_file(
+    history_dir: &Path,
+    source: &Path,
+    move_files: bool,
+) -> Result<Option<String>, String> {
+    let image = image::open(source).map_err(|e| format!("Failed to open image: {}", e))?;
+
+    // Named like captures so leftovers are removed when the app exits
+    let token = Uuid::new_v4();
+    let converted = std::env::temp_dir().join(format!("sa_import_{}.png", token));
+    let thumbnail = std::env::temp_dir().join(format!("sa_thumb_{}.png", token));
+
+    let result = (|| -> Result<Option<String>, String> {
+        let is_png = source
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+        let original: PathBuf = if is_png {
+            source.to_path_buf()
+        } else {
+            image
+                .save_with_format(&converted, ImageFormat::Png)
+                .map_err(|e| format!("Failed to convert image: {}", e))?;
+            converted.clone()
+        };
+
+        let conn = history_db::open(history_dir)?;
+        if blob_store::has_original(&conn, &blob_store::hash_file(&original)?)? {
+            return Ok(None);
+        }
+        drop(conn);
+
+        export::save_thumbnail(&image.to_rgba8(), &thumbnail)?;
+        history::save_entry(history_dir, &original, None, &thumbnail, "[]", None, None).map(Some)
+    })();
+
+    let _ = fs::remove_file(&converted);
+    let _ = fs::remove_file(&thumbnail);
+
+    let imported = result?;
+    if move_files {
+        fs::remove_file(source)
+            .map_err(|e| format!("Failed to remove {}: {}", source.display(), e))?;
+    }
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import_file, is_importable};
+    use crate::history_db;
+    use crate::history_trash;
+    use image::{Rgba, RgbaImage};
+    use std::fs;
+
+    #[test]
+    fn imports_each_image_once_and_moves_when_asked() {
+        let root = std::env::temp_dir().join(format!("sa_test_{}", uuid::Uuid::new_v4()));
+        let history_dir = root.join("history");
+        let watched = root.join("Screenshots");
+        fs::create_dir_all(&history_dir).unwrap();
+        fs::create_dir_all(&watched).unwrap();
+
+        let capture = watched.join("Screenshot 1.png");
+        RgbaImage::from_pixel(40, 30, Rgba([200, 20, 20, 255]))
+            .save(&capture)
+            .unwrap();
+        let copy = watched.join("Screenshot 1 copy.png");
+        fs::copy(&capture, &copy).unwrap();
+        assert!(is_importable(&capture));
+        assert!(!is_importable(&watched.join(".Screenshot 2.png")));
+
+        let id = import_file(&history_dir, &capture, false).unwrap().unwrap();
+        assert!(capture.exists());
+
+        // The same pixels under another name are already in history
+        assert_eq!(import_file(&history_dir, &copy, true).unwrap(), None);
+        assert!(!copy.exists());
+
+        let conn = history_db::open(&history_dir).unwrap();
+        assert!(history_db::find(&conn, &id).unwrap().is_some());
+        assert_eq!(history_db::storage_usage(&conn).unwrap().1, 1);
+
+        // A trashed entry does not count: purging it would lose the image
+        history_trash::move_to_trash(&conn, &history_dir, &id).unwrap();
+        drop(conn);
+        let copy = watched.join("Screenshot 1 again.png");
+        fs::copy(&capture, &copy).unwrap();
+        let reimported = import_file(&history_dir, &copy, true).unwrap().unwrap();
+        assert_ne!(reimported, id);
+        assert!(!copy.exists());
+
+        let conn = history_db::open(&history_dir).unwrap();
+        assert!(history_db::find(&conn, &reimported).unwrap().is_some());
+        drop(conn);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,7 +1,29 @@
+mod annotations;
+mod blob_store;
 mod capture;
 mod credentials;
 mod export;
+mod fs_util;
+mod guide;
 mod history;
+mod history_backup;
+mod history_bulk;
+mod history_crypto;
+mod history_db;
+mod history_fields;
+mod history_integrity;
+mod history_labels;
+mod history_location;
+mod history_revisions;
+mod history_schema;
+mod history_search;
+mod history_similar;
+mod history_stats;
+mod history_trash;
+mod history_watch;
+mod openraster;
+mod report;
+mod retention;
 mod upload;
 
 use tauri::Emitter;
@@ -27,8 +49,25 @@ pub fn run() {
 
             app.global_shortcut().register("CmdOrCtrl+Shift+5")?;
 
+            // A watched folder that has gone away should not stop the app
+            // from starting; saving the settings again reports the error
+            let _ = history_watch::start(app.handle().clone());
+
             Ok(())
         })
+        // Serves history images, decrypting them when history is encrypted
+        .register_uri_scheme_protocol("history", |_ctx, request| {
+            match history_crypto::read_for_protocol(request.uri().path()) {
+                Ok(bytes) => tauri::http::Response::builder()
+                    .header("Content-Type", "image/png")
+                    .body(bytes)
+                    .unwrap_or_default(),
+                Err(e) => tauri::http::Response::builder()
+                    .status(404)
+                    .body(e.into_bytes())
+                    .unwrap_or_default(),
+            }
+        })
         .on_window_event(|_window, event| {
             if let tauri::WindowEvent::Destroyed = event {
                 // Cleanup temp files when window closes
@@ -38,14 +77,65 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             capture::capture_screenshot,
             export::export_annotated,
+            openraster::export_openraster,
+            guide::export_guide,
+            report::generate_incident_report,
             history::save_to_history,
             history::get_history,
+            history::query_history,
+            history::update_history_entry,
+            history_fields::get_custom_fields,
+            history_fields::set_custom_fields,
             history::delete_from_history,
+            history_bulk::bulk_delete,
+            history_bulk::bulk_tag,
+            history_bulk::bulk_set_ticket,
+            history_bulk::bulk_export,
+            history_bulk::bulk_upload,
             history::get_storage_usage,
+            history_similar::find_similar,
+            history_stats::get_history_stats,
+            history_stats::export_history_stats,
+            retention::get_retention_policy,
+            retention::set_retention_policy,
+            retention::preview_eviction,
+            retention::get_eviction_log,
+            retention::clear_eviction_log,
+            history_trash::list_trash,
+            history_trash::restore_from_trash,
+            history_trash::purge_from_trash,
+            history_trash::empty_trash,
+            history_backup::export_history_archive,
+            history_backup::import_history_archive,
+            history_location::get_history_location,
+            history_location::set_history_location,
+            history_watch::get_watch_settings,
+            history_watch::set_watch_settings,
+            history_crypto::get_history_encryption,
+            history_crypto::set_history_encryption,
+            history_integrity::verify_history,
+            history_integrity::repair_history,
+            history_revisions::open_history_entry,
+            history_revisions::save_history_revision,
+            history_revisions::list_history_revisions,
+            history_revisions::restore_history_revision,
+            history_labels::list_tags,
+            history_labels::create_tag,
+            history_labels::rename_tag,
+            history_labels::delete_tag,
+            history_labels::assign_tags,
+            history_labels::remove_tags,
+            history_labels::list_collections,
+            history_labels::create_collection,
+            history_labels::rename_collection,
+            history_labels::delete_collection,
+            history_labels::add_to_collection,
+            history_labels::remove_from_collection,
             credentials::store_credential,
             credentials::get_credential,
             credentials::delete_credential,
             upload::upload_screenshot,
+            history_fields::preview_upload_comment,
             upload::validate_credentials,
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,337 @@
+use image::{imageops, DynamicImage, ImageFormat, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::export::base64_decode;
+use crate::history_crypto;
+
+const ORA_MIMETYPE: &str = "image/openraster";
+const ORA_THUMBNAIL_MAX: u32 = 256;
+const PIXELATE_BLOCK_SIZE: u32 = 12;
+const BLUR_SIGMA: f32 = 8.0;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenRasterResult {
+    pub ora_path: String,
+    pub layer_count: usize,
+}
+
+/// A single raster layer inside the OpenRaster stack
+struct Layer {
+    name: String,
+    src: String,
+    x: u32,
+    y: u32,
+    image: RgbaImage,
+}
+
+/// A redaction region extracted from the annotations JSON
+#[derive(Debug, PartialEq)]
+struct RedactionRegion {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    style: String,
+    reason: String,
+}
+
+/// Export the screenshot as a layered OpenRaster (.ora) file.
+///
+/// The stack contains the untouched original at the bottom, one layer per
+/// redaction, and the annotation layer on top, so the result can be opened
+/// in GIMP or Krita with every layer still toggleable. The annotation PNG
+/// must be drawn without the redactions, which the frontend's
+/// `exportLayered` does.
+#[tauri::command]
+pub async fn export_openraster(
+    original_path: String,
+    annotation_png_base64: String,
+    annotations_json: String,
+    output_path: Option<String>,
+) -> Result<OpenRasterResult, String> {
+    let original_img = history_crypto::open_image(Path::new(&original_path))
+        .map_err(|e| format!("Failed to load original image: {}", e))?
+        .to_rgba8();
+
+    let annotation_data = base64_decode(&annotation_png_base64)
+        .map_err(|e| format!("Failed to decode base64: {}", e))?;
+    let annotation_img = image::load_from_memory(&annotation_data)
+        .map_err(|e| format!("Failed to load annotation image: {}", e))?
+        .to_rgba8();
+
+    let (width, height) = original_img.dimensions();
+    let redactions = parse_redactions(&annotations_json, width, height)?;
+
+    // Layers are listed top-most first, as required by stack.xml
+    let mut layers = Vec::with_capacity(redactions.len() + 2);
+    layers.push(Layer {
+        name: "Annotations".to_string(),
+        src: "data/annotations.png".to_string(),
+        x: 0,
+        y: 0,
+        image: annotation_img,
+    });
+
+    for (i, region) in redactions.iter().enumerate().rev() {
+        layers.push(Layer {
+            name: format!("Redaction {} ({})", i + 1, region.reason),
+            src: format!("data/redaction_{}.png", i + 1),
+            x: region.x,
+            y: region.y,
+            image: render_redaction(&original_img, region),
+        });
+    }
+
+    layers.push(Layer {
+        name: "Screenshot".to_string(),
+        src: "data/original.png".to_string(),
+        x: 0,
+        y: 0,
+        image: original_img,
+    });
+
+    let ora_path = match output_path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            std::env::temp_dir().join(format!("layered_{}.ora", timestamp))
+        }
+    };
+
+    let bytes = write_openraster(width, height, &layers)?;
+    fs::write(&ora_path, bytes).map_err(|e| format!("Failed to write OpenRaster file: {}", e))?;
+
+    Ok(OpenRasterResult {
+        ora_path: ora_path.to_string_lossy().to_string(),
+        layer_count: layers.len(),
+    })
+}
+
+fn parse_redactions(
+    annotations_json: &str,
+    image_width: u32,
+    image_height: u32,
+) -> Result<Vec<RedactionRegion>, String> {
+    let annotations: serde_json::Value = serde_json::from_str(annotations_json)
+        .map_err(|e| format!("Failed to parse annotations JSON: {}", e))?;
+
+    let mut regions = Vec::new();
+    for annotation in annotations.as_array().into_iter().flatten() {
+        if annotation["type"] != "redact" {
+            continue;
+        }
+
+        let x = annotation["origin"]["x"].as_f64().unwrap_or(0.0);
+        let y = annotation["origin"]["y"].as_f64().unwrap_or(0.0);
+        let w = annotation["width"].as_f64().unwrap_or(0.0);
+        let h = annotation["height"].as_f64().unwrap_or(0.0);
+
+        // Rectangles drawn up/left have negative extents; normalize and clip
+        let left = x.min(x + w).max(0.0).floor() as u32;
+        let top = y.min(y + h).max(0.0).floor() as u32;
+        let right = (x.max(x + w).ceil() as u32).min(image_width);
+        let bottom = (y.max(y + h).ceil() as u32).min(image_height);
+
+        if right <= left || bottom <= top {
+            continue;
+        }
+
+        regions.push(RedactionRegion {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+            style: annotation["style"]
+                .as_str()
+                .unwrap_or("blackbox")
+                .to_string(),
+            reason: annotation["reason"]
+                .as_str()
+                .unwrap_or("manual")
+                .to_string(),
+        });
+    }
+
+    Ok(regions)
+}
+
+fn render_redaction(original: &RgbaImage, region: &RedactionRegion) -> RgbaImage {
+    let crop =
+        imageops::crop_imm(original, region.x, region.y, region.width, region.height).to_image();
+
+    match region.style.as_str() {
+        "blur" => imageops::blur(&crop, BLUR_SIGMA),
+        "pixelate" => {
+            let small_w = (region.width / PIXELATE_BLOCK_SIZE).max(1);
+            let small_h = (region.height / PIXELATE_BLOCK_SIZE).max(1);
+            let small = imageops::resize(&crop, small_w, small_h, imageops::FilterType::Triangle);
+            imageops::resize(
+                &small,
+                region.width,
+                region.height,
+                imageops::FilterType::Nearest,
+            )
+        }
+        _ => RgbaImage::from_pixel(region.width, region.height, Rgba([0, 0, 0, 255])),
+    }
+}
+
+fn write_openraster(width: u32, height: u32, layers: &[Layer]) -> Result<Vec<u8>, String> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // The spec requires `mimetype` to be the first, uncompressed entry
+    zip.start_file("mimetype", stored)
+        .map_err(|e| format!("Failed to write OpenRaster mimetype: {}", e))?;
+    zip.write_all(ORA_MIMETYPE.as_bytes())
+        .map_err(|e| format!("Failed to write OpenRaster mimetype: {}", e))?;
+
+    zip.start_file("stack.xml", deflated)
+        .map_err(|e| format!("Failed to write stack.xml: {}", e))?;
+    zip.write_all(build_stack_xml(width, height, layers).as_bytes())
+        .map_err(|e| format!("Failed to write stack.xml: {}", e))?;
+
+    // Composite bottom-up for the merged preview
+    let mut merged = RgbaImage::new(width, height);
+    for layer in layers.iter().rev() {
+        imageops::overlay(&mut merged, &layer.image, layer.x as i64, layer.y as i64);
+    }
+
+    for layer in layers {
+        zip.start_file(layer.src.as_str(), stored)
+            .map_err(|e| format!("Failed to write layer {}: {}", layer.name, e))?;
+        zip.write_all(&encode_png(&layer.image)?)
+            .map_err(|e| format!("Failed to write layer {}: {}", layer.name, e))?;
+    }
+
+    let thumbnail = DynamicImage::ImageRgba8(merged.clone())
+        .thumbnail(ORA_THUMBNAIL_MAX, ORA_THUMBNAIL_MAX)
+        .to_rgba8();
+
+    zip.start_file("Thumbnails/thumbnail.png", stored)
+        .map_err(|e| format!("Failed to write thumbnail: {}", e))?;
+    zip.write_all(&encode_png(&thumbnail)?)
+        .map_err(|e| format!("Failed to write thumbnail: {}", e))?;
+
+    zip.start_file("mergedimage.png", stored)
+        .map_err(|e| format!("Failed to write merged image: {}", e))?;
+    zip.write_all(&encode_png(&merged)?)
+        .map_err(|e| format!("Failed to write merged image: {}", e))?;
+
+    let cursor = zip
+        .finish()
+        .map_err(|e| format!("Failed to finalize OpenRaster file: {}", e))?;
+
+    Ok(cursor.into_inner())
+}
+
+fn build_stack_xml(width: u32, height: u32, layers: &[Layer]) -> String {
+    let mut xml = String::from("<?xml version='1.0' encoding='UTF-8'?>\n");
+    xml.push_str(&format!(
+        "<image version=\"0.0.5\" w=\"{}\" h=\"{}\">\n  <stack>\n",
+        width, height
+    ));
+
+    for layer in layers {
+        xml.push_str(&format!(
+            "    <layer name=\"{}\" src=\"{}\" x=\"{}\" y=\"{}\" opacity=\"1.0\" visibility=\"visible\" />\n",
+            escape_xml(&layer.name),
+            escape_xml(&layer.src),
+            layer.x,
+            layer.y
+        ));
+    }
+
+    xml.push_str("  </stack>\n</image>\n");
+    xml
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn encode_png(img: &RgbaImage) -> Result<Vec<u8>, String> {
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    Ok(buffer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_stack_xml, escape_xml, parse_redactions, Layer, RedactionRegion};
+    use image::RgbaImage;
+
+    #[test]
+    fn parse_redactions_normalizes_negative_extents_and_clips() {
+        let json = r#"[
+            {"type": "arrow", "start": {"x": 0, "y": 0}, "end": {"x": 5, "y": 5}},
+            {"type": "redact", "origin": {"x": 50, "y": 40}, "width": -20, "height": -10,
+             "style": "pixelate", "reason": "email"},
+            {"type": "redact", "origin": {"x": 90, "y": 90}, "width": 50, "height": 50,
+             "style": "blackbox", "reason": "manual"}
+        ]"#;
+
+        let regions = parse_redactions(json, 100, 100).unwrap();
+        assert_eq!(
+            regions,
+            vec![
+                RedactionRegion {
+                    x: 30,
+                    y: 30,
+                    width: 20,
+                    height: 10,
+                    style: "pixelate".to_string(),
+                    reason: "email".to_string(),
+                },
+                RedactionRegion {
+                    x: 90,
+                    y: 90,
+                    width: 10,
+                    height: 10,
+                    style: "blackbox".to_string(),
+                    reason: "manual".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_redactions_skips_regions_outside_image() {
+        let json = r#"[{"type": "redact", "origin": {"x": 200, "y": 200}, "width": 10,
+                        "height": 10, "style": "blur", "reason": "ip"}]"#;
+        assert!(parse_redactions(json, 100, 100).unwrap().is_empty());
+        assert!(parse_redactions("not json", 100, 100).is_err());
+    }
+
+    #[test]
+    fn stack_xml_lists_layers_with_offsets_and_escaped_names() {
+        let layers = vec![Layer {
+            name: "Redaction 1 (<ip>)".to_string(),
+            src: "data/redaction_1.png".to_string(),
+            x: 4,
+            y: 8,
+            image: RgbaImage::new(1, 1),
+        }];
+
+        let xml = build_stack_xml(640, 480, &layers);
+        assert!(xml.contains("w=\"640\" h=\"480\""));
+        assert!(xml.contains("name=\"Redaction 1 (&lt;ip&gt;)\""));
+        assert!(xml.contains("src=\"data/redaction_1.png\" x=\"4\" y=\"8\""));
+        assert_eq!(escape_xml("a&\"b'"), "a&amp;&quot;b&apos;");
+    }
+}
@@ -0,0 +1,454 @@
+use image::DynamicImage;
+use printpdf::{
+    ColorBits, ColorSpace, Image, ImageTransform, ImageXObject, IndirectFontRef, Mm, PdfDocument,
+    PdfDocumentReference, PdfLayerReference, Px,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use ttf_parser::Face;
+
+use crate::history::{self, ScreenshotMeta};
+use crate::history_crypto;
+
+// A4 portrait
+const PAGE_WIDTH_MM: f32 = 210.0;
+const PAGE_HEIGHT_MM: f32 = 297.0;
+const MARGIN_MM: f32 = 15.0;
+
+// Images are downscaled before embedding to keep reports a sensible size
+const MAX_IMAGE_DIMENSION: u32 = 2000;
+// Below this, an image that could be larger goes on a page of its own
+const MIN_IMAGE_HEIGHT_MM: f32 = 80.0;
+
+const PT_TO_MM: f32 = 25.4 / 72.0;
+
+// DejaVu Sans covers most scripts, unlike the PDF built-in fonts which
+// only cover Latin-1. See fonts/LICENSE.
+const REGULAR_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+const BOLD_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans-Bold.ttf");
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportRequest {
+    pub title: String,
+    pub ticket_id: Option<String>,
+    pub items: Vec<ReportItem>,
+    pub output_path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportItem {
+    pub id: String,
+    pub caption: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportResult {
+    pub pdf_path: String,
+    pub page_count: usize,
+}
+
+/// Compile ordered history items into a PDF incident report.
+///
+/// The report has a title page followed by a page per screenshot with its
+/// caption, capture time, annotation count and notes. Long notes continue
+/// onto further pages, and the image moves to a page of its own when too
+/// little room is left below them.
+#[tauri::command]
+pub async fn generate_incident_report(request: ReportRequest) -> Result<ReportResult, String> {
+    if request.items.is_empty() {
+        return Err("Report must contain at least one screenshot".to_string());
+    }
+
+    let entries = request
+        .items
+        .iter()
+        .map(|item| history::find_history_entry(&item.id))
+        .collect::<Result<Vec<ScreenshotMeta>, String>>()?;
+
+    let (doc, title_page, title_layer) = PdfDocument::new(
+        request.title.as_str(),
+        Mm(PAGE_WIDTH_MM),
+        Mm(PAGE_HEIGHT_MM),
+        "Title",
+    );
+
+    let regular = ReportFont::embed(&doc, REGULAR_FONT)?;
+    let bold = ReportFont::embed(&doc, BOLD_FONT)?;
+
+    // Title page
+    let layer = doc.get_page(title_page).get_layer(title_layer);
+    let mut writer = TextWriter::new(&doc, layer, PAGE_HEIGHT_MM - 60.0);
+    writer.paragraph(&request.title, 24.0, &bold);
+    writer.gap(6.0);
+
+    if let Some(ref ticket_id) = request.ticket_id {
+        writer.paragraph(&format!("Ticket: {}", ticket_id), 14.0, &regular);
+    }
+    writer.paragraph(
+        &format!(
+            "Generated: {}",
+            chrono::Utc::now().format("%Y-%m-%d %H:%M UTC")
+        ),
+        12.0,
+        &regular,
+    );
+    writer.paragraph(&format!("Screenshots: {}", entries.len()), 12.0, &regular);
+
+    let mut created: Vec<&str> = entries.iter().map(|m| m.created_at.as_str()).collect();
+    created.sort();
+    if let (Some(first), Some(last)) = (created.first(), created.last()) {
+        writer.paragraph(
+            &format!(
+                "Captured: {} to {}",
+                format_timestamp(first),
+                format_timestamp(last)
+            ),
+            12.0,
+            &regular,
+        );
+    }
+
+    // Each screenshot starts a new page; long notes continue onto more
+    for (i, (item, meta)) in request.items.iter().zip(entries.iter()).enumerate() {
+        writer.new_page(format!("Screenshot {}", i + 1));
+
+        let heading = match item.caption {
+            Some(ref caption) if !caption.trim().is_empty() => {
+                format!("{}. {}", i + 1, caption.trim())
+            }
+            _ => format!("Screenshot {} of {}", i + 1, entries.len()),
+        };
+        writer.paragraph(&heading, 16.0, &bold);
+
+        let mut details = vec![format!("Captured: {}", format_timestamp(&meta.created_at))];
+        if let Some(ref ticket_id) = meta.ticket_id {
+            details.push(format!("Ticket: {}", ticket_id));
+        }
+        details.push(format!("Annotations: {}", meta.annotation_count));
+        writer.paragraph(&details.join("   |   "), 10.0, &regular);
+
+        if let Some(ref notes) = item.notes {
+            if !notes.trim().is_empty() {
+                writer.gap(2.0);
+                writer.paragraph(notes.trim(), 11.0, &regular);
+            }
+        }
+
+        let image_path = meta.annotated_path.as_ref().unwrap_or(&meta.original_path);
+        let image = load_image(image_path)?;
+        writer.gap(4.0);
+        let (_, _, full_page_height) = fit_image(
+            image.width(),
+            image.height(),
+            PAGE_WIDTH_MM - 2.0 * MARGIN_MM,
+            PAGE_HEIGHT_MM - 2.0 * MARGIN_MM - 5.0,
+        );
+        if writer.remaining_mm() < full_page_height.min(MIN_IMAGE_HEIGHT_MM) {
+            writer.new_page(format!("Screenshot {} (continued)", i + 1));
+        }
+        place_image(&writer.layer, image, writer.cursor_mm);
+    }
+
+    let page_count = writer.page_count;
+    let bytes = doc
+        .save_to_bytes()
+        .map_err(|e| format!("Failed to render PDF: {}", e))?;
+
+    let pdf_path = match request.output_path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            std::env::temp_dir().join(format!("incident_report_{}.pdf", timestamp))
+        }
+    };
+
+    fs::write(&pdf_path, bytes).map_err(|e| format!("Failed to write PDF: {}", e))?;
+
+    Ok(ReportResult {
+        pdf_path: pdf_path.to_string_lossy().to_string(),
+        page_count,
+    })
+}
+
+/// An embedded TrueType font, with the glyph widths used to wrap text
+struct ReportFont {
+    pdf: IndirectFontRef,
+    face: Face<'static>,
+}
+
+impl ReportFont {
+    fn embed(doc: &PdfDocumentReference, data: &'static [u8]) -> Result<Self, String> {
+        let pdf = doc
+            .add_external_font(data)
+            .map_err(|e| format!("Failed to load font: {}", e))?;
+        let face = Face::parse(data, 0).map_err(|e| format!("Failed to load font: {}", e))?;
+        Ok(Self { pdf, face })
+    }
+}
+
+/// Width of `text` in mm. Characters the font lacks count as half an em.
+fn text_width_mm(face: &Face, text: &str, font_size: f32) -> f32 {
+    let units_per_em = face.units_per_em() as f32;
+    let units: f32 = text
+        .chars()
+        .map(|c| {
+            face.glyph_index(c)
+                .and_then(|glyph| face.glyph_hor_advance(glyph))
+                .map_or(units_per_em / 2.0, f32::from)
+        })
+        .sum();
+
+    units / units_per_em * font_size * PT_TO_MM
+}
+
+/// Writes wrapped lines of text downwards from a starting position, adding
+/// pages as each one fills up
+struct TextWriter<'a> {
+    doc: &'a PdfDocumentReference,
+    layer: PdfLayerReference,
+    cursor_mm: f32,
+    page_count: usize,
+}
+
+impl<'a> TextWriter<'a> {
+    fn new(doc: &'a PdfDocumentReference, layer: PdfLayerReference, top_mm: f32) -> Self {
+        Self {
+            doc,
+            layer,
+            cursor_mm: top_mm,
+            page_count: 1,
+        }
+    }
+
+    fn new_page(&mut self, name: String) {
+        let (page, layer) = self
+            .doc
+            .add_page(Mm(PAGE_WIDTH_MM), Mm(PAGE_HEIGHT_MM), name);
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.cursor_mm = PAGE_HEIGHT_MM - MARGIN_MM - 5.0;
+        self.page_count += 1;
+    }
+
+    fn paragraph(&mut self, text: &str, font_size: f32, font: &ReportFont) {
+        let line_height = font_size * PT_TO_MM * 1.4;
+        for line in wrap_text(text, &font.face, font_size, PAGE_WIDTH_MM - 2.0 * MARGIN_MM) {
+            if self.cursor_mm - line_height < MARGIN_MM {
+                self.new_page(format!("Page {}", self.page_count + 1));
+            }
+            self.layer.use_text(
+                line,
+                font_size,
+                Mm(MARGIN_MM),
+                Mm(self.cursor_mm),
+                &font.pdf,
+            );
+            self.cursor_mm -= line_height;
+        }
+    }
+
+    fn gap(&mut self, mm: f32) {
+        self.cursor_mm -= mm;
+    }
+
+    /// Height left on the current page
+    fn remaining_mm(&self) -> f32 {
+        self.cursor_mm - MARGIN_MM
+    }
+}
+
+fn load_image(path: &str) -> Result<DynamicImage, String> {
+    let img = history_crypto::open_image(Path::new(path))
+        .map_err(|e| format!("Failed to load image {}: {}", path, e))?;
+    Ok(
+        if img.width() > MAX_IMAGE_DIMENSION || img.height() > MAX_IMAGE_DIMENSION {
+            img.thumbnail(MAX_IMAGE_DIMENSION, MAX_IMAGE_DIMENSION)
+        } else {
+            img
+        },
+    )
+}
+
+fn place_image(layer: &PdfLayerReference, img: DynamicImage, box_top_mm: f32) {
+    let rgb = img.to_rgb8();
+    let (width_px, height_px) = rgb.dimensions();
+
+    let box_width = PAGE_WIDTH_MM - 2.0 * MARGIN_MM;
+    let box_height = (box_top_mm - MARGIN_MM).max(10.0);
+    let (dpi, width_mm, height_mm) = fit_image(width_px, height_px, box_width, box_height);
+
+    let xobject = ImageXObject {
+        width: Px(width_px as usize),
+        height: Px(height_px as usize),
+        color_space: ColorSpace::Rgb,
+        bits_per_component: ColorBits::Bit8,
+        interpolate: true,
+        image_data: rgb.into_raw(),
+        image_filter: None,
+        smask: None,
+        clipping_bbox: None,
+    };
+
+    // Centre horizontally, anchor to the top of the available box
+    Image::from(xobject).add_to_layer(
+        layer.clone(),
+        ImageTransform {
+            translate_x: Some(Mm(MARGIN_MM + (box_width - width_mm) / 2.0)),
+            translate_y: Some(Mm(box_top_mm - height_mm)),
+            dpi: Some(dpi),
+            ..Default::default()
+        },
+    );
+}
+
+/// Returns the DPI that fits an image into a box, plus its rendered size in mm
+fn fit_image(
+    width_px: u32,
+    height_px: u32,
+    box_width_mm: f32,
+    box_height_mm: f32,
+) -> (f32, f32, f32) {
+    let dpi_for_width = width_px as f32 * 25.4 / box_width_mm;
+    let dpi_for_height = height_px as f32 * 25.4 / box_height_mm;
+    // Never upscale small captures beyond their on-screen size
+    let dpi = dpi_for_width.max(dpi_for_height).max(72.0);
+
+    (
+        dpi,
+        width_px as f32 * 25.4 / dpi,
+        height_px as f32 * 25.4 / dpi,
+    )
+}
+
+/// Break text into lines that fit `max_width_mm`, at spaces where possible
+/// and inside words too long for a line of their own, such as URLs
+fn wrap_text(text: &str, face: &Face, font_size: f32, max_width_mm: f32) -> Vec<String> {
+    let fits = |line: &str| text_width_mm(face, line, font_size) <= max_width_mm;
+    let mut lines = Vec::new();
+
+    for source_line in text.lines() {
+        let mut current = String::new();
+        for word in source_line.split_whitespace() {
+            let candidate = if current.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", current, word)
+            };
+            if fits(&candidate) {
+                current = candidate;
+                continue;
+            }
+            if !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+            }
+            for c in word.chars() {
+                current.push(c);
+                if !fits(&current) && current.chars().count() > 1 {
+                    current.pop();
+                    lines.push(std::mem::replace(&mut current, c.to_string()));
+                }
+            }
+        }
+        lines.push(current);
+    }
+
+    lines
+}
+
+fn format_timestamp(created_at: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(created_at)
+        .map(|dt| {
+            dt.with_timezone(&chrono::Utc)
+                .format("%Y-%m-%d %H:%M UTC")
+                .to_string()
+        })
+        .unwrap_or_else(|_| created_at.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fit_image, format_timestamp, text_width_mm, wrap_text, ReportFont, TextWriter, MARGIN_MM,
+        PAGE_HEIGHT_MM, PAGE_WIDTH_MM, REGULAR_FONT,
+    };
+    use printpdf::{Mm, PdfDocument};
+    use ttf_parser::Face;
+
+    fn face() -> Face<'static> {
+        Face::parse(REGULAR_FONT, 0).unwrap()
+    }
+
+    #[test]
+    fn wrap_text_breaks_on_word_boundaries() {
+        let face = face();
+        let lines = wrap_text("alpha beta gamma delta", &face, 10.0, 30.0);
+        assert!(lines.len() > 1);
+        assert_eq!(lines.join(" "), "alpha beta gamma delta");
+        assert_eq!(
+            wrap_text("one\n\ntwo", &face, 10.0, 100.0),
+            vec!["one", "", "two"]
+        );
+
+        let url = format!("https://example.com/{}", "a".repeat(200));
+        let lines = wrap_text(&url, &face, 10.0, 100.0);
+        assert!(lines.len() > 1);
+        assert_eq!(lines.concat(), url);
+        assert!(lines
+            .iter()
+            .all(|line| text_width_mm(&face, line, 10.0) <= 100.0));
+    }
+
+    #[test]
+    fn non_latin_text_is_measured_with_real_glyphs() {
+        let face = face();
+        for text in ["Привет мир", "Γειά σου", "İstanbul ğ"] {
+            assert!(text.chars().all(|c| face.glyph_index(c).is_some()));
+        }
+        // "W" is much wider than "i", which a fixed average would miss
+        assert!(text_width_mm(&face, "WWWW", 10.0) > 2.0 * text_width_mm(&face, "iiii", 10.0));
+    }
+
+    #[test]
+    fn long_paragraphs_continue_on_new_pages() {
+        let (doc, page, layer) =
+            PdfDocument::new("Test", Mm(PAGE_WIDTH_MM), Mm(PAGE_HEIGHT_MM), "Title");
+        let font = ReportFont::embed(&doc, REGULAR_FONT).unwrap();
+        let layer = doc.get_page(page).get_layer(layer);
+        let mut writer = TextWriter::new(&doc, layer, PAGE_HEIGHT_MM - MARGIN_MM);
+
+        writer.paragraph(&"Note line\n".repeat(60), 11.0, &font);
+        assert_eq!(writer.page_count, 2);
+        assert!(writer.remaining_mm() >= 0.0);
+    }
+
+    #[test]
+    fn fit_image_respects_both_box_dimensions() {
+        let (_, w, h) = fit_image(3000, 1000, 180.0, 200.0);
+        assert!((w - 180.0).abs() < 0.01);
+        assert!(h <= 200.0);
+
+        let (_, w, h) = fit_image(1000, 3000, 180.0, 200.0);
+        assert!((h - 200.0).abs() < 0.01);
+        assert!(w <= 180.0);
+    }
+
+    #[test]
+    fn fit_image_does_not_upscale_small_captures() {
+        let (dpi, w, _) = fit_image(100, 50, 180.0, 200.0);
+        assert_eq!(dpi, 72.0);
+        assert!(w < 180.0);
+    }
+
+    #[test]
+    fn format_timestamp_falls_back_to_raw_value() {
+        assert_eq!(
+            format_timestamp("2026-09-01T10:30:00+00:00"),
+            "2026-09-01 10:30 UTC"
+        );
+        assert_eq!(format_timestamp("yesterday"), "yesterday");
+    }
+}
@@ -0,0 +1,593 @@
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::blob_store;
+use crate::history::{self, ScreenshotMeta};
+use crate::history_db;
+use crate::history_trash;
+
+const DEFAULT_BUDGET_MB: u64 = 500;
+const MIN_BUDGET_MB: u64 = 50;
+const POLICY_SETTING_KEY: &str = "retention_policy";
+const DEFAULT_TRASH_DAYS: u32 = 30;
+/// Older eviction log rows are dropped past this many
+const EVICTION_LOG_LIMIT: usize = 1000;
+
+/// How much history to keep and for how long
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    pub budget_mb: u64,
+    /// Entries older than this are evicted regardless of the budget
+    pub max_age_days: Option<u32>,
+    /// Uploaded entries are kept at least this long after their last
+    /// successful upload, even when over budget or past `max_age_days`
+    pub keep_uploaded_days: Option<u32>,
+    /// Trashed entries are purged for good after this many days
+    #[serde(default = "default_trash_days")]
+    pub trash_days: u32,
+}
+
+fn default_trash_days() -> u32 {
+    DEFAULT_TRASH_DAYS
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            budget_mb: DEFAULT_BUDGET_MB,
+            max_age_days: None,
+            keep_uploaded_days: None,
+            trash_days: DEFAULT_TRASH_DAYS,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    pub(crate) fn budget_bytes(&self) -> u64 {
+        self.budget_mb * 1024 * 1024
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct EvictionCandidate {
+    pub id: String,
+    pub created_at: String,
+    pub ticket_id: Option<String>,
+    pub size_bytes: u64,
+    /// Less than `size_bytes` when some of its images are shared with
+    /// entries that are kept
+    pub freed_bytes: u64,
+    /// `expired` (past `max_age_days`) or `over_budget`
+    pub reason: String,
+    /// Already in the trash, which is emptied before live entries go
+    pub from_trash: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct EvictionPlan {
+    /// Oldest first
+    pub evicted: Vec<EvictionCandidate>,
+    pub used_bytes: u64,
+    pub budget_bytes: u64,
+    pub freed_bytes: u64,
+    /// False for a preview, where nothing was deleted
+    pub applied: bool,
+}
+
+/// An entry eviction deleted, kept so users can see what the budget removed
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct EvictionLogEntry {
+    pub id: String,
+    pub created_at: String,
+    pub ticket_id: Option<String>,
+    pub size_bytes: u64,
+    pub reason: String,
+    pub from_trash: bool,
+    pub evicted_at: String,
+}
+
+#[tauri::command]
+pub async fn get_retention_policy() -> Result<RetentionPolicy, String> {
+    let conn = history_db::open(&history::get_history_dir()?)?;
+    load_policy(&conn)
+}
+
+/// Save a new retention policy and apply it straight away
+#[tauri::command]
+pub async fn set_retention_policy(policy: RetentionPolicy) -> Result<EvictionPlan, String> {
+    validate_policy(&policy)?;
+
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    let json = serde_json::to_string(&policy)
+        .map_err(|e| format!("Failed to serialize retention policy: {}", e))?;
+    history_db::set_setting(&conn, POLICY_SETTING_KEY, &json)?;
+
+    enforce(&history_dir, &conn)
+}
+
+/// Show what eviction would delete under `policy` (or the saved policy)
+/// without deleting anything
+#[tauri::command]
+pub async fn preview_eviction(policy: Option<RetentionPolicy>) -> Result<EvictionPlan, String> {
+    let history_dir = history::get_history_dir()?;
+    let conn = history_db::open(&history_dir)?;
+
+    let policy = match policy {
+        Some(policy) => {
+            validate_policy(&policy)?;
+            policy
+        }
+        None => load_policy(&conn)?,
+    };
+
+    let (used_bytes, _) = history_db::storage_usage(&conn)?;
+    let entries = history_db::list_oldest_first(&conn)?;
+    let trashed = trashed_oldest_first(&conn)?;
+    let blob_refs = blob_store::list_refs(&conn)?;
+
+    Ok(plan_eviction(
+        &entries,
+        &trashed,
+        &blob_refs,
+        used_bytes,
+        &policy,
+        Utc::now(),
+    ))
+}
+
+/// What eviction removed, newest first
+#[tauri::command]
+pub async fn get_eviction_log(limit: Option<usize>) -> Result<Vec<EvictionLogEntry>, String> {
+    let conn = history_db::open(&history::get_history_dir()?)?;
+    let limit = limit.unwrap_or(EVICTION_LOG_LIMIT).min(EVICTION_LOG_LIMIT);
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT screenshot_id, created_at, ticket_id, size_bytes, reason, from_trash, evicted_at
+             FROM eviction_log ORDER BY id DESC LIMIT ?1",
+        )
+        .map_err(|e| format!("Failed to read eviction log: {}", e))?;
+    let rows = stmt
+        .query_map(params![limit as i64], |row| {
+            Ok(EvictionLogEntry {
+                id: row.get(0)?,
+                created_at: row.get(1)?,
+                ticket_id: row.get(2)?,
+                size_bytes: row.get::<_, i64>(3)? as u64,
+                reason: row.get(4)?,
+                from_trash: row.get(5)?,
+                evicted_at: row.get(6)?,
+            })
+        })
+        .map_err(|e| format!("Failed to read eviction log: {}", e))?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read eviction log: {}", e))
+}
+
+#[tauri::command]
+pub async fn clear_eviction_log() -> Result<(), String> {
+    let history_dir = history::get_history_dir()?;
+    let _lock = history::lock_history(&history_dir)?;
+    let conn = history_db::open(&history_dir)?;
+
+    conn.execute("DELETE FROM eviction_log", [])
+        .map_err(|e| format!("Failed to clear eviction log: {}", e))?;
+    Ok(())
+}
+
+pub(crate) fn load_policy(conn: &Connection) -> Result<RetentionPolicy, String> {
+    match history_db::get_setting(conn, POLICY_SETTING_KEY)? {
+        Some(json) => serde_json::from_str(&json)
+            .map_err(|e| format!("Failed to read retention policy: {}", e)),
+        None => Ok(RetentionPolicy::default()),
+    }
+}
+
+fn validate_policy(policy: &RetentionPolicy) -> Result<(), String> {
+    if policy.budget_mb < MIN_BUDGET_MB {
+        return Err(format!(
+            "Storage budget must be at least {} MB",
+            MIN_BUDGET_MB
+        ));
+    }
+    if policy.max_age_days == Some(0) {
+        return Err("Maximum age must be at least one day".to_string());
+    }
+    if policy.trash_days == 0 {
+        return Err("Trash must keep entries for at least one day".to_string());
+    }
+
+    Ok(())
+}
+
+/// Evict whatever the saved policy says must go. Must be called while
+/// holding the history lock.
+pub(crate) fn enforce(history_dir: &Path, conn: &Connection) -> Result<EvictionPlan, String> {
+    let policy = load_policy(conn)?;
+    let now = Utc::now();
+    history_trash::purge_expired(conn, history_dir, policy.trash_days, now)?;
+
+    let (used_bytes, _) = history_db::storage_usage(conn)?;
+    let entries = history_db::list_oldest_first(conn)?;
+    let trashed = trashed_oldest_first(conn)?;
+    let blob_refs = blob_store::list_refs(conn)?;
+
+    let mut plan = plan_eviction(&entries, &trashed, &blob_refs, used_bytes, &policy, now);
+
+    // Evicted entries are deleted outright: moving them to the trash would
+    // not free any space
+    for candidate in &plan.evicted {
+        if candidate.from_trash {
+            history_trash::purge_entry(conn, history_dir, &candidate.id)?;
+        } else {
+            // Index first: a crash afterwards leaves an orphan directory
+            // rather than a gallery entry with missing files
+            history_db::delete(conn, &candidate.id)?;
+            let screenshot_dir = history_dir.join(&candidate.id);
+            if screenshot_dir.exists() {
+                fs::remove_dir_all(&screenshot_dir).map_err(|e| e.to_string())?;
+            }
+        }
+        log_eviction(conn, candidate, now)?;
+    }
+    if !plan.evicted.is_empty() {
+        blob_store::collect_garbage(conn, history_dir)?;
+    }
+    plan.applied = true;
+
+    Ok(plan)
+}
+
+fn trashed_oldest_first(conn: &Connection) -> Result<Vec<ScreenshotMeta>, String> {
+    Ok(history_trash::list_oldest_first(conn)?
+        .into_iter()
+        .map(|(meta, _)| meta)
+        .collect())
+}
+
+fn log_eviction(
+    conn: &Connection,
+    candidate: &EvictionCandidate,
+    evicted_at: DateTime<Utc>,
+) -> Result<(), String> {
+    let map_err = |e: rusqlite::Error| format!("Failed to write eviction log: {}", e);
+
+    conn.execute(
+        "INSERT INTO eviction_log
+         (screenshot_id, created_at, ticket_id, size_bytes, reason, from_trash, evicted_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            candidate.id,
+            candidate.created_at,
+            candidate.ticket_id,
+            candidate.size_bytes as i64,
+            candidate.reason,
+            candidate.from_trash,
+            evicted_at.to_rfc3339(),
+        ],
+    )
+    .map_err(map_err)?;
+    conn.execute(
+        "DELETE FROM eviction_log WHERE id NOT IN
+         (SELECT id FROM eviction_log ORDER BY id DESC LIMIT ?1)",
+        params![EVICTION_LOG_LIMIT as i64],
+    )
+    .map_err(map_err)?;
+
+    Ok(())
+}
+
+/// When the entry was last uploaded successfully, if ever. Entries uploaded
+/// before upload records existed only have `uploaded_url`, so their capture
+/// time stands in.
+fn last_uploaded_at(meta: &ScreenshotMeta) -> Option<DateTime<Utc>> {
+    meta.uploads
+        .iter()
+        .filter(|upload| upload.success)
+        .filter_map(|upload| parse_time(&upload.uploaded_at))
+        .max()
+        .or_else(|| {
+            meta.uploaded_url
+                .as_ref()
+                .and_then(|_| parse_time(&meta.created_at))
+        })
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+fn is_protected(meta: &ScreenshotMeta, policy: &RetentionPolicy, now: DateTime<Utc>) -> bool {
+    if meta.pinned {
+        return true;
+    }
+
+    match (policy.keep_uploaded_days, last_uploaded_at(meta)) {
+        (Some(days), Some(uploaded_at)) => now - uploaded_at < Duration::days(days as i64),
+        _ => false,
+    }
+}
+
+/// Decide what to evict: first unprotected entries past the maximum age,
+/// then trashed entries and after them the oldest unprotected entries until
+/// usage fits the budget. `entries` and `trashed` must be ordered oldest
+/// first; `blob_refs` are (entry id, blob hash, blob size) so shared images
+/// are only counted as freed when their last user goes.
+fn plan_eviction(
+    entries: &[ScreenshotMeta],
+    trashed: &[ScreenshotMeta],
+    blob_refs: &[(String, String, u64)],
+    used_bytes: u64,
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> EvictionPlan {
+    let mut ref_counts: HashMap<&str, usize> = HashMap::new();
+    let mut refs_by_entry: HashMap<&str, Vec<(&str, u64)>> = HashMap::new();
+    for (id, hash, size) in blob_refs {
+        *ref_counts.entry(hash).or_default() += 1;
+        refs_by_entry.entry(id).or_default().push((hash, *size));
+    }
+
+    let budget_bytes = policy.budget_bytes();
+    let max_age = policy.max_age_days.map(|days| Duration::days(days as i64));
+    let mut remaining = used_bytes;
+    let mut evicted = Vec::new();
+
+    // Expired entries go whatever the budget says, so they are freed first
+    // and trashed entries only make up what is still over
+    let expired = |meta: &ScreenshotMeta| match (max_age, parse_time(&meta.created_at)) {
+        (Some(max_age), Some(created_at)) => now - created_at > max_age,
+        _ => false,
+    };
+    let live: Vec<&ScreenshotMeta> = entries
+        .iter()
+        .filter(|meta| !is_protected(meta, policy, now))
+        .collect();
+    let candidates = live
+        .iter()
+        .filter(|meta| expired(meta))
+        .map(|meta| (*meta, true, false))
+        .chain(trashed.iter().map(|meta| (meta, false, true)))
+        .chain(
+            live.iter()
+                .filter(|meta| !expired(meta))
+                .map(|meta| (*meta, false, false)),
+        );
+
+    for (meta, expired, from_trash) in candidates {
+        let reason = if expired {
+            "expired"
+        } else if remaining > budget_bytes {
+            "over_budget"
+        } else {
+            continue;
+        };
+
+        let mut freed_bytes = meta.size_bytes;
+        for (hash, size) in refs_by_entry.get(meta.id.as_str()).into_iter().flatten() {
+            let count = ref_counts.entry(hash).or_default();
+            *count = count.saturating_sub(1);
+            if *count > 0 {
+                freed_bytes = freed_bytes.saturating_sub(*size);
+            }
+        }
+
+        remaining = remaining.saturating_sub(freed_bytes);
+        evicted.push(EvictionCandidate {
+            id: meta.id.clone(),
+            created_at: meta.created_at.clone(),
+            ticket_id: meta.ticket_id.clone(),
+            size_bytes: meta.size_bytes,
+            freed_bytes,
+            reason: reason.to_string(),
+            from_trash,
+        });
+    }
+
+    EvictionPlan {
+        freed_bytes: used_bytes - remaining,
+        evicted,
+        used_bytes,
+        budget_bytes,
+        applied: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan_eviction, validate_policy, RetentionPolicy};
+    use crate::history::{ScreenshotMeta, UploadRecord};
+    use chrono::{DateTime, Utc};
+
+    const MB: u64 = 1024 * 1024;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-10-01T00:00:00+00:00")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn evicted(entries: &[ScreenshotMeta], used_mb: u64, policy: &RetentionPolicy) -> Vec<String> {
+        plan_eviction(entries, &[], &[], used_mb * MB, policy, now())
+            .evicted
+            .into_iter()
+            .map(|c| format!("{}:{}", c.id, c.reason))
+            .collect()
+    }
+
+    #[test]
+    fn budget_evicts_oldest_unpinned_entries() {
+        let pinned = ScreenshotMeta {
+            created_at: "2026-09-01T00:00:00+00:00".to_string(),
+            size_bytes: 200 * MB,
+            pinned: true,
+            ..ScreenshotMeta::test_entry("a")
+        };
+        let entries = vec![
+            pinned,
+            ScreenshotMeta {
+                created_at: "2026-09-02T00:00:00+00:00".to_string(),
+                size_bytes: 200 * MB,
+                ..ScreenshotMeta::test_entry("b")
+            },
+            ScreenshotMeta {
+                created_at: "2026-09-03T00:00:00+00:00".to_string(),
+                size_bytes: 200 * MB,
+                ..ScreenshotMeta::test_entry("c")
+            },
+            ScreenshotMeta {
+                created_at: "2026-09-04T00:00:00+00:00".to_string(),
+                size_bytes: 200 * MB,
+                ..ScreenshotMeta::test_entry("d")
+            },
+        ];
+        let policy = RetentionPolicy {
+            budget_mb: 500,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            evicted(&entries, 800, &policy),
+            vec!["b:over_budget", "c:over_budget"]
+        );
+        assert!(evicted(&entries, 400, &policy).is_empty());
+    }
+
+    #[test]
+    fn max_age_expires_entries_unless_recently_uploaded() {
+        let mut uploaded = ScreenshotMeta {
+            created_at: "2026-06-01T00:00:00+00:00".to_string(),
+            size_bytes: MB,
+            ..ScreenshotMeta::test_entry("a")
+        };
+        uploaded.uploads.push(UploadRecord {
+            service: "jira".to_string(),
+            ticket_id: "PROJ-1".to_string(),
+            ticket_url: None,
+            attachment_url: None,
+            comment: None,
+            uploaded_at: "2026-09-25T00:00:00+00:00".to_string(),
+            success: true,
+            error: None,
+        });
+        let entries = vec![
+            uploaded,
+            ScreenshotMeta {
+                created_at: "2026-06-02T00:00:00+00:00".to_string(),
+                size_bytes: MB,
+                ..ScreenshotMeta::test_entry("b")
+            },
+            ScreenshotMeta {
+                created_at: "2026-09-30T00:00:00+00:00".to_string(),
+                size_bytes: MB,
+                ..ScreenshotMeta::test_entry("c")
+            },
+        ];
+
+        let policy = RetentionPolicy {
+            budget_mb: 500,
+            max_age_days: Some(30),
+            keep_uploaded_days: Some(14),
+            ..Default::default()
+        };
+        assert_eq!(evicted(&entries, 3, &policy), vec!["b:expired"]);
+
+        let policy = RetentionPolicy {
+            keep_uploaded_days: Some(3),
+            ..policy
+        };
+        assert_eq!(
+            evicted(&entries, 3, &policy),
+            vec!["a:expired", "b:expired"]
+        );
+    }
+
+    #[test]
+    fn trash_is_emptied_before_live_entries_are_evicted() {
+        let entries = vec![
+            ScreenshotMeta {
+                created_at: "2026-09-01T00:00:00+00:00".to_string(),
+                size_bytes: 100 * MB,
+                ..ScreenshotMeta::test_entry("a")
+            },
+            ScreenshotMeta {
+                created_at: "2026-09-02T00:00:00+00:00".to_string(),
+                size_bytes: 100 * MB,
+                ..ScreenshotMeta::test_entry("b")
+            },
+        ];
+        let trashed = vec![ScreenshotMeta {
+            created_at: "2026-09-20T00:00:00+00:00".to_string(),
+            size_bytes: 100 * MB,
+            ..ScreenshotMeta::test_entry("t")
+        }];
+        let policy = RetentionPolicy {
+            budget_mb: 150,
+            ..Default::default()
+        };
+
+        let plan = plan_eviction(&entries, &trashed, &[], 300 * MB, &policy, now());
+        let evicted: Vec<(&str, bool)> = plan
+            .evicted
+            .iter()
+            .map(|c| (c.id.as_str(), c.from_trash))
+            .collect();
+        assert_eq!(evicted, vec![("t", true), ("a", false)]);
+    }
+
+    #[test]
+    fn shared_images_only_count_as_freed_with_their_last_user() {
+        let entries = vec![
+            ScreenshotMeta {
+                created_at: "2026-09-01T00:00:00+00:00".to_string(),
+                size_bytes: 200 * MB,
+                ..ScreenshotMeta::test_entry("a")
+            },
+            ScreenshotMeta {
+                created_at: "2026-09-02T00:00:00+00:00".to_string(),
+                size_bytes: 200 * MB,
+                ..ScreenshotMeta::test_entry("b")
+            },
+            ScreenshotMeta {
+                created_at: "2026-09-03T00:00:00+00:00".to_string(),
+                size_bytes: 200 * MB,
+                ..ScreenshotMeta::test_entry("c")
+            },
+        ];
+        // a and b are the same 150 MB capture with different annotations
+        let shared = "f".repeat(64);
+        let blob_refs = vec![
+            ("a".to_string(), shared.clone(), 150 * MB),
+            ("b".to_string(), shared, 150 * MB),
+        ];
+        let policy = RetentionPolicy {
+            budget_mb: 300,
+            ..Default::default()
+        };
+
+        let plan = plan_eviction(&entries, &[], &blob_refs, 450 * MB, &policy, now());
+        let freed: Vec<u64> = plan.evicted.iter().map(|c| c.freed_bytes / MB).collect();
+        assert_eq!(freed, vec![50, 200]);
+        assert_eq!(plan.freed_bytes, 250 * MB);
+    }
+
+    #[test]
+    fn validate_policy_rejects_tiny_budgets() {
+        assert!(validate_policy(&RetentionPolicy::default()).is_ok());
+        assert!(validate_policy(&RetentionPolicy {
+            budget_mb: 1,
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
@@ -5,6 +5,10 @@ use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::time::Duration;
 
+use crate::history::{self, UploadRecord};
+use crate::history_crypto;
+use crate::history_fields;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UploadRequest {
     pub service: String, // "jira" | "zendesk"
@@ -14,12 +18,16 @@ pub struct UploadRequest {
     pub base_url: String, // Jira base URL or Zendesk subdomain
     pub email: String,    // For Jira
     pub api_token: String,
+    /// History entry the file belongs to; the attempt is recorded on it
+    pub history_id: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UploadResult {
     pub ticket_url: String,
     pub attachment_url: String,
+    /// Set when the upload succeeded but could not be recorded in history
+    pub warning: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -104,13 +112,46 @@ fn validate_zendesk_ticket_id(ticket_id: &str) -> Result<String, String> {
     Ok(trimmed.to_string())
 }
 
-/// Upload a screenshot to Jira or Zendesk
+/// Upload a saved screenshot to Jira or Zendesk and record the attempt on
+/// its history entry. The comment may use the entry's `{{variables}}`, such
+/// as `{{ticket_id}}`, `{{notes}}` or a custom field key.
 #[tauri::command]
-pub async fn upload_screenshot(request: UploadRequest) -> Result<UploadResult, String> {
-    match request.service.as_str() {
+pub async fn upload_screenshot(mut request: UploadRequest) -> Result<UploadResult, String> {
+    // Checked before uploading so that the attempt can always be recorded
+    let history_id = history::find_history_entry(&request.history_id)?.id;
+    request.comment = history_fields::render_entry_comment(&request.comment, &history_id)?;
+    let service = request.service.clone();
+    let ticket_id = request.ticket_id.trim().to_string();
+    let comment = Some(request.comment.trim().to_string()).filter(|c| !c.is_empty());
+
+    let result = match request.service.as_str() {
         "jira" => upload_to_jira(request).await,
         "zendesk" => upload_to_zendesk(request).await,
-        _ => Err(format!("Unknown service: {}", request.service)),
+        _ => return Err(format!("Unknown service: {}", request.service)),
+    };
+
+    let record = UploadRecord {
+        service,
+        ticket_id,
+        ticket_url: result.as_ref().ok().map(|r| r.ticket_url.clone()),
+        attachment_url: result.as_ref().ok().map(|r| r.attachment_url.clone()),
+        comment,
+        uploaded_at: chrono::Utc::now().to_rfc3339(),
+        success: result.is_ok(),
+        error: result.as_ref().err().cloned(),
+    };
+    // The upload outcome is what the caller needs; failing to record it
+    // in history must not turn a successful upload into an error
+    let recorded = history::record_upload(&history_id, record)
+        .map_err(|e| format!("The upload was not recorded in history: {}", e));
+
+    match (result, recorded) {
+        (Ok(upload), Err(warning)) => Ok(UploadResult {
+            warning: Some(warning),
+            ..upload
+        }),
+        (Err(error), Err(warning)) => Err(format!("{}; {}", error, warning)),
+        (result, Ok(_)) => result,
     }
 }
 
@@ -140,7 +181,7 @@ async fn upload_to_jira(request: UploadRequest) -> Result<UploadResult, String>
 
     // Read file bytes
     let file_bytes =
-        std::fs::read(&file_path).map_err(|e| format!("Failed to read file: {}", e))?;
+        history_crypto::read(file_path).map_err(|e| format!("Failed to read file: {}", e))?;
 
     // Create multipart form with file attachment
     let file_part = Part::bytes(file_bytes)
@@ -192,6 +233,20 @@ async fn upload_to_jira(request: UploadRequest) -> Result<UploadResult, String>
         return Err(format!("Upload failed: {} - {}", status, error_text));
     }
 
+    #[derive(Deserialize)]
+    struct JiraAttachment {
+        content: String,
+    }
+
+    // Jira answers with the created attachments; fall back to the ticket
+    // link if the body is not what we expect
+    let attachment_url = response
+        .json::<Vec<JiraAttachment>>()
+        .await
+        .ok()
+        .and_then(|attachments| attachments.into_iter().next())
+        .map(|attachment| attachment.content);
+
     // Add comment if provided
     if !request.comment.is_empty() {
         let comment_url = format!("{}/rest/api/3/issue/{}/comment", base_url, ticket_id);
@@ -243,8 +298,9 @@ async fn upload_to_jira(request: UploadRequest) -> Result<UploadResult, String>
     let ticket_url = format!("{}/browse/{}", base_url, ticket_id);
 
     Ok(UploadResult {
-        ticket_url: ticket_url.clone(),
-        attachment_url: ticket_url,
+        attachment_url: attachment_url.unwrap_or_else(|| ticket_url.clone()),
+        ticket_url,
+        warning: None,
     })
 }
 
@@ -264,7 +320,7 @@ async fn upload_to_zendesk(request: UploadRequest) -> Result<UploadResult, Strin
 
     // Read file bytes
     let file_bytes =
-        std::fs::read(&file_path).map_err(|e| format!("Failed to read file: {}", e))?;
+        history_crypto::read(file_path).map_err(|e| format!("Failed to read file: {}", e))?;
 
     // Create HTTP client
     let client = reqwest::Client::builder()
@@ -320,6 +376,12 @@ async fn upload_to_zendesk(request: UploadRequest) -> Result<UploadResult, Strin
     #[derive(Deserialize)]
     struct Upload {
         token: String,
+        attachment: Option<Attachment>,
+    }
+
+    #[derive(Deserialize)]
+    struct Attachment {
+        content_url: String,
     }
 
     let upload_data: UploadResponse = upload_response
@@ -327,6 +389,11 @@ async fn upload_to_zendesk(request: UploadRequest) -> Result<UploadResult, Strin
         .await
         .map_err(|e| format!("Failed to parse upload response: {}", e))?;
 
+    let attachment_url = upload_data
+        .upload
+        .attachment
+        .map(|attachment| attachment.content_url);
+
     // Step 2: Add comment with attachment to ticket
     let comment_url = format!(
         "https://{}.zendesk.com/api/v2/tickets/{}/comments",
@@ -378,8 +445,9 @@ async fn upload_to_zendesk(request: UploadRequest) -> Result<UploadResult, Strin
     );
 
     Ok(UploadResult {
-        ticket_url: ticket_url.clone(),
-        attachment_url: ticket_url,
+        attachment_url: attachment_url.unwrap_or_else(|| ticket_url.clone()),
+        ticket_url,
+        warning: None,
     })
 }
 
@@ -31,6 +31,7 @@ function App() {
   const [showUploadWizard, setShowUploadWizard] = useState(false);
   const [showSettings, setShowSettings] = useState(false);
   const [savedFilePath, setSavedFilePath] = useState<string | null>(null);
+  const [savedScreenshotId, setSavedScreenshotId] = useState<string | null>(null);
 
   const canvasRef = useRef<AnnotationCanvasRef>(null);
 
@@ -103,6 +104,7 @@ function App() {
 
       if (screenshotId) {
         setSavedFilePath(exportResult.annotatedPath);
+        setSavedScreenshotId(screenshotId);
         alert('Screenshot saved! Upload to a ticket?');
         setShowUploadWizard(true);
       } else {
@@ -343,11 +345,12 @@ function App() {
             <div className="saving-spinner">Saving...</div>
           </div>
         )}
-        {showUploadWizard && savedFilePath && (
+        {showUploadWizard && savedFilePath && savedScreenshotId && (
           <>
             <div className="upload-wizard-overlay" onClick={() => setShowUploadWizard(false)} />
             <UploadWizard
               filePath={savedFilePath}
+              historyId={savedScreenshotId}
               onClose={() => setShowUploadWizard(false)}
               onSuccess={handleUploadSuccess}
             />
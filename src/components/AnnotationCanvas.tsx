@@ -76,9 +76,10 @@ export const AnnotationCanvas = forwardRef<AnnotationCanvasRef, AnnotationCanvas
       const scaleX = imageWidth / rect.width;
       const scaleY = imageHeight / rect.height;
 
+      // Clamp to the image so drags past its edge stay valid annotations
       return {
-        x: (event.clientX - rect.left) * scaleX,
-        y: (event.clientY - rect.top) * scaleY,
+        x: Math.min(Math.max((event.clientX - rect.left) * scaleX, 0), imageWidth),
+        y: Math.min(Math.max((event.clientY - rect.top) * scaleY, 0), imageHeight),
       };
     }, [imageWidth, imageHeight]);
 
@@ -453,10 +454,12 @@ function AnnotationRenderer({
       const redact = annotation as RedactAnnotation;
       const normalized = normalizeRectBounds(redact.origin, redact.width, redact.height);
 
+      // Tagged so layered export can leave redactions out of the annotation layer
       switch (redact.style) {
         case 'blur':
           return (
             <rect
+              data-redaction
               x={normalized.origin.x}
               y={normalized.origin.y}
               width={normalized.width}
@@ -470,7 +473,7 @@ function AnnotationRenderer({
         case 'pixelate':
           // Pixelation effect using a small scaled-up pattern
           return (
-            <g opacity={opacity}>
+            <g data-redaction opacity={opacity}>
               <defs>
                 <pattern
                   id={`pixelate-${redact.id}`}
@@ -496,6 +499,7 @@ function AnnotationRenderer({
         case 'blackbox':
           return (
             <rect
+              data-redaction
               x={normalized.origin.x}
               y={normalized.origin.y}
               width={normalized.width}
@@ -1,5 +1,6 @@
 import { useState, useEffect } from 'react';
 import { convertFileSrc } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { useHistory } from '../hooks/useHistory';
 import type { ScreenshotMeta, StorageUsage } from '../types';
 
@@ -18,6 +19,18 @@ export function HistoryGallery({ onClose }: HistoryGalleryProps) {
     loadStorageUsage();
   }, []);
 
+  // Entries imported from watched folders appear without a manual refresh
+  useEffect(() => {
+    const unlisten = listen('history-imported', () => {
+      loadHistory(searchTerm || undefined);
+      loadStorageUsage();
+    });
+
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, [searchTerm]);
+
   const loadHistory = async (search?: string) => {
     const results = await getHistory(search, 20);
     setScreenshots(results);
@@ -38,7 +51,7 @@ export function HistoryGallery({ onClose }: HistoryGalleryProps) {
   };
 
   const handleDelete = async (id: string) => {
-    if (!confirm('Delete this screenshot? This cannot be undone.')) {
+    if (!confirm('Move this screenshot to the trash?')) {
       return;
     }
 
@@ -126,7 +139,7 @@ export function HistoryGallery({ onClose }: HistoryGalleryProps) {
           <div key={screenshot.id} className="history-card">
             <div className="history-thumbnail">
               <img
-                src={convertFileSrc(screenshot.thumbnailPath)}
+                src={convertFileSrc(screenshot.thumbnailPath, 'history')}
                 alt="Screenshot thumbnail"
               />
             </div>
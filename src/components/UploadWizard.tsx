@@ -4,6 +4,7 @@ import { writeText, readText } from '@tauri-apps/plugin-clipboard-manager';
 
 interface UploadWizardProps {
   filePath: string;
+  historyId: string;
   onClose: () => void;
   onSuccess: (ticketUrl: string) => void;
 }
@@ -16,20 +17,23 @@ interface UploadRequest {
   base_url: string;
   email: string;
   api_token: string;
+  history_id: string;
 }
 
 interface UploadResult {
   ticket_url: string;
   attachment_url: string;
+  warning: string | null;
 }
 
-export function UploadWizard({ filePath, onClose, onSuccess }: UploadWizardProps) {
+export function UploadWizard({ filePath, historyId, onClose, onSuccess }: UploadWizardProps) {
   const [service, setService] = useState<'jira' | 'zendesk'>('jira');
   const [ticketId, setTicketId] = useState('');
   const [comment, setComment] = useState('');
   const [isUploading, setIsUploading] = useState(false);
   const [error, setError] = useState<string | null>(null);
   const [successUrl, setSuccessUrl] = useState<string | null>(null);
+  const [warning, setWarning] = useState<string | null>(null);
 
   // Try to pre-fill ticket ID from clipboard
   useEffect(() => {
@@ -110,6 +114,7 @@ export function UploadWizard({ filePath, onClose, onSuccess }: UploadWizardProps
         base_url: baseUrl,
         email,
         api_token: apiToken,
+        history_id: historyId,
       };
 
       const result = (await invoke('upload_screenshot', {
@@ -117,6 +122,7 @@ export function UploadWizard({ filePath, onClose, onSuccess }: UploadWizardProps
       })) as UploadResult;
 
       setSuccessUrl(result.ticket_url);
+      setWarning(result.warning);
       onSuccess(result.ticket_url);
     } catch (err) {
       const errorMsg = String(err);
@@ -157,6 +163,7 @@ export function UploadWizard({ filePath, onClose, onSuccess }: UploadWizardProps
               {successUrl}
             </a>
           </div>
+          {warning && <p className="upload-warning">{warning}</p>}
           <div className="upload-actions">
             <button className="btn-primary" onClick={handleCopyUrl}>
               Copy URL
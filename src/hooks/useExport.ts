@@ -1,6 +1,6 @@
 import { invoke } from '@tauri-apps/api/core';
 import { toPng } from 'html-to-image';
-import type { ExportResult } from '../types';
+import type { Annotation, ExportResult, OpenRasterResult } from '../types';
 
 export function useExport() {
   const exportAnnotations = async (
@@ -27,5 +27,30 @@ export function useExport() {
     }
   };
 
-  return { exportAnnotations };
+  const exportLayered = async (
+    svgElement: SVGSVGElement,
+    originalPath: string,
+    annotations: Annotation[],
+  ): Promise<OpenRasterResult | null> => {
+    try {
+      // Redactions become their own layers in the backend, so leave them out
+      // here or hiding those layers would not reveal anything
+      const dataUrl = await toPng(svgElement as unknown as HTMLElement, {
+        backgroundColor: 'transparent',
+        cacheBust: true,
+        filter: (node) => !(node instanceof Element && node.hasAttribute('data-redaction')),
+      });
+
+      return await invoke<OpenRasterResult>('export_openraster', {
+        originalPath,
+        annotationPngBase64: dataUrl,
+        annotationsJson: JSON.stringify(annotations),
+      });
+    } catch (error) {
+      console.error('Layered export failed:', error);
+      return null;
+    }
+  };
+
+  return { exportAnnotations, exportLayered };
 }
@@ -69,6 +69,11 @@ export interface ExportResult {
   thumbnailPath: string;
 }
 
+export interface OpenRasterResult {
+  oraPath: string;
+  layerCount: number;
+}
+
 // === History Types ===
 
 export interface ScreenshotMeta {